@@ -0,0 +1,307 @@
+//! `with_permissions [..] { }` blocks: calling code with fewer permissions than the enclosing function holds
+//!
+//! Every call inside a block must be covered by the block's list, as well as by the function's own `#Permissions` (which `check_permissions` already checks), so a library function called inside `with_permissions [ReadFile] { }` can't write files even when its caller can. Blocks can be nested, and a call must fit every block around it. A function passed in (an argument like `f (str -> str :: P)`) can only be called or passed on inside a block listing its permission variable `P`.
+//!
+//! The generated C narrows the runtime permission mask for the duration of the block, see `runtime_permissions` and `codegen_c`. Permissions a function absorbs with `#Trusted` aren't needed by its callers, so they don't count against blocks either: the function widens the mask by them until it returns.
+
+use std::collections::BTreeSet;
+
+use crate::call_graph::{english_list, CallGraph, RequiredPermissions};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::Grammar;
+use crate::modules::Program;
+use crate::parse::{
+    enclosing_blocks, enclosing_function, function_calls, is_permission_variable, DataType, Node,
+};
+use crate::permissions::{PermissionHierarchy, Permissions, PERMISSION_LIST};
+
+/// What a block allows, for messages
+fn allows(permissions: &[Permissions]) -> String {
+    if permissions.is_empty() {
+        "allows no permissions".to_string()
+    } else {
+        format!(
+            "only allows {}",
+            english_list(permissions.iter().map(|p| format!("`{p}`")).collect())
+        )
+    }
+}
+
+/// Calls inside `with_permissions` blocks that need more than the block allows, paired with their module
+///
+/// Each call is reported once, against the innermost block it doesn't fit
+pub fn check_attenuation(
+    program: &Program,
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    for module in program.compiled_modules() {
+        let nodes = &module.nodes;
+        for (index, name, location) in function_calls(nodes) {
+            let blocks = enclosing_blocks(nodes, index);
+            let Some(caller) = enclosing_function(nodes, index) else {
+                continue;
+            };
+            if blocks.is_empty() {
+                continue;
+            }
+            // A function passed in needs its permission variable, whatever it turns out to be
+            let needed: Vec<(Permissions, String)> = match caller
+                .arguments
+                .iter()
+                .find(|arg| arg.name == name)
+                .map(|arg| &arg.data_type)
+            {
+                Some(DataType::Function(function)) => function
+                    .permissions
+                    .iter()
+                    .map(|variable| (Permissions::Custom(variable.clone()), String::new()))
+                    .collect(),
+                Some(_) => continue,
+                None => {
+                    let Some(call) = graph.calls.iter().find(|call| {
+                        call.caller.module == module.name
+                            && call.caller.name == caller.fn_name
+                            && (call.line, call.word) == (location.line, location.word)
+                    }) else {
+                        continue;
+                    };
+                    required
+                        .get(&call.callee)
+                        .into_iter()
+                        .flatten()
+                        .map(|(permission, introducer)| {
+                            let through = if *introducer == call.callee {
+                                String::new()
+                            } else if introducer.module == module.name {
+                                format!(" (through `{}`)", introducer.name)
+                            } else {
+                                format!(
+                                    " (through `{}` from `{}`)",
+                                    introducer.name, introducer.module
+                                )
+                            };
+                            (call.needs(permission), through)
+                        })
+                        .collect()
+                }
+            };
+            let mut reported: BTreeSet<usize> = BTreeSet::new();
+            for (permission, through) in needed {
+                let Some((block, grammar)) = blocks
+                    .iter()
+                    .find(|(_, g)| !graph.hierarchy.covers(&g.permissions, &permission))
+                else {
+                    continue;
+                };
+                // One problem per call is enough: the fix is the same for every permission
+                if !reported.insert(block.source_line) {
+                    continue;
+                }
+                problems.push((
+                    module.name.clone(),
+                    CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!(
+                            "`{name}` requires `{permission}`{through}, but it's called inside a block that {}",
+                            allows(&grammar.permissions)
+                        ),
+                        &format!(
+                            "call `{name}` outside the block, or add `{permission}` to the block's list"
+                        ),
+                        location.line,
+                        location.word,
+                    )
+                    .with_related("the block starts here", block.source_line, 0),
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// The built-in permissions a block's list allows at runtime, without scopes (paths and hosts are only checked at compile time)
+///
+/// A permission variable could stand for anything, so it leaves the mask alone: what the function passed in does was checked where it was passed
+fn runtime_permissions(
+    listed: &[Permissions],
+    hierarchy: &PermissionHierarchy,
+    variable: impl Fn(&Permissions) -> bool,
+) -> Vec<Permissions> {
+    let mut allowed: BTreeSet<Permissions> = BTreeSet::new();
+    for permission in listed {
+        if variable(permission) {
+            allowed.extend(PERMISSION_LIST.iter().map(|p| Permissions::from_str(p)));
+            continue;
+        }
+        allowed.extend(
+            hierarchy
+                .implied(permission)
+                .iter()
+                .map(|p| p.unscoped().clone())
+                .filter(|p| !matches!(p, Permissions::Custom(_))),
+        );
+    }
+    allowed.into_iter().collect()
+}
+
+/// Work out what each `with_permissions` block and `#Trusted` line allows at runtime, once every custom permission is declared
+pub fn expand_attenuation(nodes: &mut [Node], hierarchy: &PermissionHierarchy) {
+    for index in 0..nodes.len() {
+        if let Grammar::Trusted(g) = &mut nodes[index].grammar {
+            g.runtime = runtime_permissions(&g.permissions, hierarchy, |_| false);
+            continue;
+        }
+        let Grammar::Attenuation(g) = &nodes[index].grammar else {
+            continue;
+        };
+        let arguments = enclosing_function(nodes, index)
+            .map(|f| f.arguments.clone())
+            .unwrap_or_default();
+        let runtime = runtime_permissions(&g.permissions, hierarchy, |p| {
+            is_permission_variable(&arguments, p)
+        });
+        if let Grammar::Attenuation(g) = &mut nodes[index].grammar {
+            g.runtime = runtime;
+        }
+    }
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen_c::tests::compile_and_run;
+    use crate::codegen_c::CodegenOptions;
+    use crate::modules::tests::project;
+    use crate::sandbox::runtime_permissions;
+
+    #[test]
+    fn calls_inside_blocks_checked() {
+        let root = project(
+            "attenuation",
+            &[(
+                "main.iona",
+                "import read_file write_file from std.files
+permission Config :: ReadFile(\"/etc/app/*\")
+fn main :: void {
+    #Permissions :: ReadFile WriteFile
+    with_permissions [ReadFile] {
+        let text :: str = load \"a.txt\"
+        save text
+        with_permissions [] {
+            let again :: str = load \"b.txt\"
+        }
+    }
+    with_permissions [Config] {
+        let settings :: str = read_file \"/etc/app/settings\"
+        let other :: str = read_file \"/etc/passwd\"
+    }
+    save \"done\"
+}
+fn load :: path str -> str {
+    #Permissions :: ReadFile
+    return read_file path
+}
+fn save :: text str -> void {
+    #Permissions :: WriteFile
+    write_file text \"out.txt\"
+}
+fn apply :: f (str -> str :: P) -> x str -> str {
+    #Permissions :: P
+    with_permissions [ReadFile] {
+        let y :: str = f x
+    }
+    with_permissions [P] {
+        return f x
+    }
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems: Vec<&CompilerProblem> = program.modules["main"]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .collect();
+        let errors: Vec<(&str, usize)> = problems
+            .iter()
+            .map(|p| (p.message.as_str(), p.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("`save` requires `WriteFile`, but it's called inside a block that only allows `ReadFile`", 6),
+                ("`load` requires `ReadFile` (through `read_file` from `std.files`), but it's called inside a block that allows no permissions", 8),
+                ("`read_file` requires `ReadFile(\"/etc/passwd\")`, but it's called inside a block that only allows `Config`", 13),
+                ("`f` requires `P`, but it's called inside a block that only allows `ReadFile`", 28),
+            ]
+        );
+        assert_eq!(problems[1].related.as_ref().unwrap().line, 7);
+
+        // Blocks only narrow what's allowed at runtime to the built-in permissions they cover
+        let runtime: Vec<Vec<Permissions>> = program.modules["main"]
+            .nodes
+            .iter()
+            .filter_map(|node| match &node.grammar {
+                Grammar::Attenuation(g) => Some(g.runtime.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(runtime[0], vec![Permissions::ReadFile]);
+        assert!(runtime[1].is_empty());
+        assert_eq!(runtime[2], vec![Permissions::ReadFile]);
+        assert_eq!(runtime[4].len(), PERMISSION_LIST.len());
+    }
+
+    #[test]
+    fn trusted_calls_inside_blocks() {
+        let root = project(
+            "attenuation-trusted",
+            &[
+                (
+                    "main.iona",
+                    "import read_file from std.files
+fn main :: void {
+    #Permissions :: WriteConsole
+    with_permissions [WriteConsole] {
+        println load
+    }
+    with_permissions [] {
+        let again :: str = load
+    }
+    println \"done\"
+}
+fn load :: str {
+    #Trusted :: ReadFile \"only reads its own config file\"
+    return read_file \"config.txt\"
+}",
+                ),
+                ("config.txt", "configured\n"),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems = &program.modules["main"].problems;
+        assert!(
+            !problems.iter().any(|p| p.class == ProblemClass::Error),
+            "{:#?}",
+            problems
+        );
+        // The trusted function gets its permission back at runtime, whatever block it's called from
+        let options = CodegenOptions {
+            sandbox: runtime_permissions(&program),
+            ..Default::default()
+        };
+        let Some(output) = compile_and_run(&program, &root, &options) else {
+            eprintln!("skipping: no C compiler");
+            return;
+        };
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "configured\n\ndone\n"
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! `iona audit`: which functions need which permissions, and where each permission comes from
+//!
+//! This is for reviewing a project (and its dependencies) without reading its code. The report covers every function in the call graph, with a JSON form for tools and a summary grouped by permission for people.
+
+use std::collections::BTreeMap;
+
+use crate::call_graph::{CallGraph, FunctionId, RequiredPermissions};
+use crate::modules::Program;
+use crate::parse::FunctionData;
+use crate::permissions::{Permissions, Trusted};
+
+/// One function's entry in the audit
+#[derive(Debug)]
+pub struct FunctionAudit {
+    pub id: FunctionId,
+    pub data: FunctionData,
+    /// The declaration line, or `None` for the standard library
+    pub line: Option<usize>,
+    pub required: Vec<RequiredPermission>,
+}
+
+/// A permission a function requires, and how it comes to require it
+#[derive(Debug)]
+pub struct RequiredPermission {
+    pub permission: Permissions,
+    /// Whether the function's `#Permissions` cover it
+    pub declared: bool,
+    /// The function that introduces the permission
+    pub introduced_by: FunctionId,
+    /// Every function from this one down to the one introducing the permission
+    pub chain: Vec<FunctionId>,
+    /// The import bringing in the first function of the chain, if it's defined in another module
+    pub import: Option<ImportedThrough>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportedThrough {
+    pub module: String,
+    /// `None` for the prelude, which is imported implicitly
+    pub line: Option<usize>,
+}
+
+/// Audit every function in a program
+pub fn audit(program: &Program) -> Vec<FunctionAudit> {
+    let graph = CallGraph::build(program);
+    let required = graph.required_permissions();
+    graph
+        .functions
+        .iter()
+        .map(|(id, data)| FunctionAudit {
+            id: id.clone(),
+            data: data.clone(),
+            line: graph.lines.get(id).copied(),
+            required: required
+                .get(id)
+                .into_iter()
+                .flatten()
+                .map(|(permission, introducer)| {
+                    requirement(program, &graph, &required, id, permission, introducer)
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// How a function comes to require a permission introduced by another function
+fn requirement(
+    program: &Program,
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+    function: &FunctionId,
+    permission: &Permissions,
+    introducer: &FunctionId,
+) -> RequiredPermission {
+    let calls = graph
+        .chain(
+            function,
+            |f| f == introducer,
+            |call| call.needed(required).any(|p| p == *permission),
+        )
+        .unwrap_or_default();
+    let import = calls
+        .first()
+        .filter(|call| call.callee.module != function.module)
+        .map(|call| {
+            let imported = program
+                .modules
+                .get(&function.module)
+                .and_then(|module| program.import_of(module, &call.called_as));
+            ImportedThrough {
+                module: imported.map_or(call.callee.module.clone(), |i| i.module.clone()),
+                line: imported.map(|i| i.line),
+            }
+        });
+    RequiredPermission {
+        permission: permission.clone(),
+        declared: graph
+            .hierarchy
+            .covers(graph.declared_permissions(function), permission),
+        introduced_by: introducer.clone(),
+        chain: std::iter::once(function.clone())
+            .chain(calls.iter().map(|call| call.callee.clone()))
+            .collect(),
+        import,
+    }
+}
+
+/// Quote a string for JSON
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn json_list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<String>>().join(", "))
+}
+
+fn json_line(line: Option<usize>) -> String {
+    line.map_or("null".to_string(), |l| (l + 1).to_string())
+}
+
+/// Every `#Trusted` line in the project, with its function
+fn trusted(audits: &[FunctionAudit]) -> impl Iterator<Item = (&FunctionAudit, &Trusted)> {
+    audits
+        .iter()
+        .flat_map(|audit| audit.data.trusted.iter().map(move |t| (audit, t)))
+}
+
+/// The audit as JSON (line numbers start at 1, like in compiler messages)
+pub fn audit_json(entry: &str, audits: &[FunctionAudit]) -> String {
+    let functions = audits.iter().map(|audit| {
+        let required = audit.required.iter().map(|r| {
+            let import = match &r.import {
+                Some(import) => format!(
+                    "{{\"module\": {}, \"line\": {}}}",
+                    json_string(&import.module),
+                    json_line(import.line)
+                ),
+                None => "null".to_string(),
+            };
+            format!(
+                "{{\"permission\": {}, \"declared\": {}, \"introduced_by\": {}, \"chain\": {}, \"import\": {}}}",
+                json_string(&r.permission.to_string()),
+                r.declared,
+                json_string(&r.introduced_by.to_string()),
+                json_list(r.chain.iter().map(|f| json_string(&f.to_string()))),
+                import
+            )
+        });
+        format!(
+            "    {{\"module\": {}, \"name\": {}, \"line\": {}, \"builtin\": {}, \"properties\": {}, \"declared_permissions\": {}, \"required_permissions\": {}}}",
+            json_string(&audit.id.module),
+            json_string(&audit.id.name),
+            json_line(audit.line),
+            audit.line.is_none(),
+            json_list(audit.data.properties.iter().map(|p| json_string(&format!("{p:?}")))),
+            json_list(audit.data.permissions.iter().map(|p| json_string(&p.to_string()))),
+            json_list(required)
+        )
+    });
+    // Suppressed permissions come first, so reviewers can't miss them
+    let trusted =
+        trusted(audits).map(|(audit, trusted)| {
+            format!(
+            "    {{\"function\": {}, \"line\": {}, \"permissions\": {}, \"justification\": {}}}",
+            json_string(&audit.id.to_string()),
+            json_line(Some(trusted.line)),
+            json_list(trusted.permissions.iter().map(|p| json_string(&p.to_string()))),
+            json_string(&trusted.justification)
+        )
+        });
+    format!(
+        "{{\n  \"entry\": {},\n  \"trusted\": [\n{}\n  ],\n  \"functions\": [\n{}\n  ]\n}}",
+        json_string(entry),
+        trusted.collect::<Vec<String>>().join(",\n"),
+        functions.collect::<Vec<String>>().join(",\n")
+    )
+}
+
+/// The audit for people: every `#Trusted` suppression, then the functions in the project needing each permission, and why
+pub fn audit_summary(audits: &[FunctionAudit]) -> String {
+    let mut by_permission: BTreeMap<&Permissions, Vec<(&FunctionAudit, &RequiredPermission)>> =
+        BTreeMap::new();
+    let mut unprivileged: Vec<String> = Vec::new();
+    // Builtins are where permissions start, so they're only listed in the JSON
+    for audit in audits.iter().filter(|a| a.line.is_some()) {
+        if audit.required.is_empty() && audit.data.trusted.is_empty() {
+            unprivileged.push(audit.id.to_string());
+        }
+        for required in audit.required.iter() {
+            by_permission
+                .entry(&required.permission)
+                .or_default()
+                .push((audit, required));
+        }
+    }
+    let mut summary = String::new();
+    let trusted: Vec<String> = trusted(audits)
+        .map(|(audit, trusted)| {
+            let permissions: Vec<String> =
+                trusted.permissions.iter().map(|p| p.to_string()).collect();
+            format!(
+                "  {} (line {}): {}, \"{}\"\n",
+                audit.id,
+                trusted.line + 1,
+                permissions.join(" "),
+                trusted.justification
+            )
+        })
+        .collect();
+    if !trusted.is_empty() {
+        summary.push_str("TRUSTED (permissions hidden from callers)\n");
+        summary.push_str(&trusted.concat());
+    }
+    for (permission, functions) in by_permission {
+        summary.push_str(&format!("{permission}\n"));
+        for (audit, required) in functions {
+            let status = if required.declared {
+                "declared"
+            } else {
+                "MISSING"
+            };
+            let chain = required
+                .chain
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join(" -> ");
+            let import = match &required.import {
+                Some(ImportedThrough {
+                    module,
+                    line: Some(line),
+                }) => format!(" (import from {module} on line {})", line + 1),
+                Some(ImportedThrough { module, line: None }) => {
+                    format!(" (from the {module} prelude)")
+                }
+                None => String::new(),
+            };
+            summary.push_str(&format!("  {:<8} {}: {chain}{import}\n", status, audit.id));
+        }
+    }
+    if !unprivileged.is_empty() {
+        summary.push_str(&format!("No permissions\n  {}\n", unprivileged.join(", ")));
+    }
+    summary
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::tests::project;
+
+    #[test]
+    fn audit_project() {
+        let root = project(
+            "audit",
+            &[
+                (
+                    "main.iona",
+                    "import copy_to from files\nfn main :: void {\n    copy_to \"a\" \"b\"\n}\nfn quiet :: void {\n}",
+                ),
+                (
+                    "files.iona",
+                    "import read_file write_file from std.files
+fn copy_to :: old str -> new str -> void {
+    #Properties :: Export
+    #Permissions :: ReadFile WriteFile
+    let data :: str = read_file old
+    write_file data new
+}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let audits = audit(&program);
+        let main = audits
+            .iter()
+            .find(|a| a.id.to_string() == "main.main")
+            .unwrap();
+        assert_eq!(main.required.len(), 2);
+        let read = &main.required[0];
+        assert_eq!(read.permission, Permissions::ReadFile);
+        assert!(!read.declared);
+        assert_eq!(read.introduced_by.to_string(), "std.files.read_file");
+        let chain: Vec<String> = read.chain.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            chain,
+            vec!["main.main", "files.copy_to", "std.files.read_file"]
+        );
+        assert_eq!(
+            read.import,
+            Some(ImportedThrough {
+                module: "files".to_string(),
+                line: Some(0)
+            })
+        );
+
+        let json = audit_json("main", &audits);
+        println!("{json}");
+        assert!(json.contains("{\"module\": \"files\", \"name\": \"copy_to\", \"line\": 2, \"builtin\": false, \"properties\": [\"Export\"], \"declared_permissions\": [\"ReadFile\", \"WriteFile\"]"));
+        assert!(json.contains("{\"permission\": \"ReadFile\", \"declared\": true, \"introduced_by\": \"std.files.read_file\", \"chain\": [\"files.copy_to\", \"std.files.read_file\"], \"import\": {\"module\": \"std.files\", \"line\": 1}}"));
+        assert!(json.contains("\"name\": \"read_file\", \"line\": null, \"builtin\": true"));
+
+        let summary = audit_summary(&audits);
+        println!("{summary}");
+        assert_eq!(
+            summary.lines().take(3).collect::<Vec<&str>>(),
+            vec![
+                "ReadFile",
+                "  declared files.copy_to: files.copy_to -> std.files.read_file (import from std.files on line 1)",
+                "  MISSING  main.main: main.main -> files.copy_to -> std.files.read_file (import from files on line 1)",
+            ]
+        );
+        assert!(summary.ends_with("No permissions\n  main.quiet\n"));
+        assert_eq!(json_string("a \"b\"\n"), "\"a \\\"b\\\"\\n\"");
+    }
+
+    #[test]
+    fn audit_trusted() {
+        let root = project(
+            "audit_trusted",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn main :: void {
+    let config :: str = load_config
+}
+fn load_config :: str {
+    #Trusted :: ReadFile \"only reads the config file at startup\"
+    return read_file \"app.toml\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let audits = audit(&program);
+        let json = audit_json("main", &audits);
+        println!("{json}");
+        assert!(json.contains("  \"trusted\": [\n    {\"function\": \"main.load_config\", \"line\": 6, \"permissions\": [\"ReadFile\"], \"justification\": \"only reads the config file at startup\"}\n  ],"));
+        let summary = audit_summary(&audits);
+        println!("{summary}");
+        assert!(summary.starts_with("TRUSTED (permissions hidden from callers)\n  main.load_config (line 6): ReadFile, \"only reads the config file at startup\"\n"));
+        assert!(summary.ends_with("No permissions\n  main.main\n"));
+    }
+}
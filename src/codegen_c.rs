@@ -1,41 +1,1147 @@
 //! Handles code generation for the C language target
 
 use std::collections::BTreeMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{Error, Write};
 
-use crate::parse::FunctionData;
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::{AssignmentTypes, Grammar, GrammarVariableAssignments};
+use crate::parse::{
+    declared_type, enclosing_function, rhs_node, DataType, FunctionData, Node, NodeType,
+    PrimitiveDataType,
+};
+use crate::parse_expressions::{Expression, Literal, Location, Object, Operator};
+use crate::permissions::Permissions;
+use crate::properties::Properties;
+use crate::sandbox::C_SANDBOX;
+use crate::stdlib;
+use crate::type_check;
 
-/// Emits a compact, function-signature-only header file
-pub fn emit_c_header(function_table: &BTreeMap<String, FunctionData>) -> Result<(), Error> {
-    // Construct the header file string
-    let mut buffer_str: String = "#include <stdbool.h>\n\n".to_string();
-    for (name, data) in function_table {
-        let mut definition: String = "".to_string();
-        // Start with return type
-        definition += data.return_type.to_str();
-        // Add fn name
-        definition += &format!(" {name}(");
-        // Add arguments
-        if data.args.len() == 0 {
-            definition += ");";
-        }
-        for (index, arg) in data.args.iter().enumerate() {
-            definition += &format!("{} {}", arg.data_type.to_str(), arg.name);
-            // Comma separate all but the last argument
-            if index + 1 < data.args.len() {
-                definition += ", ";
-            } else {
-                definition += ");";
+/// Runtime support shared by every generated program: indexing into strings is bounds checked, and builtins check the permissions `with_permissions` blocks leave them
+pub(crate) const C_RUNTIME: &str = r#"#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+enum {
+    IONA_READ_FILE = 1, IONA_WRITE_FILE = 2, IONA_READ_NETWORK = 4, IONA_WRITE_NETWORK = 8,
+    IONA_READ_CONSOLE = 16, IONA_WRITE_CONSOLE = 32, IONA_READ_ENVIRONMENT = 64, IONA_SPAWN_PROCESS = 128,
+    IONA_READ_CLOCK = 256, IONA_READ_RANDOM = 512, IONA_CALL_FOREIGN = 1024
+};
+
+/* The permissions code may use right now, narrowed inside `with_permissions` blocks (shared by every module) */
+unsigned iona_permissions __attribute__((weak)) = ~0u;
+
+/* Stops the program after an error, keeping what it already printed */
+static inline __attribute__((noreturn)) void iona_abort(void) {
+    fflush(stdout);
+    abort();
+}
+
+static inline void iona_require(unsigned permission, const char* name) {
+    if ((iona_permissions & permission) != permission) {
+        fprintf(stderr, "iona: this with_permissions block doesn't allow %s\n", name);
+        iona_abort();
+    }
+}
+
+static inline void iona_out_of_bounds(long index, long length, int line) {
+    fprintf(stderr, "iona: index %ld is out of bounds for length %ld (line %d)\n", index, length, line);
+    iona_abort();
+}
+
+static inline char* iona_str_slice(const char* s, long start, long end, int line) {
+    long length = (long)strlen(s);
+    if (start < 0 || start > length) iona_out_of_bounds(start, length, line);
+    if (end < start || end > length) iona_out_of_bounds(end, length, line);
+    char* out = malloc((size_t)(end - start) + 1);
+    memcpy(out, s + start, (size_t)(end - start));
+    out[end - start] = '\0';
+    return out;
+}
+
+static inline char* iona_str_index(const char* s, long index, int line) {
+    long length = (long)strlen(s);
+    if (index < 0 || index >= length) iona_out_of_bounds(index, length, line);
+    return iona_str_slice(s, index, index + 1, line);
+}
+
+static inline void iona_str_set(char* s, long start, long end, const char* value, int line) {
+    long length = (long)strlen(s);
+    if (start < 0 || start >= length) iona_out_of_bounds(start, length, line);
+    if (end < start || end > length) iona_out_of_bounds(end, length, line);
+    if ((long)strlen(value) != end - start) {
+        fprintf(stderr, "iona: cannot replace %ld characters with %ld characters (line %d)\n", end - start, (long)strlen(value), line);
+        iona_abort();
+    }
+    memcpy(s + start, value, (size_t)(end - start));
+}
+"#;
+
+/// Runtime support for `--trace-permissions` builds: every call of a builtin needing a permission logs a line through `iona_trace`
+const C_TRACE: &str = r#"/* Where traced permission uses go: the file named by IONA_TRACE_FILE, or stderr (shared by every module) */
+FILE* iona_trace_output __attribute__((weak)) = NULL;
+
+static inline void iona_trace_open(void) {
+    if (iona_trace_output != NULL) return;
+    const char* path = getenv("IONA_TRACE_FILE");
+    if (path != NULL && path[0] != '\0') {
+        iona_trace_output = fopen(path, "a");
+        if (iona_trace_output == NULL) fprintf(stderr, "iona: unable to open trace file %s, tracing to stderr\n", path);
+    }
+    if (iona_trace_output == NULL) iona_trace_output = stderr;
+}
+
+/* One tab separated line per use: the permission, the calling Iona function, its source line, and the path or URL */
+static inline void iona_trace(const char* permission, const char* function, int line, const char* argument) {
+    iona_trace_open();
+    fprintf(iona_trace_output, "iona-trace\t%s\t%s\t%d\t%s\n", permission, function, line, argument);
+    fflush(iona_trace_output);
+}
+"#;
+
+/// Spells a data type the way generated C code refers to it
+///
+/// Tuples are lowered to structs, see `emit_tuple_struct`, and functions to function pointers
+fn c_type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Primitive(p) => p.to_str().to_string(),
+        DataType::Tuple(_) => format!("iona_{}", data_type.mangle()),
+        DataType::Function(_) => c_declaration(data_type, ""),
+    }
+}
+
+/// The runtime flag of a built-in permission, see `iona_require`
+fn c_permission_flag(permission: &Permissions) -> Option<&'static str> {
+    match permission {
+        Permissions::ReadFile => Some("IONA_READ_FILE"),
+        Permissions::WriteFile => Some("IONA_WRITE_FILE"),
+        Permissions::ReadNetwork => Some("IONA_READ_NETWORK"),
+        Permissions::WriteNetwork => Some("IONA_WRITE_NETWORK"),
+        Permissions::ReadConsole => Some("IONA_READ_CONSOLE"),
+        Permissions::WriteConsole => Some("IONA_WRITE_CONSOLE"),
+        Permissions::ReadEnvironment => Some("IONA_READ_ENVIRONMENT"),
+        Permissions::SpawnProcess => Some("IONA_SPAWN_PROCESS"),
+        Permissions::ReadClock => Some("IONA_READ_CLOCK"),
+        Permissions::ReadRandom => Some("IONA_READ_RANDOM"),
+        Permissions::CallForeign => Some("IONA_CALL_FOREIGN"),
+        Permissions::Custom(_) | Permissions::Scoped(..) => None,
+    }
+}
+
+/// The runtime mask allowing a list of built-in permissions, like `IONA_READ_FILE | IONA_WRITE_FILE`
+fn c_permission_mask(permissions: &[Permissions]) -> String {
+    let mask: Vec<&str> = permissions.iter().filter_map(c_permission_flag).collect();
+    if mask.is_empty() {
+        "0u".to_string()
+    } else {
+        mask.join(" | ")
+    }
+}
+
+/// Declares a name with a data type, like `int x`, or `int (*f)(int)` for a function pointer
+fn c_declaration(data_type: &DataType, name: &str) -> String {
+    match data_type {
+        DataType::Function(function) => {
+            let args: Vec<String> = function.args.iter().map(c_type_name).collect();
+            format!(
+                "{} (*{name})({})",
+                c_type_name(&function.return_type),
+                args.join(", ")
+            )
+        }
+        _ => format!("{} {name}", c_type_name(data_type)),
+    }
+}
+
+/// Collects every tuple type used by a data type, inner tuples before the tuples that contain them
+fn collect_tuple_types(data_type: &DataType, found: &mut Vec<DataType>) {
+    match data_type {
+        DataType::Tuple(elements) => {
+            for element in elements {
+                collect_tuple_types(element, found);
+            }
+            if !found.contains(data_type) {
+                found.push(data_type.clone());
+            }
+        }
+        DataType::Function(function) => {
+            for arg in function.args.iter() {
+                collect_tuple_types(arg, found);
             }
+            collect_tuple_types(&function.return_type, found);
+        }
+        DataType::Primitive(_) => {}
+    }
+}
+
+/// Tuples become structs with positional fields, so `(int, bool)` is `struct { int _0; bool _1; }`
+///
+/// Several modules' headers can define the same tuple, so each definition is guarded
+fn emit_tuple_struct(tuple: &DataType) -> String {
+    let guard = c_type_name(tuple).to_uppercase();
+    let mut definition: String = format!("#ifndef {guard}\n#define {guard}\ntypedef struct {{\n");
+    if let DataType::Tuple(elements) = tuple {
+        for (index, element) in elements.iter().enumerate() {
+            definition += &format!("    {} _{index};\n", c_type_name(element));
+        }
+    }
+    definition += &format!("}} {};\n#endif", c_type_name(tuple));
+    definition
+}
+
+/// The tuple types used by function signatures (these structs live in the header)
+fn signature_tuple_types(function_table: &BTreeMap<String, FunctionData>) -> Vec<DataType> {
+    let mut tuple_types: Vec<DataType> = Vec::new();
+    for data in function_table.values() {
+        collect_tuple_types(&data.return_type, &mut tuple_types);
+        for arg in data.args.iter() {
+            collect_tuple_types(&arg.data_type, &mut tuple_types);
+        }
+    }
+    tuple_types
+}
+
+/// Whether this is a `main` without arguments returning `void` or `int`, which C needs declared as `int main(void)` (a `void` one returns 0)
+fn is_entry_point(name: &str, data: &FunctionData) -> bool {
+    name == "main"
+        && data.args.is_empty()
+        && (data.return_type == PrimitiveDataType::Void
+            || data.return_type == PrimitiveDataType::Int)
+}
+
+/// A function's C signature, without the trailing `;` or the body
+fn c_signature(name: &str, data: &FunctionData) -> String {
+    if is_entry_point(name, data) {
+        return "int main(void)".to_string();
+    }
+    let mut definition: String = "".to_string();
+    // Start with return type
+    definition += &c_type_name(&data.return_type);
+    // Add fn name
+    definition += &format!(" {name}(");
+    // Add arguments
+    if data.args.is_empty() {
+        definition += ")";
+    }
+    for (index, arg) in data.args.iter().enumerate() {
+        definition += &c_declaration(&arg.data_type, &arg.name);
+        // Comma separate all but the last argument
+        if index + 1 < data.args.len() {
+            definition += ", ";
+        } else {
+            definition += ")";
         }
+    }
+    definition
+}
+
+/// Only `Export` functions can be called from other modules, so everything else is `static` in C
+///
+/// `main` is the exception, C needs to find it
+fn visible_to_linker(name: &str, data: &FunctionData) -> bool {
+    data.properties.contains(&Properties::Export) || name == "main"
+}
+
+/// The functions a module's header declares: the ones other modules can import
+fn header_functions(
+    function_table: &BTreeMap<String, FunctionData>,
+) -> BTreeMap<String, FunctionData> {
+    function_table
+        .iter()
+        .filter(|(name, data)| data.origin.is_none() && visible_to_linker(name, data))
+        .map(|(name, data)| (name.clone(), data.clone()))
+        .collect()
+}
+
+/// The file name (without extension) of a module's generated C, `a.b` becomes `a_b`
+pub fn c_file_stem(module: &str) -> String {
+    module.replace('.', "_")
+}
+
+/// Builds a compact, function-signature-only header (plus the struct definitions those signatures need)
+///
+/// Only exported functions defined in the module are declared, imported functions are declared by their own module's header
+pub fn build_c_header(module: &str, function_table: &BTreeMap<String, FunctionData>) -> String {
+    let local = header_functions(function_table);
+    let guard = format!("IONA_{}_H", c_file_stem(module).to_uppercase());
+    let mut buffer_str: String =
+        format!("#ifndef {guard}\n#define {guard}\n\n#include <stdbool.h>\n\n");
+    // Tuple structs must be defined before any function uses them
+    for tuple in signature_tuple_types(&local).iter() {
+        buffer_str += &emit_tuple_struct(tuple);
+        buffer_str += "\n\n";
+    }
+    for (name, data) in local.iter() {
         // Push this fn to the buffer
-        buffer_str += &definition;
+        buffer_str += &c_signature(name, data);
+        buffer_str += ";\n\n";
+    }
+    buffer_str += "#endif\n";
+    buffer_str
+}
+
+/// Lowers function bodies to C, one node (line of Iona) at a time
+struct SourceWriter<'a> {
+    nodes: &'a [Node],
+    function_table: &'a BTreeMap<String, FunctionData>,
+    /// Every tuple type the bodies need a struct for
+    tuple_types: Vec<DataType>,
+    /// Counter for naming compiler-generated temporary variables
+    temporaries: usize,
+    /// The variables saving the permission mask of each `with_permissions` block we're inside, outermost first
+    blocks: Vec<String>,
+    /// The module being lowered, which traced calls are logged under
+    module: &'a str,
+    options: &'a CodegenOptions,
+}
+
+impl<'a> SourceWriter<'a> {
+    fn problem(message: &str, hint: &str, location: Location) -> CompilerProblem {
+        CompilerProblem::new(
+            ProblemClass::Error,
+            message,
+            hint,
+            location.line,
+            location.word,
+        )
+    }
+
+    /// Aliased and qualified names refer to the function by its original name
+    fn c_function_name<'b>(&'b self, name: &'b str) -> &'b str {
+        self.function_table
+            .get(name)
+            .and_then(|f| f.origin.as_ref())
+            .map_or(name, |o| &o.name)
+    }
+
+    /// The type of an object (type errors were reported before code generation)
+    fn object_type(&self, index: usize, object: &Object) -> Option<DataType> {
+        type_check::type_of(self.nodes, self.function_table, index, object)
+    }
+
+    /// Lower an expression, `expected` is the type the surrounding code needs (if known)
+    fn lower_expression(
+        &mut self,
+        index: usize,
+        object: &Object,
+        expected: Option<&DataType>,
+    ) -> Result<String, CompilerProblem> {
+        match object {
+            Object::Value(literal, _) => Ok(match literal {
+                Literal::Int(n) => n.to_string(),
+                Literal::Float(f) => format!("{f:?}"),
+                Literal::Bool(b) => b.to_string(),
+                Literal::Str(text) => text.clone(),
+                Literal::Symbol(name) => name.clone(),
+                Literal::Function(name) => self.c_function_name(name).to_string(),
+            }),
+            Object::Tuple(elements, location) => {
+                let tuple_type = match expected {
+                    Some(t @ DataType::Tuple(types)) if types.len() == elements.len() => t.clone(),
+                    _ => self.object_type(index, object).ok_or_else(|| {
+                        Self::problem(
+                            "unable to work out the type of this tuple",
+                            "declare the type of the variable it is assigned to",
+                            *location,
+                        )
+                    })?,
+                };
+                collect_tuple_types(&tuple_type, &mut self.tuple_types);
+                let element_types = match &tuple_type {
+                    DataType::Tuple(types) => types.clone(),
+                    _ => Vec::new(),
+                };
+                let mut lowered: Vec<String> = Vec::new();
+                for (element, element_type) in elements.iter().zip(element_types.iter()) {
+                    lowered.push(self.lower_expression(index, element, Some(element_type))?);
+                }
+                Ok(format!(
+                    "(({}){{{}}})",
+                    c_type_name(&tuple_type),
+                    lowered.join(", ")
+                ))
+            }
+            Object::Range { location, .. } => Err(Self::problem(
+                "ranges can only be used to index into a collection",
+                "try something like `@ data 0..2`",
+                *location,
+            )),
+            Object::Operation(Expression::Prefix { op, args, location }) => {
+                let symbol = match op {
+                    Operator::Add => "+",
+                    Operator::Subtract => "-",
+                    Operator::Multiply => "*",
+                    Operator::Divide => "/",
+                    Operator::Index => {
+                        return self.lower_index(index, &args[0], &args[1], *location)
+                    }
+                    Operator::Function { name } => {
+                        // Functions passed as arguments are called like any other function
+                        let arg_types: Vec<DataType> = match self.function_table.get(name) {
+                            Some(f) => f.args.iter().map(|a| a.data_type.clone()).collect(),
+                            None => match declared_type(self.nodes, index, name) {
+                                Some(DataType::Function(f)) => f.args,
+                                _ => Vec::new(),
+                            },
+                        };
+                        let mut lowered: Vec<String> = Vec::new();
+                        for (position, arg) in args.iter().enumerate() {
+                            lowered.push(self.lower_expression(
+                                index,
+                                arg,
+                                arg_types.get(position),
+                            )?);
+                        }
+                        // Traced builtins are called through a wrapper logging where they're called from
+                        let traced = self
+                            .function_table
+                            .get(name)
+                            .and_then(|f| f.origin.as_ref())
+                            .and_then(|o| stdlib::builtin(&o.module, &o.name))
+                            .filter(|b| {
+                                self.options.trace_permissions && !b.permissions.is_empty()
+                            });
+                        if let Some(builtin) = traced {
+                            let caller = enclosing_function(self.nodes, index)
+                                .map_or("", |f| f.fn_name.as_str());
+                            lowered.insert(0, (location.line + 1).to_string());
+                            lowered.insert(0, format!("\"{}.{caller}\"", self.module));
+                            return Ok(format!(
+                                "iona_traced_{}({})",
+                                builtin.name,
+                                lowered.join(", ")
+                            ));
+                        }
+                        return Ok(format!(
+                            "{}({})",
+                            self.c_function_name(name),
+                            lowered.join(", ")
+                        ));
+                    }
+                    Operator::Negate | Operator::Inverse => {
+                        return Err(Self::problem(
+                            "this operation is not supported by the C backend yet",
+                            "please wait for compiler update",
+                            *location,
+                        ));
+                    }
+                };
+                let left = self.lower_expression(index, &args[0], expected)?;
+                let right = self.lower_expression(index, &args[1], expected)?;
+                Ok(format!("({left} {symbol} {right})"))
+            }
+        }
+    }
+
+    /// The C for the bounds of a range, with the end made exclusive
+    fn lower_range_bounds(
+        &mut self,
+        index: usize,
+        range: &Object,
+    ) -> Result<Option<(String, String)>, CompilerProblem> {
+        match range {
+            Object::Range {
+                start,
+                end,
+                inclusive,
+                ..
+            } => {
+                let start = self.lower_expression(index, start, None)?;
+                let mut end = self.lower_expression(index, end, None)?;
+                if *inclusive {
+                    end = format!("({end} + 1)");
+                }
+                Ok(Some((start, end)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reading from a string is bounds checked at runtime, reading from a tuple was bounds checked at compile time
+    fn lower_index(
+        &mut self,
+        index: usize,
+        target: &Object,
+        position: &Object,
+        location: Location,
+    ) -> Result<String, CompilerProblem> {
+        let target_c = self.lower_expression(index, target, None)?;
+        let line = location.line + 1;
+        match self.object_type(index, target) {
+            Some(DataType::Primitive(PrimitiveDataType::Str)) => {
+                match self.lower_range_bounds(index, position)? {
+                    Some((start, end)) => {
+                        Ok(format!("iona_str_slice({target_c}, {start}, {end}, {line})"))
+                    }
+                    None => {
+                        let i = self.lower_expression(index, position, None)?;
+                        Ok(format!("iona_str_index({target_c}, {i}, {line})"))
+                    }
+                }
+            }
+            Some(DataType::Tuple(_)) => match position {
+                Object::Value(Literal::Int(n), _) => Ok(format!("({target_c})._{n}")),
+                _ => Err(Self::problem(
+                    "tuples can only be indexed by a literal int",
+                    "tuple elements can have different types, so the index must be known at compile time",
+                    location,
+                )),
+            },
+            _ => Err(Self::problem(
+                "unable to work out the type of the value being indexed",
+                "declare the type of the variable being indexed",
+                location,
+            )),
+        }
+    }
+
+    /// Lower a `let` or `set` line (including its right hand side)
+    fn lower_assignment(
+        &mut self,
+        index: usize,
+        g: &GrammarVariableAssignments,
+        value: &Object,
+    ) -> Result<String, CompilerProblem> {
+        let location = Location {
+            line: self.nodes[index].source_line,
+            word: 0,
+        };
+        if g.assignment_type == AssignmentTypes::Mutate {
+            let target_type = declared_type(self.nodes, index, &g.name);
+            let rhs = self.lower_expression(index, value, target_type.as_ref())?;
+            let line = location.line + 1;
+            return match (&g.index, target_type) {
+                (None, _) => Ok(format!("{} = {rhs};", g.name)),
+                (Some(position), Some(DataType::Primitive(PrimitiveDataType::Str))) => {
+                    let (start, end) = match self.lower_range_bounds(index, position)? {
+                        Some(bounds) => bounds,
+                        None => {
+                            let i = self.lower_expression(index, position, None)?;
+                            (i.clone(), format!("({i} + 1)"))
+                        }
+                    };
+                    Ok(format!(
+                        "iona_str_set({}, {start}, {end}, {rhs}, {line});",
+                        g.name
+                    ))
+                }
+                (Some(Object::Value(Literal::Int(n), _)), Some(DataType::Tuple(_))) => {
+                    Ok(format!("{}._{n} = {rhs};", g.name))
+                }
+                (Some(_), _) => Err(Self::problem(
+                    &format!("unable to work out how to index into `{}`", g.name),
+                    "declare the type of the variable being indexed",
+                    location,
+                )),
+            };
+        }
+        let declared = if g.type_provided || g.inferred {
+            Some(g.data_type.clone())
+        } else {
+            self.object_type(index, value)
+        };
+        let mut rhs = self.lower_expression(index, value, declared.as_ref())?;
+        // String literals are read-only in C, so mutable strings get their own copy
+        if g.mutable && matches!(value, Object::Value(Literal::Str(_), _)) {
+            rhs = format!("strdup({rhs})");
+        }
+        let c_type = match &declared {
+            Some(t) => {
+                collect_tuple_types(t, &mut self.tuple_types);
+                c_type_name(t)
+            }
+            None => "__auto_type".to_string(),
+        };
+        match &g.destructure {
+            None => Ok(format!("{c_type} {} = {rhs};", g.name)),
+            Some(names) => {
+                self.temporaries += 1;
+                let temporary = format!("iona_tmp_{}", self.temporaries);
+                let mut lowered = format!("{c_type} {temporary} = {rhs};");
+                for (position, name) in names.iter().enumerate() {
+                    let element_type = match &declared {
+                        Some(DataType::Tuple(types)) => types.get(position).map(c_type_name),
+                        _ => None,
+                    }
+                    .unwrap_or_else(|| "__auto_type".to_string());
+                    lowered += &format!("\n    {element_type} {name} = {temporary}._{position};");
+                }
+                Ok(lowered)
+            }
+        }
+    }
+
+    /// Lower every function in the program
+    fn lower_functions(&mut self) -> Result<String, Vec<CompilerProblem>> {
+        let mut buffer_str = String::new();
+        let mut errors: Vec<CompilerProblem> = Vec::new();
+        let mut return_type: Option<DataType> = None;
+        // The entry point returns 0 to C, even where the Iona code returns nothing
+        let mut entry_point = false;
+        let mut index = 0;
+        while index < self.nodes.len() {
+            let node = &self.nodes[index];
+            // Lines like `let` and `return` consume the expression node that follows them
+            let rhs = rhs_node(self.nodes, index).and_then(|n| n.expression.as_ref());
+            let mut consumed_rhs = false;
+            let bare_return = if entry_point { "return 0;" } else { "return;" };
+            let lowered: Result<Option<String>, CompilerProblem> = match (
+                &node.node_type,
+                &node.grammar,
+            ) {
+                (NodeType::FunctionDeclaration, Grammar::Function(g)) => {
+                    match self.function_table.get(&g.fn_name) {
+                        Some(data) => {
+                            return_type = Some(data.return_type.clone());
+                            entry_point = is_entry_point(&g.fn_name, data)
+                                && data.return_type == PrimitiveDataType::Void;
+                            if !visible_to_linker(&g.fn_name, data) {
+                                buffer_str += "static ";
+                            }
+                            buffer_str += &c_signature(&g.fn_name, data);
+                            buffer_str += " {\n";
+                            // The trace file has to be open before the sandbox could stop it opening
+                            if g.fn_name == "main" && self.options.trace_permissions {
+                                buffer_str += "    iona_trace_open();\n";
+                            }
+                            if let (Some(allowed), "main") =
+                                (&self.options.sandbox, g.fn_name.as_str())
+                            {
+                                buffer_str += &format!(
+                                    "    iona_install_sandbox({});\n",
+                                    c_permission_mask(allowed)
+                                );
+                            }
+                        }
+                        None => {
+                            return_type = None;
+                            entry_point = false;
+                        }
+                    }
+                    Ok(None)
+                }
+                (NodeType::CloseScope, _) => {
+                    // Only a `#Trusted` line can still be open here
+                    if let Some(saved) = self.blocks.first() {
+                        buffer_str += &format!("    iona_permissions = {saved};\n");
+                    }
+                    self.blocks.clear();
+                    if entry_point && !buffer_str.ends_with("    return 0;\n") {
+                        buffer_str += "    return 0;\n";
+                    }
+                    buffer_str += "}\n\n";
+                    Ok(None)
+                }
+                // Blocks narrow the permission mask until they close (they don't need a C scope)
+                (NodeType::Attenuation, Grammar::Attenuation(g)) => {
+                    self.temporaries += 1;
+                    let saved = format!("iona_saved_permissions_{}", self.temporaries);
+                    let mask = c_permission_mask(&g.runtime);
+                    let lowered = format!(
+                        "unsigned {saved} = iona_permissions;\n    iona_permissions &= {mask};"
+                    );
+                    self.blocks.push(saved);
+                    Ok(Some(lowered))
+                }
+                // Trusted permissions widen the mask until the function returns, whatever block it was called from
+                (NodeType::TrustedDeclaration, Grammar::Trusted(g)) if !g.runtime.is_empty() => {
+                    self.temporaries += 1;
+                    let saved = format!("iona_saved_permissions_{}", self.temporaries);
+                    let mask = c_permission_mask(&g.runtime);
+                    let lowered = format!(
+                        "unsigned {saved} = iona_permissions;\n    iona_permissions |= {mask};"
+                    );
+                    self.blocks.push(saved);
+                    Ok(Some(lowered))
+                }
+                (NodeType::CloseBlock, _) => Ok(self
+                    .blocks
+                    .pop()
+                    .map(|saved| format!("iona_permissions = {saved};"))),
+                (NodeType::VariableAssignment, Grammar::VariableAssignment(g)) => match rhs {
+                    Some(value) => {
+                        consumed_rhs = true;
+                        self.lower_assignment(index, g, value).map(Some)
+                    }
+                    None => Ok(None),
+                },
+                // Returning from inside blocks restores the mask the function started with (after computing the result)
+                (NodeType::ReturnStatement, _) => match (rhs, self.blocks.first().cloned()) {
+                    (Some(value), None) => {
+                        consumed_rhs = true;
+                        let expected = return_type.clone();
+                        self.lower_expression(index, value, expected.as_ref())
+                            .map(|e| Some(format!("return {e};")))
+                    }
+                    (Some(value), Some(saved)) => {
+                        consumed_rhs = true;
+                        let expected = return_type.clone();
+                        self.temporaries += 1;
+                        let result = format!("iona_tmp_{}", self.temporaries);
+                        let declaration = match &expected {
+                            Some(t) => c_declaration(t, &result),
+                            None => format!("__auto_type {result}"),
+                        };
+                        self.lower_expression(index, value, expected.as_ref())
+                                .map(|e| {
+                                    Some(format!(
+                                        "{declaration} = {e};\n    iona_permissions = {saved};\n    return {result};"
+                                    ))
+                                })
+                    }
+                    (None, None) => Ok(Some(bare_return.to_string())),
+                    (None, Some(saved)) => Ok(Some(format!(
+                        "iona_permissions = {saved};\n    {bare_return}"
+                    ))),
+                },
+                (NodeType::Expression, _) => match &node.expression {
+                    Some(value) => self
+                        .lower_expression(index, value, None)
+                        .map(|e| Some(format!("{e};"))),
+                    None => Ok(None),
+                },
+                _ => Ok(None),
+            };
+            match lowered {
+                Ok(Some(statement)) => {
+                    buffer_str += &format!("    {statement}\n");
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+            index += if consumed_rhs { 2 } else { 1 };
+        }
+        if errors.is_empty() {
+            Ok(buffer_str)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The modules (outside the standard library) whose headers declare the functions a module imports
+fn imported_headers(function_table: &BTreeMap<String, FunctionData>) -> Vec<String> {
+    let mut headers: Vec<String> = Vec::new();
+    for origin in function_table
+        .values()
+        .filter_map(|data| data.origin.as_ref())
+    {
+        if !stdlib::is_stdlib_module(&origin.module) && !headers.contains(&origin.module) {
+            headers.push(origin.module.clone());
+        }
+    }
+    headers
+}
+
+/// What to generate besides the function bodies
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// For the entry module of an executable: the built-in permissions reachable from `main`, which it starts by restricting the process to (see `sandbox`)
+    pub sandbox: Option<Vec<Permissions>>,
+    /// Log every call of a builtin needing a permission, with the Iona function and line it was called from
+    pub trace_permissions: bool,
+}
+
+/// Builds the C source for every function body in a module
+pub fn build_c_source(
+    module: &str,
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+    options: &CodegenOptions,
+) -> Result<String, Vec<CompilerProblem>> {
+    let mut writer = SourceWriter {
+        nodes,
+        function_table,
+        tuple_types: Vec::new(),
+        temporaries: 0,
+        blocks: Vec::new(),
+        module,
+        options,
+    };
+    let functions = writer.lower_functions()?;
+    let mut buffer_str: String = String::new();
+    let mut headers = imported_headers(function_table);
+    headers.push(module.to_string());
+    for header in headers.iter() {
+        buffer_str += &format!("#include \"{}.h\"\n", c_file_stem(header));
+    }
+    buffer_str += C_RUNTIME;
+    if options.sandbox.is_some() {
+        buffer_str += "\n";
+        buffer_str += C_SANDBOX;
+    }
+    if options.trace_permissions {
+        buffer_str += "\n";
+        buffer_str += C_TRACE;
+    }
+    buffer_str += "\n";
+    buffer_str += &stdlib::c_definitions(function_table, options.trace_permissions);
+    // Tuple types only used inside function bodies (or by static functions) aren't defined by the included headers
+    let mut in_headers: BTreeMap<String, FunctionData> = header_functions(function_table);
+    in_headers.extend(
+        function_table
+            .iter()
+            .filter(|(_, data)| data.origin.is_some())
+            .map(|(name, data)| (name.clone(), data.clone())),
+    );
+    let in_headers = signature_tuple_types(&in_headers);
+    let mut tuple_types: Vec<DataType> = Vec::new();
+    for data in function_table.values().filter(|data| data.origin.is_none()) {
+        collect_tuple_types(&data.return_type, &mut tuple_types);
+        for arg in data.args.iter() {
+            collect_tuple_types(&arg.data_type, &mut tuple_types);
+        }
+    }
+    for tuple in writer.tuple_types.iter() {
+        if !tuple_types.contains(tuple) {
+            tuple_types.push(tuple.clone());
+        }
+    }
+    for tuple in tuple_types.iter().filter(|t| !in_headers.contains(t)) {
+        buffer_str += &emit_tuple_struct(tuple);
         buffer_str += "\n\n";
     }
+    // Static functions aren't in the header, so declare them before any function can call them
+    for (name, data) in function_table.iter() {
+        if data.origin.is_none() && !visible_to_linker(name, data) {
+            buffer_str += &format!("static {};\n", c_signature(name, data));
+        }
+    }
+    buffer_str += "\n";
+    buffer_str += &functions;
+    Ok(buffer_str)
+}
+
+/// Emits a compact, function-signature-only header file for a module
+pub fn emit_c_header(
+    module: &str,
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Result<(), Error> {
+    // Construct the header file string
+    let buffer_str = build_c_header(module, function_table);
     // Write to a file
-    let path = "./codegen/iona_generated_header.h";
+    let path = format!("./codegen/{}.h", c_file_stem(module));
+    fs::create_dir_all("./codegen")?;
     println!("wrote C header file to {path}");
     let mut output = File::create(path)?;
     write!(output, "{}", buffer_str)
 }
+
+/// Emits the C source file holding every function body of a module
+pub fn emit_c_source(module: &str, source: &str) -> Result<(), Error> {
+    let path = format!("./codegen/{}.c", c_file_stem(module));
+    fs::create_dir_all("./codegen")?;
+    println!("wrote C source file to {path}");
+    let mut output = File::create(path)?;
+    write!(output, "{}", source)
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::attenuation::expand_attenuation;
+    use crate::lex::lex;
+    use crate::modules::Program;
+    use crate::parse::{compute_scopes, parse, parse_node_expressions, populate_function_table};
+    use crate::permissions::PermissionHierarchy;
+    use std::path::Path;
+    use std::process::{Command, Output};
+
+    /// Compiles every module of a program into `root` with `cc`, and runs it from there
+    ///
+    /// Only the entry module gets the sandbox. Returns `None` if there's no C compiler to run
+    pub(crate) fn compile_and_run(
+        program: &Program,
+        root: &Path,
+        options: &CodegenOptions,
+    ) -> Option<Output> {
+        let mut sources = Vec::new();
+        for module in program.compiled_modules() {
+            let options = CodegenOptions {
+                sandbox: options
+                    .sandbox
+                    .clone()
+                    .filter(|_| module.name == program.entry),
+                ..options.clone()
+            };
+            let source = build_c_source(
+                &module.name,
+                &module.nodes,
+                &module.function_table,
+                &options,
+            )
+            .unwrap();
+            let stem = c_file_stem(&module.name);
+            let header = build_c_header(&module.name, &module.function_table);
+            fs::write(root.join(format!("{stem}.h")), header).unwrap();
+            fs::write(root.join(format!("{stem}.c")), source).unwrap();
+            sources.push(root.join(format!("{stem}.c")));
+        }
+        let executable = root.join("program");
+        let compiled = Command::new("cc")
+            .args(&sources)
+            .arg("-o")
+            .arg(&executable)
+            .status()
+            .ok()?;
+        assert!(compiled.success());
+        Some(
+            Command::new(&executable)
+                .current_dir(root)
+                .output()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn header_tuple_struct() {
+        let code: &str = "fn divmod :: a int -> b int -> (int, int) {
+            #Properties :: Export
+            return (1, 2)
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        let header = build_c_header("main", &function_table);
+        println!("{header}");
+        assert!(
+            header.contains("typedef struct {\n    long _0;\n    long _1;\n} iona_tuple2_int_int;")
+        );
+        assert!(header.contains("iona_tuple2_int_int divmod(long a, long b);"));
+    }
+
+    #[test]
+    fn source_bounds_checked_indexing() {
+        let code: &str = "fn shout :: word str -> str {
+            let copy :: str mut = \"hello\"
+            set copy @ 0..=1 = \"HE\"
+            let pair :: (int, str) = (1, @ word 0)
+            return @ pair 1
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let source =
+            build_c_source("main", &nodes, &function_table, &CodegenOptions::default()).unwrap();
+        println!("{source}");
+        assert!(source.contains("char* copy = strdup(\"hello\");"));
+        assert!(source.contains("iona_str_set(copy, 0, (1 + 1), \"HE\", 3);"));
+        assert!(source.contains(
+            "iona_tuple2_int_str pair = ((iona_tuple2_int_str){1, iona_str_index(word, 0, 4)});"
+        ));
+        assert!(source.contains("return (pair)._1;"));
+        // `(int, str)` isn't part of any signature, so the source defines it
+        assert!(source.contains("} iona_tuple2_int_str;"));
+    }
+
+    #[test]
+    fn source_static_functions() {
+        let code: &str = "fn helper :: x int -> int {
+            return * x 2
+        }
+        fn twice :: x int -> int {
+            #Properties :: Export
+            return helper x
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let header = build_c_header("main", &function_table);
+        assert!(header.contains("long twice(long x);"));
+        assert!(!header.contains("helper"));
+        let source =
+            build_c_source("main", &nodes, &function_table, &CodegenOptions::default()).unwrap();
+        println!("{source}");
+        assert!(source.contains("static long helper(long x);\n"));
+        assert!(source.contains("static long helper(long x) {"));
+        assert!(source.contains("\nlong twice(long x) {"));
+    }
+
+    #[test]
+    fn source_function_pointers() {
+        let code: &str = "fn apply :: f (int -> int :: P) -> x int -> int {
+            #Permissions :: P
+            return f x
+        }
+        fn triple :: x int -> int {
+            return * x 3
+        }
+        fn main :: void {
+            let y :: int = apply &triple 3
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let source =
+            build_c_source("main", &nodes, &function_table, &CodegenOptions::default()).unwrap();
+        println!("{source}");
+        assert!(source.contains("static long apply(long (*f)(long), long x) {\n    return f(x);"));
+        assert!(source.contains("    long y = apply(triple, 3);\n"));
+    }
+
+    #[test]
+    fn source_permission_blocks() {
+        let code: &str = "import read_file from std.files
+        fn load :: path str -> str {
+            #Permissions :: ReadFile WriteFile
+            with_permissions [ReadFile] {
+                with_permissions [] {
+                    println path
+                }
+                return read_file path
+            }
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        expand_attenuation(&mut nodes, &PermissionHierarchy::default());
+        let mut function_table = populate_function_table(&nodes).unwrap();
+        function_table.extend(stdlib::function_table("std.io"));
+        function_table.extend(stdlib::function_table("std.files"));
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let source =
+            build_c_source("main", &nodes, &function_table, &CodegenOptions::default()).unwrap();
+        println!("{source}");
+        assert!(source.contains(
+            "    unsigned iona_saved_permissions_1 = iona_permissions;
+    iona_permissions &= IONA_READ_FILE;
+    unsigned iona_saved_permissions_2 = iona_permissions;
+    iona_permissions &= 0u;
+    println(path);
+    iona_permissions = iona_saved_permissions_2;
+    char* iona_tmp_3 = read_file(path);
+    iona_permissions = iona_saved_permissions_1;
+    return iona_tmp_3;
+    iona_permissions = iona_saved_permissions_1;
+}"
+        ));
+    }
+
+    #[test]
+    fn source_sandbox() {
+        let code: &str = "fn helper :: void {
+            println \"hi\"
+        }
+        fn main :: void {
+            helper
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let mut function_table = populate_function_table(&nodes).unwrap();
+        function_table.extend(stdlib::function_table("std.io"));
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let allowed = [Permissions::ReadFile, Permissions::WriteNetwork];
+        let options = CodegenOptions {
+            sandbox: Some(allowed.to_vec()),
+            ..Default::default()
+        };
+        let source = build_c_source("main", &nodes, &function_table, &options).unwrap();
+        println!("{source}");
+        assert!(source.contains("static void iona_install_sandbox(unsigned allowed) {"));
+        assert!(source.contains(
+            "int main(void) {\n    iona_install_sandbox(IONA_READ_FILE | IONA_WRITE_NETWORK);\n    helper();\n    return 0;\n}"
+        ));
+        // Libraries leave the process alone
+        let source =
+            build_c_source("main", &nodes, &function_table, &CodegenOptions::default()).unwrap();
+        assert!(!source.contains("iona_install_sandbox"));
+    }
+
+    #[test]
+    fn source_entry_point() {
+        let code: &str = "fn helper :: void {
+            return
+        }
+        fn main :: void {
+            with_permissions [] {
+                return
+            }
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        expand_attenuation(&mut nodes, &PermissionHierarchy::default());
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let source =
+            build_c_source("main", &nodes, &function_table, &CodegenOptions::default()).unwrap();
+        println!("{source}");
+        assert!(source.contains("static void helper() {\n    return;\n}"));
+        assert!(source.contains(
+            "int main(void) {\n    unsigned iona_saved_permissions_1 = iona_permissions;\n    iona_permissions &= 0u;\n    iona_permissions = iona_saved_permissions_1;\n    return 0;\n    iona_permissions = iona_saved_permissions_1;\n    return 0;\n}"
+        ));
+        assert!(build_c_header("main", &function_table).contains("int main(void);"));
+    }
+
+    #[test]
+    fn source_trace_permissions() {
+        let root = crate::modules::tests::project(
+            "trace_permissions",
+            &[(
+                "main.iona",
+                "import read_file write_file from std.files
+fn copy :: source str -> target str -> void {
+    #Permissions :: ReadFile WriteFile WriteConsole
+    write_file (read_file source) target
+    println target
+}",
+            )],
+        );
+        let program =
+            crate::modules::Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let module = &program.modules["main"];
+        let options = CodegenOptions {
+            trace_permissions: true,
+            ..Default::default()
+        };
+        let source =
+            build_c_source("main", &module.nodes, &module.function_table, &options).unwrap();
+        println!("{source}");
+        assert!(source.contains(
+            "static inline char* iona_traced_read_file(const char* iona_function, int iona_line, char* path) {
+    iona_trace(\"ReadFile\", iona_function, iona_line, path);
+    return read_file(path);
+}"
+        ));
+        assert!(source.contains("    iona_trace(\"WriteFile\", iona_function, iona_line, path);\n    write_file(data, path);\n}"));
+        assert!(source.contains(
+            "    iona_traced_write_file(\"main.copy\", 4, iona_traced_read_file(\"main.copy\", 4, source), target);\n    iona_traced_println(\"main.copy\", 5, target);"
+        ));
+    }
+
+    #[test]
+    fn random_int_wide_ranges() {
+        // The widest range used to overflow `high - low + 1` to 0 and divide by it
+        let root = crate::modules::tests::project(
+            "random_int",
+            &[(
+                "main.iona",
+                "import random_int from std.random
+fn main :: void {
+    #Permissions :: ReadRandom
+    let low :: int = - (- 0 9223372036854775807) 1
+    let widest :: int = random_int low 9223372036854775807
+    let wide :: int = random_int 0 4000000000000
+    let die :: int = random_int 1 6
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert!(program.modules["main"].problems.is_empty());
+        let Some(output) = compile_and_run(&program, &root, &CodegenOptions::default()) else {
+            eprintln!("skipping: no C compiler");
+            return;
+        };
+        assert!(output.status.success(), "{output:?}");
+    }
+}
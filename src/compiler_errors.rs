@@ -13,7 +13,7 @@ impl Display for ProblemClass {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProblemClass::Lint => write!(f, "lint"),
-            ProblemClass::Warning => write!(f, "earning"),
+            ProblemClass::Warning => write!(f, "warning"),
             ProblemClass::Error => write!(f, "error"),
         }
     }
@@ -26,6 +26,26 @@ pub struct CompilerProblem {
     hint: String,
     pub line: usize,
     pub word_index: usize,
+    /// A second place in the code that explains the problem, like the declaration a value doesn't match
+    pub related: Option<Box<RelatedLocation>>,
+    /// A change to the source that solves the problem, applied by `--fix`
+    pub fix: Option<Box<Fix>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RelatedLocation {
+    pub message: String,
+    pub line: usize,
+    pub word_index: usize,
+    /// The file it's in and the text of its line, if it isn't in the file with the problem
+    pub file: Option<(String, String)>,
+}
+
+/// A machine-applicable change: replace a whole line, or remove it if there's no replacement
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fix {
+    pub line: usize,
+    pub replacement: Option<String>,
 }
 
 impl CompilerProblem {
@@ -42,8 +62,79 @@ impl CompilerProblem {
             hint: hint.to_string(),
             line,
             word_index: word,
+            related: None,
+            fix: None,
+        }
+    }
+
+    /// Point at a second location, which is shown as a note below the problem
+    pub fn with_related(mut self, message: &str, line: usize, word: usize) -> CompilerProblem {
+        self.related = Some(Box::new(RelatedLocation {
+            message: message.to_string(),
+            line,
+            word_index: word,
+            file: None,
+        }));
+        self
+    }
+
+    /// Point at a location in another file, like the project's policy file
+    pub fn with_related_in(
+        mut self,
+        file: &str,
+        line_text: &str,
+        message: &str,
+        line: usize,
+        word: usize,
+    ) -> CompilerProblem {
+        self.related = Some(Box::new(RelatedLocation {
+            message: message.to_string(),
+            line,
+            word_index: word,
+            file: Some((file.to_string(), line_text.to_string())),
+        }));
+        self
+    }
+
+    pub fn with_fix(mut self, line: usize, replacement: Option<String>) -> CompilerProblem {
+        self.fix = Some(Box::new(Fix { line, replacement }));
+        self
+    }
+}
+
+/// Apply every fix to the program text, returning the new text and how many fixes were applied
+///
+/// Only the first fix for a line is applied, since later ones were written against the original line
+pub fn apply_fixes(program_text: &str, problems: &[CompilerProblem]) -> (String, usize) {
+    let mut fixes: Vec<&Fix> = Vec::new();
+    for fix in problems.iter().filter_map(|p| p.fix.as_deref()) {
+        if !fixes.iter().any(|f| f.line == fix.line) {
+            fixes.push(fix);
+        }
+    }
+    let mut text = String::new();
+    for (number, line) in program_text.lines().enumerate() {
+        match fixes.iter().find(|f| f.line == number) {
+            Some(Fix {
+                replacement: Some(replacement),
+                ..
+            }) => {
+                text.push_str(replacement);
+                text.push('\n');
+            }
+            Some(Fix {
+                replacement: None, ..
+            }) => {}
+            None => {
+                text.push_str(line);
+                text.push('\n');
+            }
         }
     }
+    if !program_text.ends_with('\n') {
+        text.pop();
+    }
+    (text, fixes.len())
 }
 
 /// Pass in the raw program text and a compiler problem to print out issues
@@ -68,10 +159,29 @@ pub fn display_problem(program_text: &str, message_context: &str, problem: &Comp
         })
         .collect::<String>();
 
+    // The related location gets one line of context
+    let note = match &problem.related {
+        Some(related) => {
+            let (place, text) = match &related.file {
+                Some((file, text)) => (format!("{file}, line {}", related.line + 1), text.as_str()),
+                None => (
+                    format!("line {}", related.line + 1),
+                    program_text.lines().nth(related.line).unwrap_or(""),
+                ),
+            };
+            format!(
+                "\x1b[1;34m note:\x1b[0m {} ({place})\n   \x1b[1;34m{} |\x1b[0m {text}\n",
+                related.message,
+                related.line + 1,
+            )
+        }
+        None => String::new(),
+    };
+
     println!(
         // Hex codes are for colored output
         // We have to push line number up by 1 b/c zero-index vs 1-index
-        "{color_hex_code}{}\x1b[0m: {message_context} on line {}: {}\n{}\n\x1b[1;34m hint:\x1b[0m {}",
+        "{color_hex_code}{}\x1b[0m: {message_context} on line {}: {}\n{}\n{note}\x1b[1;34m hint:\x1b[0m {}",
         problem.class, problem.line+1, problem.message, context.trim_end(), problem.hint
     );
 }
@@ -0,0 +1,162 @@
+//! Checking the `Deterministic` property: a `Deterministic` function returns the same result for the same arguments, on every run
+//!
+//! Unlike a `Pure` function, a `Deterministic` function may do I/O, but nothing it calls (directly or through other functions) may read the clock, draw random numbers or read environment variables, since those change from run to run. `#Trusted` doesn't hide them here: a trusted function reading the clock is still nondeterministic. Functions passed in count as part of the input, so they're checked where they're passed. Iona has no unordered collections, so iteration order can't break determinism (yet).
+
+use std::collections::BTreeSet;
+
+use crate::call_graph::{CallGraph, FunctionId};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::permissions::Permissions;
+use crate::properties::Properties;
+use crate::stdlib::is_stdlib_module;
+
+/// The permissions of builtins whose results change from run to run, and what those builtins do
+const NONDETERMINISTIC: [(Permissions, &str); 3] = [
+    (Permissions::ReadClock, "reads the clock"),
+    (Permissions::ReadRandom, "draws random numbers"),
+    (Permissions::ReadEnvironment, "reads environment variables"),
+];
+
+fn is_deterministic(graph: &CallGraph, function: &FunctionId) -> bool {
+    graph
+        .functions
+        .get(function)
+        .is_some_and(|data| data.properties.contains(&Properties::Deterministic))
+}
+
+/// What a builtin does that makes it nondeterministic, or `None` if it isn't
+fn nondeterminism(graph: &CallGraph, function: &FunctionId) -> Option<&'static str> {
+    if !is_stdlib_module(&function.module) {
+        return None;
+    }
+    let data = graph.functions.get(function)?;
+    NONDETERMINISTIC
+        .iter()
+        .find(|(permission, _)| data.permissions.contains(permission))
+        .map(|(_, effect)| *effect)
+}
+
+/// `Deterministic` functions that call something nondeterministic, paired with their module
+///
+/// Each callee is reported once per function, at the first call, with the chain of calls leading to the builtin. Callees that are `Deterministic` themselves are reported on their own.
+pub fn check_determinism(graph: &CallGraph) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    for function in graph.lines.keys().filter(|f| is_deterministic(graph, f)) {
+        let mut reported: BTreeSet<&FunctionId> = BTreeSet::new();
+        for call in graph.calls_from(function) {
+            if is_deterministic(graph, &call.callee) || !reported.insert(&call.callee) {
+                continue;
+            }
+            let Some(chain) = graph.chain(
+                &call.callee,
+                |f| nondeterminism(graph, f).is_some(),
+                |c| !is_deterministic(graph, &c.callee),
+            ) else {
+                continue;
+            };
+            let source = chain.last().map_or(&call.callee, |c| &c.callee);
+            let effect = nondeterminism(graph, source).unwrap_or_default();
+            let through = if chain.is_empty() {
+                String::new()
+            } else {
+                let names: Vec<&str> = std::iter::once(call.called_as.as_str())
+                    .chain(chain.iter().map(|c| c.called_as.as_str()))
+                    .collect();
+                format!(" (`{}`)", names.join("` -> `"))
+            };
+            let problem = CompilerProblem::new(
+                ProblemClass::Error,
+                &format!(
+                    "`{}` is `Deterministic`, but calls `{}`, which {effect}{through}",
+                    function.name, call.called_as
+                ),
+                &format!(
+                    "pass what `{}` returns in as an argument, or remove `Deterministic` from `{}`",
+                    source.name, function.name
+                ),
+                call.line,
+                call.word,
+            );
+            let problem = match chain.last() {
+                Some(last) if last.caller.module == function.module => problem.with_related(
+                    &format!("`{}` is called here", last.called_as),
+                    last.line,
+                    last.word,
+                ),
+                _ => problem,
+            };
+            problems.push((function.module.clone(), problem));
+        }
+    }
+    problems
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::tests::project;
+    use crate::modules::Program;
+
+    #[test]
+    fn check_deterministic_functions() {
+        let root = project(
+            "determinism",
+            &[(
+                "main.iona",
+                "import now from std.time
+import random_int from std.random
+import get_env from std.env
+fn main :: void {
+    #Permissions :: ReadClock ReadRandom ReadEnvironment WriteConsole
+    let s :: int = simulate 3
+}
+fn simulate :: steps int -> int {
+    #Properties :: Deterministic
+    #Permissions :: ReadClock ReadRandom ReadEnvironment WriteConsole
+    println \"simulating\"
+    let a :: int = step steps
+    let b :: int = jitter steps
+    let c :: int = roll
+    let d :: int = roll
+    let e :: str = get_env \"SEED\"
+    return + a b
+}
+fn step :: n int -> int {
+    #Properties :: Deterministic
+    return * n 2
+}
+fn jitter :: n int -> int {
+    #Permissions :: ReadClock
+    return + n (stamp)
+}
+fn stamp :: int {
+    #Trusted :: ReadClock \"only used for logging\"
+    return now
+}
+fn roll :: int {
+    #Permissions :: ReadRandom
+    return random_int 1 6
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let graph = CallGraph::build(&program);
+        let problems: Vec<CompilerProblem> = check_determinism(&graph)
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect();
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`simulate` is `Deterministic`, but calls `jitter`, which reads the clock (`jitter` -> `stamp` -> `now`)",
+                "`simulate` is `Deterministic`, but calls `roll`, which draws random numbers (`roll` -> `random_int`)",
+                "`simulate` is `Deterministic`, but calls `get_env`, which reads environment variables",
+            ]
+        );
+        assert_eq!((problems[0].line, problems[0].word_index), (12, 5));
+        assert_eq!(problems[0].related.as_ref().unwrap().line, 28);
+    }
+}
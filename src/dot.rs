@@ -0,0 +1,286 @@
+//! `iona graph`: the call graph in Graphviz DOT, annotated with permissions and properties
+//!
+//! Functions are grouped into a cluster per module and filled with the colours of the permissions they require (striped when there's more than one). `Pure` functions are dashed and `Export` functions are bold. Render it with `dot -Tsvg main.dot -o main.svg`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::call_graph::{CallGraph, FunctionId};
+use crate::modules::Program;
+use crate::permissions::Permissions;
+use crate::properties::Properties;
+use crate::stdlib::is_stdlib_module;
+
+/// What to draw
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    /// Draw each standard library module as one node, instead of one per function
+    pub collapse_stdlib: bool,
+    /// Highlight every call path leading to this permission, and grey out the rest
+    pub highlight: Option<Permissions>,
+}
+
+/// Custom permissions take these colours in the order they're declared, wrapping around
+const CUSTOM_COLORS: [&str; 6] = [
+    "#fb9a99", "#fdbf6f", "#cab2d6", "#ffff99", "#fccde5", "#d9d9d9",
+];
+
+fn permission_color(program: &Program, permission: &Permissions) -> &'static str {
+    match permission {
+        Permissions::ReadFile => "#a6cee3",
+        Permissions::WriteFile => "#80b1d3",
+        Permissions::ReadNetwork => "#b3de69",
+        Permissions::WriteNetwork => "#8dd3c7",
+        Permissions::ReadConsole => "#bebada",
+        Permissions::WriteConsole => "#bc80bd",
+        Permissions::ReadEnvironment => "#ccebc5",
+        Permissions::SpawnProcess => "#fb8072",
+        Permissions::ReadClock => "#ffed6f",
+        Permissions::ReadRandom => "#fdb462",
+        Permissions::CallForeign => "#e31a1c",
+        Permissions::Scoped(permission, _) => permission_color(program, permission),
+        Permissions::Custom(name) => {
+            let index = program
+                .permissions
+                .implies
+                .keys()
+                .position(|p| p == name)
+                .unwrap_or(0);
+            CUSTOM_COLORS[index % CUSTOM_COLORS.len()]
+        }
+    }
+}
+
+/// Quote an ID for DOT
+fn dot_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// One box in the drawing: a function, or a whole collapsed module
+#[derive(Debug, Default)]
+struct DotNode {
+    label: String,
+    /// The module cluster it's drawn in, or `None` for a collapsed module
+    cluster: Option<String>,
+    required: BTreeSet<Permissions>,
+    properties: Vec<Properties>,
+}
+
+/// The name of the box a function is drawn in
+fn node_name(function: &FunctionId, options: &DotOptions) -> String {
+    if options.collapse_stdlib && is_stdlib_module(&function.module) {
+        function.module.clone()
+    } else {
+        function.to_string()
+    }
+}
+
+/// How to fill a box requiring some permissions: its style and its `fillcolor`
+fn fill(program: &Program, required: &BTreeSet<Permissions>) -> Option<(&'static str, String)> {
+    let mut colors: Vec<&str> = Vec::new();
+    for color in required.iter().map(|p| permission_color(program, p)) {
+        if !colors.contains(&color) {
+            colors.push(color);
+        }
+    }
+    match colors.len() {
+        0 => None,
+        1 => Some(("filled", colors[0].to_string())),
+        _ => Some(("striped", colors.join(":"))),
+    }
+}
+
+/// The call graph of a program as a DOT file
+pub fn call_graph_dot(program: &Program, options: &DotOptions) -> String {
+    let graph = CallGraph::build(program);
+    let required = graph.required_permissions();
+    // Highlighting a permission includes its scoped versions
+    let requires = |f: &FunctionId, p: &Permissions| {
+        required
+            .get(f)
+            .is_some_and(|r| r.keys().any(|q| q.narrows(p)))
+    };
+
+    // Only standard library functions that are called are worth drawing
+    let called: BTreeSet<&FunctionId> = graph.calls.iter().map(|call| &call.callee).collect();
+    let mut nodes: BTreeMap<String, DotNode> = BTreeMap::new();
+    for (id, data) in graph.functions.iter() {
+        if !graph.lines.contains_key(id) && !called.contains(id) {
+            continue;
+        }
+        let collapsed = options.collapse_stdlib && is_stdlib_module(&id.module);
+        let node = nodes.entry(node_name(id, options)).or_default();
+        if collapsed {
+            node.label = id.module.clone();
+        } else {
+            node.label = id.name.clone();
+            node.cluster = Some(id.module.clone());
+            node.properties = data.properties.clone();
+        }
+        node.required
+            .extend(required.get(id).into_iter().flat_map(|r| r.keys().cloned()));
+    }
+
+    // Calls between the same two boxes are drawn once, highlighted if any of them leads to the permission
+    let mut edges: BTreeMap<(String, String), bool> = BTreeMap::new();
+    for call in graph.calls.iter() {
+        let on_path = options
+            .highlight
+            .as_ref()
+            .is_some_and(|p| requires(&call.callee, p));
+        let edge = edges
+            .entry((
+                node_name(&call.caller, options),
+                node_name(&call.callee, options),
+            ))
+            .or_default();
+        *edge |= on_path;
+    }
+
+    let mut clusters: BTreeMap<Option<&str>, Vec<(&String, &DotNode)>> = BTreeMap::new();
+    for (name, node) in nodes.iter() {
+        clusters
+            .entry(node.cluster.as_deref())
+            .or_default()
+            .push((name, node));
+    }
+    let mut dot = format!("digraph {} {{\n", dot_string(&program.entry));
+    dot.push_str("  rankdir=LR;\n  node [shape=box, fontname=\"Helvetica\"];\n");
+    for (cluster, members) in clusters {
+        let indent = match cluster {
+            Some(module) => {
+                dot.push_str(&format!(
+                    "  subgraph {} {{\n    label={};\n",
+                    dot_string(&format!("cluster_{module}")),
+                    dot_string(module)
+                ));
+                "    "
+            }
+            None => "  ",
+        };
+        for (name, node) in members {
+            let fill = fill(program, &node.required);
+            let mut style: Vec<&str> = fill.iter().map(|(style, _)| *style).collect();
+            if node.properties.contains(&Properties::Pure) {
+                style.push("dashed");
+            }
+            if node.properties.contains(&Properties::Export) {
+                style.push("bold");
+            }
+            let mut attributes = vec![format!("label={}", dot_string(&node.label))];
+            if !style.is_empty() {
+                attributes.push(format!("style={}", dot_string(&style.join(","))));
+            }
+            if let Some((_, color)) = fill {
+                attributes.push(format!("fillcolor={}", dot_string(&color)));
+            }
+            if node.cluster.is_none() {
+                attributes.push("peripheries=2".to_string());
+            }
+            match &options.highlight {
+                Some(p) if node.required.iter().any(|q| q.narrows(p)) => {
+                    attributes.push("color=red, penwidth=2".to_string())
+                }
+                Some(_) => attributes.push("color=gray70, fontcolor=gray50".to_string()),
+                None => {}
+            }
+            dot.push_str(&format!(
+                "{indent}{} [{}];\n",
+                dot_string(name),
+                attributes.join(", ")
+            ));
+        }
+        if cluster.is_some() {
+            dot.push_str("  }\n");
+        }
+    }
+    for ((caller, callee), on_path) in edges {
+        let attributes = match (&options.highlight, on_path) {
+            (Some(_), true) => " [color=red, penwidth=2]",
+            (Some(_), false) => " [color=gray70]",
+            (None, _) => "",
+        };
+        dot.push_str(&format!(
+            "  {} -> {}{attributes};\n",
+            dot_string(&caller),
+            dot_string(&callee)
+        ));
+    }
+
+    // A key for every permission that shows up (scoped ones share a colour with the unscoped one)
+    let shown: BTreeSet<&Permissions> = nodes
+        .values()
+        .flat_map(|n| n.required.iter().map(|p| p.unscoped()))
+        .collect();
+    if !shown.is_empty() {
+        dot.push_str("  subgraph \"cluster_legend\" {\n    label=\"Permissions\";\n");
+        for permission in shown {
+            dot.push_str(&format!(
+                "    {} [label={}, style=filled, fillcolor={}];\n",
+                dot_string(&format!("legend {permission}")),
+                dot_string(&permission.to_string()),
+                dot_string(permission_color(program, permission))
+            ));
+        }
+        dot.push_str("  }\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::tests::project;
+
+    #[test]
+    fn call_graph_dot_output() {
+        let root = project(
+            "dot",
+            &[(
+                "main.iona",
+                "import read_file write_file from std.files
+fn main :: void {
+    #Permissions :: ReadFile WriteFile
+    let text :: str = load \"a.txt\"
+    write_file text \"b.txt\"
+    let n :: int = double 2
+}
+fn load :: path str -> str {
+    #Permissions :: ReadFile
+    return read_file path
+}
+fn double :: n int -> int {
+    #Properties :: Pure
+    return * n 2
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+
+        let dot = call_graph_dot(&program, &DotOptions::default());
+        println!("{dot}");
+        assert!(dot.starts_with("digraph \"main\" {\n"));
+        assert!(dot.contains("  subgraph \"cluster_main\" {\n    label=\"main\";\n"));
+        assert!(dot.contains(
+            "    \"main.main\" [label=\"main\", style=\"striped\", fillcolor=\"#a6cee3:#80b1d3\"];\n"
+        ));
+        assert!(dot.contains("    \"main.double\" [label=\"double\", style=\"dashed\"];\n"));
+        assert!(dot.contains("  \"main.load\" -> \"std.files.read_file\";\n"));
+        assert!(dot.contains("\"legend WriteFile\" [label=\"WriteFile\", style=filled"));
+
+        let options = DotOptions {
+            collapse_stdlib: true,
+            highlight: Some(Permissions::ReadFile),
+        };
+        let dot = call_graph_dot(&program, &options);
+        println!("{dot}");
+        assert!(dot.contains("  \"std.files\" [label=\"std.files\", style=\"striped\""));
+        assert!(!dot.contains("std.files.read_file"));
+        assert!(dot.contains("  \"main.main\" -> \"main.load\" [color=red, penwidth=2];\n"));
+        assert!(dot.contains("  \"main.main\" -> \"main.double\" [color=gray70];\n"));
+        assert!(dot.contains("  \"main.main\" -> \"std.files\" [color=gray70];\n"));
+        assert!(dot.contains("  \"main.load\" -> \"std.files\" [color=red, penwidth=2];\n"));
+    }
+}
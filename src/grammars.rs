@@ -8,8 +8,9 @@ use std::fmt::Debug;
 
 use crate::compiler_errors::{CompilerProblem, ProblemClass};
 use crate::lex::{Symbol, Token, VALID_EXPRESSION_TOKENS};
-use crate::parse::{PrimitiveDataType, Variable};
-use crate::permissions::Permissions;
+use crate::parse::{DataType, FunctionType, PrimitiveDataType, Variable};
+use crate::parse_expressions::{is_identifier, Object};
+use crate::permissions::{Permissions, PERMISSION_LIST};
 use crate::properties::{Properties, PROPERTY_LIST};
 
 #[derive(Debug)]
@@ -19,6 +20,9 @@ pub enum Grammar {
     Function(GrammarFunctionDeclaration),
     Property(GrammarProperty),
     Permission(GrammarPermissions),
+    CustomPermission(GrammarCustomPermission),
+    Attenuation(GrammarAttenuation),
+    Trusted(GrammarTrusted),
     VariableAssignment(GrammarVariableAssignments),
     Return,
     Expression(GrammarExpression),
@@ -29,10 +33,13 @@ pub enum Grammar {
 impl Grammar {
     pub fn new(symbol: Symbol) -> Grammar {
         match symbol {
-            Symbol::Import => Grammar::Import(GrammarImports::new()),
+            Symbol::Import | Symbol::Export => Grammar::Import(GrammarImports::new(symbol)),
             Symbol::FunctionDeclare => Grammar::Function(GrammarFunctionDeclaration::new()),
             Symbol::PropertyDeclaration => Grammar::Property(GrammarProperty::new()),
             Symbol::PermissionsDeclaration => Grammar::Permission(GrammarPermissions::new()),
+            Symbol::PermissionDeclare => Grammar::CustomPermission(GrammarCustomPermission::new()),
+            Symbol::WithPermissions => Grammar::Attenuation(GrammarAttenuation::new()),
+            Symbol::TrustedDeclaration => Grammar::Trusted(GrammarTrusted::new()),
             Symbol::Let | Symbol::Set => {
                 Grammar::VariableAssignment(GrammarVariableAssignments::new(symbol))
             }
@@ -54,6 +61,9 @@ impl Grammar {
             Grammar::Function(g) => g.step(token),
             Grammar::Property(g) => g.step(token),
             Grammar::Permission(g) => g.step(token),
+            Grammar::CustomPermission(g) => g.step(token),
+            Grammar::Attenuation(g) => g.step(token),
+            Grammar::Trusted(g) => g.step(token),
             Grammar::VariableAssignment(g) => g.step(token),
             Grammar::Return => None,
             Grammar::Expression(g) => g.step(token),
@@ -69,6 +79,9 @@ impl Grammar {
             Grammar::Function(g) => g.done,
             Grammar::Property(g) => g.done,
             Grammar::Permission(g) => g.done,
+            Grammar::CustomPermission(g) => g.done,
+            Grammar::Attenuation(g) => g.done,
+            Grammar::Trusted(g) => g.done,
             Grammar::VariableAssignment(g) => g.done,
             Grammar::Return => true,
             Grammar::Expression(g) => g.done,
@@ -84,46 +97,126 @@ impl Grammar {
 enum StagesImport {
     Initialized,
     ProcessingArguments,
+    ProcessingAlias,
     ProcessingFile,
 }
 
+/// A single imported item, like `read_file` or `read_file as rf`
+#[derive(Debug, Clone)]
+pub struct ImportItem {
+    pub name: Token,
+    pub alias: Option<Token>,
+}
+
+impl ImportItem {
+    /// The token holding the name the item is known by in the importing module
+    pub fn binding(&self) -> &Token {
+        self.alias.as_ref().unwrap_or(&self.name)
+    }
+}
+
 /// The grammar for importing a file or functions/data
+///
+/// `export` lines share this grammar: they import items and make them available to modules that import this one
 #[derive(Debug)]
 pub struct GrammarImports {
     is_valid: bool,
     done: bool,
     stage: StagesImport,
-    arguments: Option<Vec<Token>>,
-    file: String,
+    pub items: Vec<ImportItem>,
+    /// `import * from module`
+    pub wildcard: bool,
+    /// `export ... from module`
+    pub reexport: bool,
+    pub file: String,
+    /// Position of the module name within the line, for error messages
+    pub file_word: usize,
 }
 
 impl GrammarImports {
-    fn new() -> GrammarImports {
+    fn new(symbol: Symbol) -> GrammarImports {
         GrammarImports {
             is_valid: true,
             done: false,
             stage: StagesImport::Initialized,
-            arguments: None,
+            items: Vec::new(),
+            wildcard: false,
+            reexport: symbol == Symbol::Export,
             file: "unknown".to_string(),
+            file_word: 0,
+        }
+    }
+
+    /// `import a.b` imports the whole module, to be used like `b.function`
+    pub fn is_whole_module(&self) -> bool {
+        self.items.is_empty() && !self.wildcard
+    }
+
+    /// Wrap up an import of the whole module named by `module`
+    fn import_whole_module(&mut self, module: &Token) -> Option<CompilerProblem> {
+        self.file = module.text.to_string();
+        self.file_word = module.word;
+        self.done = true;
+        if !self.reexport {
+            return None;
+        }
+        Some(CompilerProblem::new(
+            ProblemClass::Error,
+            "a whole module cannot be re-exported",
+            &format!(
+                "re-export items by name, or all of them with `export * from {}`",
+                module.text
+            ),
+            module.line,
+            module.word,
+        ))
+    }
+
+    /// The line ended: only `import module` (without a `.`) is complete without `from`
+    fn end_of_line(&mut self, next: &Token) -> Option<CompilerProblem> {
+        let single_item = matches!(self.stage, StagesImport::ProcessingArguments)
+            && !self.wildcard
+            && self.items.len() == 1
+            && self.items[0].alias.is_none();
+        if single_item {
+            let module = self.items.remove(0).name;
+            return self.import_whole_module(&module);
         }
+        self.done = true;
+        self.is_valid = false;
+        Some(CompilerProblem::new(
+            ProblemClass::Error,
+            "this import ended before naming the module to import from",
+            "imports look like this: `import read_file from std.files`, or `import std.files` for the whole module",
+            next.line,
+            next.word,
+        ))
     }
 
     fn step(&mut self, next: &Token) -> Option<CompilerProblem> {
         if self.done {
             return None;
         }
+        if next.symbol == Symbol::Newline {
+            return self.end_of_line(next);
+        }
         let mut error_message = None;
         match self.stage {
             StagesImport::Initialized => {
-                // If there's a dot we're importing a file and can wrap up immediately
-                if next.text.contains(".") {
-                    self.file = next.text.to_string();
-                    self.done = true;
+                if next.symbol == Symbol::OpMul {
+                    self.wildcard = true;
+                    self.stage = StagesImport::ProcessingArguments;
+                } else if next.text.contains(".") {
+                    // If there's a dot we're importing a file and can wrap up immediately
+                    error_message = self.import_whole_module(next);
                 } else {
                     // We must be importing arguments so grab the first one
                     self.stage = StagesImport::ProcessingArguments;
                     if next.symbol == Symbol::Value {
-                        self.arguments = Some(vec![next.clone()]);
+                        self.items.push(ImportItem {
+                            name: next.clone(),
+                            alias: None,
+                        });
                     } else {
                         error_message = Some(CompilerProblem::new(
                             ProblemClass::Error,
@@ -137,10 +230,34 @@ impl GrammarImports {
             }
             StagesImport::ProcessingArguments => match next.symbol {
                 Symbol::From => self.stage = StagesImport::ProcessingFile,
-                Symbol::Value => {
-                    if let Some(args) = &mut self.arguments {
-                        args.push(next.clone());
-                    }
+                Symbol::Value if !self.wildcard => {
+                    self.items.push(ImportItem {
+                        name: next.clone(),
+                        alias: None,
+                    });
+                }
+                Symbol::As if self.items.last().is_some_and(|i| i.alias.is_none()) => {
+                    self.stage = StagesImport::ProcessingAlias;
+                }
+                Symbol::Value | Symbol::As | Symbol::OpMul
+                    if self.wildcard || next.symbol == Symbol::OpMul =>
+                {
+                    error_message = Some(CompilerProblem::new(
+                        ProblemClass::Error,
+                        "`*` imports every exported item, it can't be combined with named items or aliases",
+                        "either import everything with `*`, or import items by name",
+                        next.line,
+                        next.word,
+                    ));
+                }
+                Symbol::As => {
+                    error_message = Some(CompilerProblem::new(
+                        ProblemClass::Error,
+                        "`as` must follow the name of an imported item",
+                        "aliases look like this: `import read_file as rf from std.files`",
+                        next.line,
+                        next.word,
+                    ));
                 }
                 _ => {
                     error_message = Some(CompilerProblem::new(
@@ -155,10 +272,28 @@ impl GrammarImports {
                     ));
                 }
             },
+            StagesImport::ProcessingAlias => match next.symbol {
+                Symbol::Value => {
+                    if let Some(item) = self.items.last_mut() {
+                        item.alias = Some(next.clone());
+                    }
+                    self.stage = StagesImport::ProcessingArguments;
+                }
+                _ => {
+                    error_message = Some(CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("expected an alias after `as` but received: {}", next.text),
+                        "aliases look like this: `import read_file as rf from std.files`",
+                        next.line,
+                        next.word,
+                    ));
+                }
+            },
             // Only entered if we had arguments
             StagesImport::ProcessingFile => match next.symbol {
                 Symbol::Value => {
                     self.file = next.text.to_string();
+                    self.file_word = next.word;
                     self.done = true;
                 }
                 _ => {
@@ -179,6 +314,240 @@ impl GrammarImports {
     }
 }
 
+// -------------------- Grammar: Tuple Types --------------------
+
+/// Accumulates a (possibly nested) tuple type like `(int, (str, bool))` one token at a time
+///
+/// This is not a grammar of its own: it is embedded in the grammars that accept types. It should be created after the opening `(` has been consumed.
+///
+/// Arguments can also be functions, whose types are written like `(int -> str -> bool :: P)`: the argument types, the return type, and optionally a permission variable.
+#[derive(Debug)]
+struct TupleTypeParser {
+    /// One list of element types per currently open parenthesis
+    stack: Vec<Vec<DataType>>,
+    expecting_element: bool,
+    /// Only arguments can be functions
+    functions_allowed: bool,
+    /// A `->` was found inside the outermost parentheses, so this is a function type
+    is_function: bool,
+    /// The permission variable, once the `::` before it has been found (empty until its name is)
+    permissions: Option<String>,
+}
+
+impl TupleTypeParser {
+    fn new() -> TupleTypeParser {
+        TupleTypeParser {
+            stack: vec![Vec::new()],
+            expecting_element: true,
+            functions_allowed: false,
+            is_function: false,
+            permissions: None,
+        }
+    }
+
+    /// A parser for the type of an argument, which may also be a function type
+    fn for_argument() -> TupleTypeParser {
+        TupleTypeParser {
+            functions_allowed: true,
+            ..TupleTypeParser::new()
+        }
+    }
+
+    /// Returns the finished type once the outermost `)` is found
+    fn step(&mut self, next: &Token) -> Result<Option<DataType>, CompilerProblem> {
+        let error = |message: &str, hint: &str| {
+            Err(CompilerProblem::new(
+                ProblemClass::Error,
+                message,
+                hint,
+                next.line,
+                next.word,
+            ))
+        };
+        let outermost = self.stack.len() == 1;
+        // After `::` comes the permission variable, and then the end of the function type
+        if let Some(variable) = &self.permissions {
+            return match next.symbol {
+                Symbol::Value if variable.is_empty() && is_identifier(&next.text) => {
+                    if PERMISSION_LIST.contains(&next.text.as_str()) {
+                        return error(
+                            &format!("`{}` is a permission, not a permission variable", next.text),
+                            "function types name a variable standing for whatever the function requires, like `(int -> int :: P)`",
+                        );
+                    }
+                    self.permissions = Some(next.text.clone());
+                    Ok(None)
+                }
+                Symbol::ParenClose if !variable.is_empty() => Ok(Some(self.finish_function())),
+                _ if variable.is_empty() => error(
+                    &format!("expected a permission variable, but found `{}`", next.text),
+                    "name the permissions the function may require, like `(int -> int :: P)`",
+                ),
+                _ => error(
+                    &format!(
+                        "expected a `)` after the permission variable, but found `{}`",
+                        next.text
+                    ),
+                    "a function type ends with its permission variable, like `(int -> int :: P)`",
+                ),
+            };
+        }
+        match next.symbol {
+            Symbol::ParenOpen
+            | Symbol::TypeBool
+            | Symbol::TypeInt
+            | Symbol::TypeFloat
+            | Symbol::TypeStr
+            | Symbol::TypeVoid
+                if !self.expecting_element =>
+            {
+                error(
+                    &format!(
+                        "expected a `,` or a `)` in tuple type, but found `{}`",
+                        next.text
+                    ),
+                    "separate the elements of a tuple type with commas, like this: `(int, str)`",
+                )
+            }
+            Symbol::ParenOpen => {
+                self.stack.push(Vec::new());
+                Ok(None)
+            }
+            // Functions can return nothing
+            Symbol::TypeVoid if self.is_function && outermost => {
+                if let Some(top) = self.stack.last_mut() {
+                    top.push(DataType::Primitive(PrimitiveDataType::Void));
+                }
+                self.expecting_element = false;
+                Ok(None)
+            }
+            Symbol::TypeVoid => error(
+                "`void` cannot be an element of a tuple",
+                "the `void` keyword is only valid as a return type",
+            ),
+            Symbol::TypeAuto => error(
+                "`auto` cannot be used inside a tuple type",
+                "either write out every element type, or use `auto` for the whole tuple",
+            ),
+            Symbol::RightArrow => {
+                if !self.functions_allowed {
+                    return error(
+                        "only arguments can be functions",
+                        "take the function as an argument, like `fn apply :: f (int -> int) -> x int -> int`",
+                    );
+                }
+                if !outermost {
+                    return error(
+                        "function types can't be nested inside other types",
+                        "take each function as a separate argument",
+                    );
+                }
+                let top = self.stack.last().map_or(0, |t| t.len());
+                if self.expecting_element {
+                    return error(
+                        "expected a type in function type, but found `->`",
+                        "function types look like this: `(int -> str)`",
+                    );
+                }
+                if !self.is_function && top > 1 {
+                    return error(
+                        "found a `->` in a tuple type",
+                        "tuple types look like `(int, str)`, and function types like `(int -> str)`",
+                    );
+                }
+                if self.stack.last().and_then(|t| t.last()) == Some(&PrimitiveDataType::Void.into())
+                {
+                    return error(
+                        "`void` can only be the return type of a function type",
+                        "functions without arguments can't be passed yet",
+                    );
+                }
+                self.is_function = true;
+                self.expecting_element = true;
+                Ok(None)
+            }
+            Symbol::DoubleColon => {
+                if !self.is_function || !outermost || self.expecting_element {
+                    return error(
+                        "`::` can only follow the return type of a function type",
+                        "function types look like this: `(int -> int :: P)`",
+                    );
+                }
+                self.permissions = Some(String::new());
+                Ok(None)
+            }
+            Symbol::Comma => {
+                if self.is_function && outermost {
+                    return error(
+                        "found a `,` in a function type",
+                        "separate the argument types of a function type with `->`, like `(int -> str -> bool)`",
+                    );
+                }
+                if self.expecting_element {
+                    return error(
+                        "expected a type in tuple type, but found `,`",
+                        "tuple types look like this: `(int, str)`",
+                    );
+                }
+                self.expecting_element = true;
+                Ok(None)
+            }
+            Symbol::ParenClose => {
+                if self.expecting_element {
+                    return error(
+                        "expected a type in tuple type, but found `)`",
+                        "tuple types look like this: `(int, str)`",
+                    );
+                }
+                if self.is_function && outermost {
+                    return Ok(Some(self.finish_function()));
+                }
+                let elements = self.stack.pop().unwrap_or_default();
+                if elements.len() < 2 {
+                    return error(
+                        "tuple types must have at least two elements",
+                        "remove the parentheses to use a single value instead",
+                    );
+                }
+                let tuple = DataType::Tuple(elements);
+                match self.stack.last_mut() {
+                    Some(parent) => {
+                        parent.push(tuple);
+                        Ok(None)
+                    }
+                    None => Ok(Some(tuple)),
+                }
+            }
+            _ => match PrimitiveDataType::from_symbol(next.symbol) {
+                Some(p) => {
+                    if let Some(top) = self.stack.last_mut() {
+                        top.push(DataType::Primitive(p));
+                    }
+                    self.expecting_element = false;
+                    Ok(None)
+                }
+                None => error(
+                    &format!("expected a type in tuple type, but found `{}`", next.text),
+                    "tuple types look like this: `(int, str)`",
+                ),
+            },
+        }
+    }
+
+    /// The function type made of the outermost elements: the last one is the return type
+    fn finish_function(&mut self) -> DataType {
+        let mut elements = self.stack.pop().unwrap_or_default();
+        let return_type = elements
+            .pop()
+            .unwrap_or(DataType::Primitive(PrimitiveDataType::Void));
+        DataType::Function(Box::new(FunctionType {
+            args: elements,
+            return_type,
+            permissions: self.permissions.take(),
+        }))
+    }
+}
+
 // -------------------- Grammar: Functions --------------------
 
 #[derive(Debug)]
@@ -186,6 +555,8 @@ enum StagesFunction {
     Initialized,
     NameProcessed,
     SeekingArguments,
+    ParsingArgumentTuple,
+    ParsingReturnTuple,
     SeekingBracket,
     SeekingNewLine,
 }
@@ -196,7 +567,7 @@ enum StagesFunction {
 ///
 ///     0: Initialized
 ///     1: Name processed, seeking :: or {
-///     2: :: processed, seeking arguments
+///     2: :: processed, seeking arguments (tuple types are handed off to a `TupleTypeParser`)
 ///     3: arguments complete, seeking {
 #[derive(Debug)]
 pub struct GrammarFunctionDeclaration {
@@ -204,9 +575,12 @@ pub struct GrammarFunctionDeclaration {
     done: bool,
     stage: StagesFunction,
     last_symbol: Symbol,
+    tuple_parser: Option<TupleTypeParser>,
     pub fn_name: String,
     pub arguments: Vec<Variable>,
-    pub return_type: PrimitiveDataType,
+    /// Where each argument's name is on the declaration line
+    pub argument_words: Vec<usize>,
+    pub return_type: DataType,
 }
 
 impl GrammarFunctionDeclaration {
@@ -216,9 +590,11 @@ impl GrammarFunctionDeclaration {
             done: false,
             stage: StagesFunction::Initialized,
             last_symbol: Symbol::FunctionDeclare,
+            tuple_parser: None,
             fn_name: "undefined".to_string(),
             arguments: Vec::<Variable>::new(),
-            return_type: PrimitiveDataType::Void,
+            argument_words: Vec::new(),
+            return_type: DataType::Primitive(PrimitiveDataType::Void),
         }
     }
 
@@ -279,27 +655,33 @@ impl GrammarFunctionDeclaration {
                         // If we receive a type after :: or ->, it implies that is the return type and there are no arguments
                         Symbol::TypeBool => {
                             self.stage = StagesFunction::SeekingBracket;
-                            self.return_type = PrimitiveDataType::Bool;
+                            self.return_type = DataType::Primitive(PrimitiveDataType::Bool);
                         }
                         Symbol::TypeInt => {
                             self.stage = StagesFunction::SeekingBracket;
-                            self.return_type = PrimitiveDataType::Int;
+                            self.return_type = DataType::Primitive(PrimitiveDataType::Int);
                         }
                         Symbol::TypeStr => {
                             self.stage = StagesFunction::SeekingBracket;
-                            self.return_type = PrimitiveDataType::Str;
+                            self.return_type = DataType::Primitive(PrimitiveDataType::Str);
                         }
                         Symbol::TypeVoid => {
                             self.stage = StagesFunction::SeekingBracket;
-                            self.return_type = PrimitiveDataType::Void;
+                            self.return_type = DataType::Primitive(PrimitiveDataType::Void);
+                        }
+                        // An opening parenthesis implies a tuple return type
+                        Symbol::ParenOpen => {
+                            self.stage = StagesFunction::ParsingReturnTuple;
+                            self.tuple_parser = Some(TupleTypeParser::new());
                         }
                         // A value here implies the argument name
                         Symbol::Value => {
                             self.arguments.push(Variable {
                                 name: next.text.to_string(),
-                                data_type: PrimitiveDataType::Void,
+                                data_type: DataType::Primitive(PrimitiveDataType::Void),
                                 value: None,
                             });
+                            self.argument_words.push(next.word);
                         }
                         _ => {
                             self.is_valid = false;
@@ -314,19 +696,23 @@ impl GrammarFunctionDeclaration {
                             self.arguments
                                 .last_mut()
                                 .expect("expected argument to exist")
-                                .data_type = PrimitiveDataType::Bool;
+                                .data_type = DataType::Primitive(PrimitiveDataType::Bool);
                         }
                         Symbol::TypeInt => {
                             self.arguments
                                 .last_mut()
                                 .expect("expected argument to exist")
-                                .data_type = PrimitiveDataType::Int;
+                                .data_type = DataType::Primitive(PrimitiveDataType::Int);
                         }
                         Symbol::TypeStr => {
                             self.arguments
                                 .last_mut()
                                 .expect("expected argument to exist")
-                                .data_type = PrimitiveDataType::Str;
+                                .data_type = DataType::Primitive(PrimitiveDataType::Str);
+                        }
+                        Symbol::ParenOpen => {
+                            self.stage = StagesFunction::ParsingArgumentTuple;
+                            self.tuple_parser = Some(TupleTypeParser::for_argument());
                         }
                         Symbol::TypeVoid => {
                             self.is_valid = false;
@@ -366,6 +752,7 @@ impl GrammarFunctionDeclaration {
                 } else if self.last_symbol == Symbol::TypeBool
                     || self.last_symbol == Symbol::TypeInt
                     || self.last_symbol == Symbol::TypeStr
+                    || self.last_symbol == Symbol::ParenClose
                 {
                     // We just received an argument type, so we need an arrow
                     if next.symbol != Symbol::RightArrow {
@@ -387,6 +774,33 @@ impl GrammarFunctionDeclaration {
                     }
                 }
             }
+            StagesFunction::ParsingArgumentTuple | StagesFunction::ParsingReturnTuple => {
+                let outcome = match self.tuple_parser.as_mut() {
+                    Some(parser) => parser.step(next),
+                    None => Ok(None),
+                };
+                match outcome {
+                    Ok(Some(tuple)) => {
+                        if matches!(self.stage, StagesFunction::ParsingReturnTuple) {
+                            self.return_type = tuple;
+                            self.stage = StagesFunction::SeekingBracket;
+                        } else {
+                            self.arguments
+                                .last_mut()
+                                .expect("expected argument to exist")
+                                .data_type = tuple;
+                            self.stage = StagesFunction::SeekingArguments;
+                        }
+                        self.tuple_parser = None;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        self.is_valid = false;
+                        self.done = true;
+                        error_message = Some(e);
+                    }
+                }
+            }
             StagesFunction::SeekingBracket => match next.symbol {
                 Symbol::BraceOpen => {
                     self.stage = StagesFunction::SeekingNewLine;
@@ -490,6 +904,7 @@ impl GrammarProperty {
             StagesAnnotation::ExpectValues => match next.symbol {
                 Symbol::Value => match next.text.as_str() {
                     "Pure" => self.p_list.push(Properties::Pure),
+                    "Deterministic" => self.p_list.push(Properties::Deterministic),
                     "Public" => self.p_list.push(Properties::Public),
                     "Export" => self.p_list.push(Properties::Export),
                     _ => {
@@ -508,8 +923,8 @@ impl GrammarProperty {
                     if self.p_list.is_empty() {
                         error_message = Some(CompilerProblem::new(
                             ProblemClass::Warning,
-                            &format!("empty property list"),
-                            &format!("either remove the property list or add properties"),
+                            "empty property list",
+                            "either remove the property list or add properties",
                             next.line,
                             next.word,
                         ));
@@ -575,8 +990,8 @@ impl GrammarPermissions {
                     if self.p_list.is_empty() {
                         error_message = Some(CompilerProblem::new(
                             ProblemClass::Warning,
-                            &format!("empty permission list"),
-                            &format!("either remove the permission list or add properties"),
+                            "empty permission list",
+                            "either remove the permission list or add properties",
                             next.line,
                             next.word,
                         ));
@@ -601,10 +1016,318 @@ impl GrammarPermissions {
     }
 }
 
+// -------------------- Grammar: Custom Permission --------------------
+
+#[derive(Debug)]
+enum StagesCustomPermission {
+    Initialized,
+    SeekingImplies,
+    ExpectValues,
+}
+
+/// Grammar for declaring a permission, like `permission FileIO :: ReadFile WriteFile`
+///
+/// The permissions after `::` are the ones this permission implies (and is optional)
+#[derive(Debug)]
+pub struct GrammarCustomPermission {
+    is_valid: bool,
+    done: bool,
+    stage: StagesCustomPermission,
+    pub name: String,
+    pub implies: Vec<Permissions>,
+}
+
+impl GrammarCustomPermission {
+    fn new() -> GrammarCustomPermission {
+        GrammarCustomPermission {
+            is_valid: true,
+            done: false,
+            stage: StagesCustomPermission::Initialized,
+            name: String::new(),
+            implies: Vec::new(),
+        }
+    }
+
+    fn step(&mut self, next: &Token) -> Option<CompilerProblem> {
+        if self.done {
+            return None;
+        }
+        let mut error_message: Option<CompilerProblem> = None;
+        let fail = |message: String| {
+            Some(CompilerProblem::new(
+                ProblemClass::Error,
+                &message,
+                "a permission declaration should look like this: `permission FileIO :: ReadFile WriteFile`",
+                next.line,
+                next.word,
+            ))
+        };
+        match self.stage {
+            StagesCustomPermission::Initialized => match next.symbol {
+                Symbol::Value if is_identifier(&next.text) => {
+                    self.name = next.text.clone();
+                    self.stage = StagesCustomPermission::SeekingImplies;
+                }
+                _ => {
+                    error_message = fail(format!(
+                        "expected a permission name, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+            StagesCustomPermission::SeekingImplies => match next.symbol {
+                Symbol::DoubleColon => self.stage = StagesCustomPermission::ExpectValues,
+                Symbol::Newline => self.done = true,
+                _ => {
+                    error_message = fail(format!(
+                        "expected `::` or a new line after the permission name, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+            StagesCustomPermission::ExpectValues => match next.symbol {
+                Symbol::Value if next.text == self.name => {
+                    error_message = fail(format!("`{}` can't imply itself", self.name));
+                }
+                Symbol::Value => self.implies.push(Permissions::from_str(&next.text)),
+                Symbol::Newline if self.implies.is_empty() => {
+                    error_message = fail(format!(
+                        "`{}` is declared to imply other permissions, but none are listed",
+                        self.name
+                    ));
+                }
+                Symbol::Newline => self.done = true,
+                _ => {
+                    error_message = fail(format!(
+                        "expected a permission name or a new line, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+        }
+        if error_message.is_some() {
+            self.is_valid = false;
+            self.done = true;
+        }
+        error_message
+    }
+}
+
+// -------------------- Grammar: Trusted Permissions --------------------
+
+#[derive(Debug)]
+enum StagesTrusted {
+    Initialized,
+    ExpectValues,
+    Justification,
+    SeekingNewline,
+}
+
+/// Grammar for a function absorbing permissions, like `#Trusted :: ReadFile "only reads the config file at startup"`
+///
+/// The justification is a string literal, which the lexer splits at spaces, so its words are collected back together here
+#[derive(Debug)]
+pub struct GrammarTrusted {
+    is_valid: bool,
+    done: bool,
+    stage: StagesTrusted,
+    pub permissions: Vec<Permissions>,
+    pub justification: String,
+    /// The built-in permissions the trusted ones cover, which the function may use at runtime even inside a caller's `with_permissions` block (filled in once custom permissions are known)
+    pub runtime: Vec<Permissions>,
+}
+
+impl GrammarTrusted {
+    fn new() -> GrammarTrusted {
+        GrammarTrusted {
+            is_valid: true,
+            done: false,
+            stage: StagesTrusted::Initialized,
+            permissions: Vec::new(),
+            justification: String::new(),
+            runtime: Vec::new(),
+        }
+    }
+
+    /// Add a word of the justification, finishing it at the closing quote
+    fn push_justification(&mut self, word: &str) {
+        if !self.justification.is_empty() {
+            self.justification.push(' ');
+        }
+        self.justification.push_str(word);
+        if self.justification.len() > 1 && self.justification.ends_with('"') {
+            self.justification = self.justification[1..self.justification.len() - 1]
+                .trim()
+                .to_string();
+            self.stage = StagesTrusted::SeekingNewline;
+        }
+    }
+
+    fn step(&mut self, next: &Token) -> Option<CompilerProblem> {
+        if self.done {
+            return None;
+        }
+        let mut error_message: Option<CompilerProblem> = None;
+        let fail = |message: String| {
+            Some(CompilerProblem::new(
+                ProblemClass::Error,
+                &message,
+                "a trusted permission should look like this: `#Trusted :: ReadFile \"only reads the config file at startup\"`",
+                next.line,
+                next.word,
+            ))
+        };
+        match self.stage {
+            StagesTrusted::Initialized => match next.symbol {
+                Symbol::DoubleColon => self.stage = StagesTrusted::ExpectValues,
+                _ => {
+                    error_message = fail(format!(
+                        "expected a `::` after `#Trusted`, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+            StagesTrusted::ExpectValues => match next.symbol {
+                Symbol::Value if next.text.starts_with('"') && self.permissions.is_empty() => {
+                    error_message = fail(
+                        "expected the trusted permissions before the justification".to_string(),
+                    );
+                }
+                Symbol::Value if next.text.starts_with('"') => {
+                    self.stage = StagesTrusted::Justification;
+                    self.push_justification(&next.text);
+                }
+                Symbol::Value => self.permissions.push(Permissions::from_str(&next.text)),
+                Symbol::Newline if self.permissions.is_empty() => {
+                    error_message = fail("`#Trusted` needs at least one permission".to_string());
+                }
+                Symbol::Newline => {
+                    error_message = fail(
+                        "`#Trusted` needs a justification, saying why hiding these permissions from callers is safe".to_string(),
+                    );
+                }
+                _ => {
+                    error_message = fail(format!(
+                        "expected a permission name or a justification, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+            StagesTrusted::Justification => match next.symbol {
+                Symbol::Newline => {
+                    error_message =
+                        fail("the justification is missing its closing `\"`".to_string());
+                }
+                _ => self.push_justification(&next.text),
+            },
+            StagesTrusted::SeekingNewline => match next.symbol {
+                Symbol::Newline if self.justification.is_empty() => {
+                    error_message = fail("the justification for `#Trusted` is empty".to_string());
+                }
+                Symbol::Newline => self.done = true,
+                _ => {
+                    error_message = fail(format!(
+                        "expected a new line after the justification, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+        }
+        if error_message.is_some() {
+            self.is_valid = false;
+            self.done = true;
+        }
+        error_message
+    }
+}
+
+// -------------------- Grammar: Permission Attenuation --------------------
+
+#[derive(Debug)]
+enum StagesAttenuation {
+    Initialized,
+    ExpectValues,
+    SeekingBrace,
+}
+
+/// Grammar for opening a block that restricts the permissions of the calls inside it, like `with_permissions [ReadFile] {`
+#[derive(Debug)]
+pub struct GrammarAttenuation {
+    is_valid: bool,
+    done: bool,
+    stage: StagesAttenuation,
+    pub permissions: Vec<Permissions>,
+    /// The built-in permissions the listed ones cover, which is what the block allows at runtime (filled in once custom permissions are known)
+    pub runtime: Vec<Permissions>,
+}
+
+impl GrammarAttenuation {
+    fn new() -> GrammarAttenuation {
+        GrammarAttenuation {
+            is_valid: true,
+            done: false,
+            stage: StagesAttenuation::Initialized,
+            permissions: Vec::new(),
+            runtime: Vec::new(),
+        }
+    }
+
+    fn step(&mut self, next: &Token) -> Option<CompilerProblem> {
+        if self.done {
+            return None;
+        }
+        let mut error_message: Option<CompilerProblem> = None;
+        let fail = |message: String| {
+            Some(CompilerProblem::new(
+                ProblemClass::Error,
+                &message,
+                "a restricted block should look like this: `with_permissions [ReadFile WriteFile] {`",
+                next.line,
+                next.word,
+            ))
+        };
+        match self.stage {
+            StagesAttenuation::Initialized => match next.symbol {
+                Symbol::BracketOpen => self.stage = StagesAttenuation::ExpectValues,
+                _ => {
+                    error_message = fail(format!(
+                        "expected a `[` before the permissions, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+            StagesAttenuation::ExpectValues => match next.symbol {
+                Symbol::Value => self.permissions.push(Permissions::from_str(&next.text)),
+                Symbol::BracketClose => self.stage = StagesAttenuation::SeekingBrace,
+                _ => {
+                    error_message = fail(format!(
+                        "expected a permission name or a `]`, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+            StagesAttenuation::SeekingBrace => match next.symbol {
+                Symbol::BraceOpen => self.done = true,
+                _ => {
+                    error_message = fail(format!(
+                        "expected a `{{` after the permissions, but found `{}`",
+                        next.text
+                    ));
+                }
+            },
+        }
+        if error_message.is_some() {
+            self.is_valid = false;
+            self.done = true;
+        }
+        error_message
+    }
+}
+
 // -------------------- Grammar: Variable Assignment --------------------
 
 #[derive(Debug, PartialEq, Eq)]
-enum AssignmentTypes {
+pub enum AssignmentTypes {
     Initialize, // let x = ...
     Mutate,     // set x = ...
 }
@@ -612,9 +1335,11 @@ enum AssignmentTypes {
 #[derive(Debug)]
 enum StagesVariableAssignment {
     FindingName,
+    FindingTupleNames,
     GettingIndexValue,
     DeclaringType,
     SeekingTypeName,
+    ParsingTupleType,
     CheckingMutability,
 }
 
@@ -623,19 +1348,28 @@ pub struct GrammarVariableAssignments {
     is_valid: bool,
     done: bool,
     stage: StagesVariableAssignment,
-    assignment_type: AssignmentTypes,
-    type_provided: bool,
-    data_type: PrimitiveDataType,
-    name: String,
-    mutable: bool,
+    pub assignment_type: AssignmentTypes,
+    pub type_provided: bool,
+    /// Set once type inference has worked out `data_type` for a binding without a type
+    pub inferred: bool,
+    pub data_type: DataType,
+    /// For destructuring bindings this is the whole pattern, e.g. `(q, r)`
+    pub name: String,
+    pub mutable: bool,
     index_text: Option<String>,
+    /// The index in `set data @ 0..2 = ...`, parsed into `index` once the function table is known
+    pub index_tokens: Vec<Token>,
+    pub index: Option<Object>,
+    /// The names bound by a destructuring `let (q, r) = ...`
+    pub destructure: Option<Vec<String>>,
+    tuple_parser: Option<TupleTypeParser>,
 }
 
 impl GrammarVariableAssignments {
     fn new(symbol: Symbol) -> GrammarVariableAssignments {
         let this_type = match symbol {
             Symbol::Let => AssignmentTypes::Initialize,
-            Symbol::Mut => AssignmentTypes::Mutate,
+            Symbol::Set | Symbol::Mut => AssignmentTypes::Mutate,
             _ => panic!("internal compiler error: received illegal symbol while initializing GrammarVariableAssignments -- please file a bug report.")
         };
         GrammarVariableAssignments {
@@ -644,11 +1378,37 @@ impl GrammarVariableAssignments {
             stage: StagesVariableAssignment::FindingName,
             assignment_type: this_type,
             type_provided: false,
-            data_type: PrimitiveDataType::Void,
+            inferred: false,
+            data_type: DataType::Primitive(PrimitiveDataType::Void),
             name: "unknown".to_string(),
             mutable: false,
             index_text: None,
+            index_tokens: Vec::new(),
+            index: None,
+            destructure: None,
+            tuple_parser: None,
+        }
+    }
+
+    /// A destructuring pattern must bind exactly one name per element of its declared type
+    fn check_destructure_arity(&self, next: &Token) -> Option<CompilerProblem> {
+        let names = self.destructure.as_ref()?;
+        if names.len() == self.data_type.arity() {
+            return None;
         }
+        Some(CompilerProblem::new(
+            ProblemClass::Error,
+            &format!(
+                "`{}` binds {} names, but its type `{}` has {} element(s)",
+                self.name,
+                names.len(),
+                self.data_type,
+                self.data_type.arity()
+            ),
+            "bind exactly one name per element of the tuple",
+            next.line,
+            next.word,
+        ))
     }
 
     fn step(&mut self, next: &Token) -> Option<CompilerProblem> {
@@ -674,6 +1434,24 @@ impl GrammarVariableAssignments {
                         self.done = true;
                     }
                 }
+                // An opening parenthesis starts a destructuring pattern like `(q, r)`
+                Symbol::ParenOpen => match self.assignment_type {
+                    AssignmentTypes::Initialize => {
+                        self.destructure = Some(Vec::new());
+                        self.stage = StagesVariableAssignment::FindingTupleNames;
+                    }
+                    AssignmentTypes::Mutate => {
+                        error_message = Some(CompilerProblem::new(
+                            ProblemClass::Error,
+                            "cannot destructure a tuple when mutating a variable",
+                            "destructuring is only supported when declaring new variables with `let`",
+                            next.line,
+                            next.word,
+                        ));
+                        self.is_valid = false;
+                        self.done = true;
+                    }
+                },
                 _ => {
                     error_message = Some(
                         CompilerProblem::new(ProblemClass::Error, &format!("expected a variable name, but found a system reserved keyword instead (found `{}`", next.text), "try using a different variable name", next.line, next.word)
@@ -682,10 +1460,58 @@ impl GrammarVariableAssignments {
                     self.done = true;
                 }
             },
+            StagesVariableAssignment::FindingTupleNames => {
+                let names = self.destructure.get_or_insert_with(Vec::new);
+                match next.symbol {
+                    Symbol::Value if next.text.is_ascii() => names.push(next.text.to_string()),
+                    Symbol::Comma => {}
+                    Symbol::ParenClose if names.len() >= 2 => {
+                        self.name = format!("({})", names.join(", "));
+                        self.stage = StagesVariableAssignment::DeclaringType;
+                    }
+                    Symbol::ParenClose => {
+                        error_message = Some(CompilerProblem::new(
+                            ProblemClass::Error,
+                            "a destructuring pattern must bind at least two names",
+                            "remove the parentheses to bind a single name",
+                            next.line,
+                            next.word,
+                        ));
+                        self.is_valid = false;
+                        self.done = true;
+                    }
+                    _ => {
+                        error_message = Some(CompilerProblem::new(
+                            ProblemClass::Error,
+                            &format!(
+                                "expected a variable name in destructuring pattern, but found `{}`",
+                                next.text
+                            ),
+                            "destructuring patterns look like this: `let (q, r) :: (int, int) = ...`",
+                            next.line,
+                            next.word,
+                        ));
+                        self.is_valid = false;
+                        self.done = true;
+                    }
+                }
+            }
             StagesVariableAssignment::GettingIndexValue => match next.symbol {
-                Symbol::Value => {
-                    self.index_text = Some(next.text.to_string());
-                    self.stage = StagesVariableAssignment::DeclaringType;
+                Symbol::EqualSign | Symbol::DoubleColon if !self.index_tokens.is_empty() => {
+                    self.index_text = Some(
+                        self.index_tokens
+                            .iter()
+                            .map(|t| t.text.as_str())
+                            .collect::<String>(),
+                    );
+                    if next.symbol == Symbol::EqualSign {
+                        self.done = true;
+                    } else {
+                        self.stage = StagesVariableAssignment::SeekingTypeName;
+                    }
+                }
+                _ if VALID_EXPRESSION_TOKENS.contains(&next.symbol) => {
+                    self.index_tokens.push(next.clone());
                 }
                 _ => {
                     error_message = Some(
@@ -716,21 +1542,19 @@ impl GrammarVariableAssignments {
                 Symbol::EqualSign => {
                     self.type_provided = false;
                     self.done = true;
-                    let keyword = if self.assignment_type == AssignmentTypes::Initialize {
-                        "let"
-                    } else {
-                        "set"
-                    };
-                    error_message = Some(CompilerProblem::new(
-                        ProblemClass::Lint,
-                        &format!(
-                            "use `auto` with `{}` to be explicit about your type inference",
-                            self.name
-                        ),
-                        &format!("try this: `{keyword} {} :: auto = ...`", self.name),
-                        next.line,
-                        next.word,
-                    ));
+                    // `set` reuses the type of the existing variable, so only `let` has a type to leave out
+                    if self.assignment_type == AssignmentTypes::Initialize {
+                        error_message = Some(CompilerProblem::new(
+                            ProblemClass::Lint,
+                            &format!(
+                                "use `auto` with `{}` to be explicit about your type inference",
+                                self.name
+                            ),
+                            &format!("try this: `let {} :: auto = ...`", self.name),
+                            next.line,
+                            next.word,
+                        ));
+                    }
                 }
                 _ => {
                     error_message = Some(CompilerProblem::new(
@@ -751,7 +1575,6 @@ impl GrammarVariableAssignments {
                 if next.symbol == Symbol::Mut {
                     self.type_provided = false;
                     self.mutable = true;
-                    self.done = true;
                     let keyword = if self.assignment_type == AssignmentTypes::Initialize {
                         "let"
                     } else {
@@ -771,13 +1594,21 @@ impl GrammarVariableAssignments {
                 match PrimitiveDataType::from_symbol(next.symbol) {
                     Some(d) => {
                         self.type_provided = true;
-                        self.data_type = d;
+                        self.data_type = DataType::Primitive(d);
                         self.stage = StagesVariableAssignment::CheckingMutability;
+                        if let Some(e) = self.check_destructure_arity(next) {
+                            error_message = Some(e);
+                            self.is_valid = false;
+                            self.done = true;
+                        }
                     }
                     None => {
-                        if next.symbol == Symbol::TypeAuto || next.symbol == Symbol::Mut {
+                        if next.symbol == Symbol::ParenOpen {
+                            self.tuple_parser = Some(TupleTypeParser::new());
+                            self.stage = StagesVariableAssignment::ParsingTupleType;
+                        } else if next.symbol == Symbol::TypeAuto || next.symbol == Symbol::Mut {
                             self.type_provided = false;
-                            self.data_type = PrimitiveDataType::Void;
+                            self.data_type = DataType::Primitive(PrimitiveDataType::Void);
                             self.stage = StagesVariableAssignment::CheckingMutability;
                         } else {
                             error_message = Some(CompilerProblem::new(
@@ -793,10 +1624,34 @@ impl GrammarVariableAssignments {
                     }
                 }
             }
+            StagesVariableAssignment::ParsingTupleType => {
+                let outcome = match self.tuple_parser.as_mut() {
+                    Some(parser) => parser.step(next),
+                    None => Ok(None),
+                };
+                match outcome {
+                    Ok(Some(tuple)) => {
+                        self.type_provided = true;
+                        self.data_type = tuple;
+                        self.tuple_parser = None;
+                        self.stage = StagesVariableAssignment::CheckingMutability;
+                        if let Some(e) = self.check_destructure_arity(next) {
+                            error_message = Some(e);
+                            self.is_valid = false;
+                            self.done = true;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        error_message = Some(e);
+                        self.is_valid = false;
+                        self.done = true;
+                    }
+                }
+            }
             StagesVariableAssignment::CheckingMutability => match next.symbol {
-                Symbol::Mut => {
+                Symbol::Mut if !self.mutable => {
                     self.mutable = true;
-                    self.done = true;
                 }
                 Symbol::EqualSign => self.done = true,
                 _ => {
@@ -867,7 +1722,7 @@ mod tests {
 
     #[test]
     fn declare_import_1() {
-        let mut gi = GrammarImports::new();
+        let mut gi = GrammarImports::new(Symbol::Import);
         let line: &str = "import a b from c";
         let tokens = lex(line);
         for t in tokens.into_iter().skip(1) {
@@ -876,16 +1731,14 @@ mod tests {
         assert!(gi.done);
         assert!(gi.is_valid);
         assert_eq!(gi.file, "c".to_string());
-        assert!(gi.arguments.is_some());
-        if let Some(args) = gi.arguments.as_ref() {
-            assert_eq!(args[0].text, "a".to_string());
-            assert_eq!(args[1].text, "b".to_string());
-        }
+        assert!(!gi.is_whole_module());
+        assert_eq!(gi.items[0].name.text, "a".to_string());
+        assert_eq!(gi.items[1].name.text, "b".to_string());
     }
 
     #[test]
     fn declare_import_2() {
-        let mut gi = GrammarImports::new();
+        let mut gi = GrammarImports::new(Symbol::Import);
         let line: &str = "import this.c";
         let tokens = lex(line);
         for t in tokens.into_iter().skip(1) {
@@ -894,7 +1747,144 @@ mod tests {
         assert!(gi.done);
         assert!(gi.is_valid);
         assert_eq!(gi.file, "this.c".to_string());
-        assert!(gi.arguments.is_none());
+        assert!(gi.is_whole_module());
+    }
+
+    #[test]
+    fn declare_import_single_segment() {
+        let mut gi = GrammarImports::new(Symbol::Import);
+        for t in lex("import geometry\nfn main {").into_iter().skip(1) {
+            assert!(gi.step(&t).is_none());
+        }
+        assert!(gi.done);
+        assert_eq!(gi.file, "geometry".to_string());
+        assert!(gi.is_whole_module());
+        // Without `from`, a list of items is reported once, at the end of the line
+        let mut gi = GrammarImports::new(Symbol::Import);
+        let errors: Vec<CompilerProblem> = lex("import a b\nfn main {\n}")
+            .into_iter()
+            .skip(1)
+            .filter_map(|t| gi.step(&t))
+            .collect();
+        assert!(gi.done);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].word_index, 3);
+    }
+
+    #[test]
+    fn declare_import_alias() {
+        let mut gi = GrammarImports::new(Symbol::Import);
+        let tokens = lex("import read_file as rf write_file from std.files");
+        for t in tokens.into_iter().skip(1) {
+            assert!(gi.step(&t).is_none());
+        }
+        assert!(gi.done);
+        assert_eq!(gi.items.len(), 2);
+        assert_eq!(gi.items[0].binding().text, "rf");
+        assert_eq!(gi.items[0].name.text, "read_file");
+        assert_eq!(gi.items[1].binding().text, "write_file");
+    }
+
+    #[test]
+    fn declare_import_wildcard() {
+        let mut gi = GrammarImports::new(Symbol::Import);
+        let tokens = lex("import * from std.math");
+        for t in tokens.into_iter().skip(1) {
+            assert!(gi.step(&t).is_none());
+        }
+        assert!(gi.done);
+        assert!(gi.wildcard);
+        assert!(gi.items.is_empty());
+        // `*` can't be mixed with named items
+        let mut gi = GrammarImports::new(Symbol::Import);
+        let errors: Vec<CompilerProblem> = lex("import * sqrt from std.math")
+            .into_iter()
+            .skip(1)
+            .filter_map(|t| gi.step(&t))
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].word_index, 2);
+    }
+
+    #[test]
+    fn declare_reexport() {
+        let mut gi = GrammarImports::new(Symbol::Export);
+        let tokens = lex("export area from geometry.shapes");
+        for t in tokens.into_iter().skip(1) {
+            assert!(gi.step(&t).is_none());
+        }
+        assert!(gi.done);
+        assert!(gi.reexport);
+        let mut gi = GrammarImports::new(Symbol::Export);
+        let errors: Vec<CompilerProblem> = lex("export geometry.shapes")
+            .into_iter()
+            .skip(1)
+            .filter_map(|t| gi.step(&t))
+            .collect();
+        assert_eq!(errors[0].message, "a whole module cannot be re-exported");
+    }
+
+    #[test]
+    fn declare_custom_permission() {
+        let mut gp = GrammarCustomPermission::new();
+        for t in lex("permission FileIO :: ReadFile WriteFile\n}")
+            .into_iter()
+            .skip(1)
+        {
+            assert!(gp.step(&t).is_none());
+        }
+        assert!(gp.done);
+        assert_eq!(gp.name, "FileIO");
+        assert_eq!(
+            gp.implies,
+            vec![Permissions::ReadFile, Permissions::WriteFile]
+        );
+        let mut gp = GrammarCustomPermission::new();
+        for t in lex("permission Telemetry\n}").into_iter().skip(1) {
+            assert!(gp.step(&t).is_none());
+        }
+        assert!(gp.done && gp.implies.is_empty());
+        let mut gp = GrammarCustomPermission::new();
+        let errors: Vec<CompilerProblem> = lex("permission Loop :: Loop\n")
+            .into_iter()
+            .skip(1)
+            .filter_map(|t| gp.step(&t))
+            .collect();
+        assert_eq!(errors[0].message, "`Loop` can't imply itself");
+    }
+
+    #[test]
+    fn open_permission_block() {
+        let mut ga = GrammarAttenuation::new();
+        for t in lex("with_permissions [ReadFile WriteFile(\"/tmp/*\")] {")
+            .into_iter()
+            .skip(1)
+        {
+            assert!(ga.step(&t).is_none());
+        }
+        assert!(ga.done);
+        assert_eq!(
+            ga.permissions,
+            vec![
+                Permissions::ReadFile,
+                Permissions::from_str("WriteFile(\"/tmp/*\")")
+            ]
+        );
+        let mut ga = GrammarAttenuation::new();
+        for t in lex("with_permissions [] {").into_iter().skip(1) {
+            assert!(ga.step(&t).is_none());
+        }
+        assert!(ga.done && ga.permissions.is_empty());
+        let mut ga = GrammarAttenuation::new();
+        let errors: Vec<CompilerProblem> = lex("with_permissions ReadFile {")
+            .into_iter()
+            .skip(1)
+            .filter_map(|t| ga.step(&t))
+            .collect();
+        assert_eq!(
+            errors[0].message,
+            "expected a `[` before the permissions, but found `ReadFile`"
+        );
     }
 
     #[test]
@@ -979,7 +1969,7 @@ mod tests {
         // assert!(gv.done); // this will fail b/c no newline, but this is okay
         assert!(gv.is_valid);
         assert_eq!(gv.data_type, PrimitiveDataType::Int);
-        assert_eq!(gv.mutable, false);
+        assert!(!gv.mutable);
         assert!(gv.type_provided);
         assert_eq!(gv.name, "a".to_string());
     }
@@ -996,7 +1986,7 @@ mod tests {
         // assert!(gv.done); // this will fail b/c no newline, but this is okay
         assert!(gv.is_valid);
         assert_eq!(gv.data_type, PrimitiveDataType::Str);
-        assert_eq!(gv.mutable, true);
+        assert!(gv.mutable);
         assert!(gv.type_provided);
         assert_eq!(gv.name, "a".to_string());
     }
@@ -1012,8 +2002,8 @@ mod tests {
         println!("{:#?}", gv);
         assert!(gv.is_valid);
         assert_eq!(gv.data_type, PrimitiveDataType::Void); // Void is a filler type here
-        assert_eq!(gv.mutable, true);
-        assert_eq!(gv.type_provided, false);
+        assert!(gv.mutable);
+        assert!(!gv.type_provided);
         assert_eq!(gv.name, "a".to_string());
     }
 
@@ -1028,8 +2018,8 @@ mod tests {
         println!("{:#?}", gv);
         assert!(gv.is_valid);
         assert_eq!(gv.data_type, PrimitiveDataType::Void);
-        assert_eq!(gv.mutable, true);
-        assert_eq!(gv.type_provided, false);
+        assert!(gv.mutable);
+        assert!(!gv.type_provided);
         assert_eq!(gv.name, "a".to_string());
     }
 
@@ -1059,4 +2049,134 @@ mod tests {
         assert_eq!(gv.index_text.unwrap(), "10".to_string());
         assert_eq!(gv.name, "a".to_string());
     }
+
+    #[test]
+    fn declare_fn_tuple_return() {
+        let mut gfd = GrammarFunctionDeclaration::new();
+        let line: &str = "fn divmod :: a int -> b int -> (int, int) {
+        ";
+        let tokens = lex(line);
+        for t in tokens.into_iter().skip(1) {
+            gfd.step(&t);
+        }
+        assert!(gfd.done);
+        assert!(gfd.is_valid);
+        assert_eq!(gfd.arguments.len(), 2);
+        assert_eq!(
+            gfd.return_type,
+            DataType::Tuple(vec![
+                DataType::Primitive(PrimitiveDataType::Int),
+                DataType::Primitive(PrimitiveDataType::Int)
+            ])
+        );
+    }
+
+    #[test]
+    fn declare_fn_nested_tuple_argument() {
+        let mut gfd = GrammarFunctionDeclaration::new();
+        let line: &str = "fn first :: pair (int, (str, bool)) -> int {
+        ";
+        let tokens = lex(line);
+        for t in tokens.into_iter().skip(1) {
+            gfd.step(&t);
+        }
+        assert!(gfd.done);
+        assert!(gfd.is_valid);
+        assert_eq!(gfd.arguments[0].data_type.to_string(), "(int, (str, bool))");
+        assert_eq!(gfd.return_type, PrimitiveDataType::Int);
+    }
+
+    #[test]
+    fn declare_fn_function_argument() {
+        let mut gfd = GrammarFunctionDeclaration::new();
+        let line: &str = "fn map :: f ((int, int) -> str -> bool :: P) -> x int -> bool {
+        ";
+        for t in lex(line).into_iter().skip(1) {
+            assert_eq!(gfd.step(&t), None);
+        }
+        assert!(gfd.done);
+        assert!(gfd.is_valid);
+        assert_eq!(gfd.arguments.len(), 2);
+        assert_eq!(
+            gfd.arguments[0].data_type.to_string(),
+            "((int, int) -> str -> bool :: P)"
+        );
+        assert_eq!(gfd.arguments[0].data_type.permission_variable(), Some("P"));
+
+        // Functions can't be returned, nested, or mixed up with tuples
+        for (line, message) in [
+            (
+                "fn f :: x int -> (int -> int) {",
+                "only arguments can be functions",
+            ),
+            (
+                "fn f :: g (int, (int -> int)) -> int {",
+                "function types can't be nested inside other types",
+            ),
+            (
+                "fn f :: g (int, str -> int) -> int {",
+                "found a `->` in a tuple type",
+            ),
+            (
+                "fn f :: g (int -> int :: ReadFile) -> int {",
+                "`ReadFile` is a permission, not a permission variable",
+            ),
+        ] {
+            let mut gfd = GrammarFunctionDeclaration::new();
+            let errors: Vec<CompilerProblem> = lex(line)
+                .into_iter()
+                .skip(1)
+                .filter_map(|t| gfd.step(&t))
+                .collect();
+            assert!(!gfd.is_valid);
+            assert_eq!(errors[0].message, message);
+        }
+    }
+
+    #[test]
+    fn declare_variable_destructure() {
+        let mut gv = GrammarVariableAssignments::new(Symbol::Let);
+        let line: &str = "let (q, r) :: (int, int) = divmod 7 2";
+        let tokens = lex(line);
+        for t in tokens.into_iter().skip(1) {
+            gv.step(&t);
+        }
+        assert!(gv.is_valid);
+        assert!(gv.type_provided);
+        assert_eq!(gv.name, "(q, r)".to_string());
+        assert_eq!(gv.destructure, Some(vec!["q".to_string(), "r".to_string()]));
+        assert_eq!(gv.data_type.arity(), 2);
+    }
+
+    #[test]
+    fn declare_variable_destructure_arity_mismatch() {
+        let mut gv = GrammarVariableAssignments::new(Symbol::Let);
+        let line: &str = "let (q, r, s) :: (int, int) = divmod 7 2";
+        let tokens = lex(line);
+        let mut errors: Vec<CompilerProblem> = Vec::new();
+        for t in tokens.into_iter().skip(1) {
+            errors.extend(gv.step(&t));
+        }
+        assert!(!gv.is_valid);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "`(q, r, s)` binds 3 names, but its type `(int, int)` has 2 element(s)"
+        );
+    }
+
+    #[test]
+    fn declare_variable_set_index_range() {
+        let mut gv = GrammarVariableAssignments::new(Symbol::Set);
+        let line: &str = "set a @ 1..=i = \"xyz\"";
+        let tokens = lex(line);
+        for t in tokens.into_iter().skip(1) {
+            gv.step(&t);
+        }
+        assert!(gv.is_valid);
+        assert!(gv.done);
+        assert_eq!(gv.index_text, Some("1..=i".to_string()));
+        assert_eq!(gv.index_tokens.len(), 3);
+        assert_eq!(gv.name, "a".to_string());
+    }
 }
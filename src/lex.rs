@@ -18,10 +18,14 @@ pub enum Symbol {
     OpLte,
     ParenOpen,
     ParenClose,
+    BracketOpen,
+    BracketClose,
     BraceOpen,
     BraceClose,
     Return,
     Import,
+    Export,
+    As,
     From,
     Set,
     Get,
@@ -31,6 +35,9 @@ pub enum Symbol {
     Newline,
     PropertyDeclaration,
     PermissionsDeclaration,
+    PermissionDeclare,
+    TrustedDeclaration,
+    WithPermissions,
     ContractPre,
     ContractPost,
     ContractInvariant,
@@ -43,6 +50,9 @@ pub enum Symbol {
     TypeFloat,
     TypeAuto,
     At,
+    Comma,
+    Range,
+    RangeInclusive,
 }
 
 impl Symbol {
@@ -64,10 +74,14 @@ impl Symbol {
             "<=" => Symbol::OpLte,
             "(" => Symbol::ParenOpen,
             ")" => Symbol::ParenClose,
+            "[" => Symbol::BracketOpen,
+            "]" => Symbol::BracketClose,
             "{" => Symbol::BraceOpen,
             "}" => Symbol::BraceClose,
             "return" => Symbol::Return,
             "import" => Symbol::Import,
+            "export" => Symbol::Export,
+            "as" => Symbol::As,
             "from" => Symbol::From,
             "set" => Symbol::Set,
             "get" => Symbol::Get,
@@ -77,6 +91,8 @@ impl Symbol {
             "\n" => Symbol::Newline,
             "#Properties" => Symbol::PropertyDeclaration,
             "#Permissions" => Symbol::PermissionsDeclaration,
+            "#Trusted" => Symbol::TrustedDeclaration,
+            "with_permissions" => Symbol::WithPermissions,
             "#In" => Symbol::ContractPre,
             "#Out" => Symbol::ContractPost,
             "#Invariant" => Symbol::ContractInvariant,
@@ -89,13 +105,16 @@ impl Symbol {
             "void" => Symbol::TypeVoid,
             "auto" => Symbol::TypeAuto,
             "@" => Symbol::At,
+            "," => Symbol::Comma,
+            ".." => Symbol::Range,
+            "..=" => Symbol::RangeInclusive,
             _ => Symbol::Value,
         }
     }
 }
 
 /// These symbols are banned on the RHS of any expression
-pub const BANNED_RHS_SYMBOLS: [Symbol; 18] = [
+pub const BANNED_RHS_SYMBOLS: [Symbol; 20] = [
     Symbol::FunctionDeclare,
     Symbol::DoubleColon,
     Symbol::Return,
@@ -103,6 +122,8 @@ pub const BANNED_RHS_SYMBOLS: [Symbol; 18] = [
     Symbol::From,
     Symbol::PropertyDeclaration,
     Symbol::PermissionsDeclaration,
+    Symbol::TrustedDeclaration,
+    Symbol::WithPermissions,
     Symbol::ContractPre,
     Symbol::ContractPost,
     Symbol::ContractInvariant,
@@ -116,7 +137,7 @@ pub const BANNED_RHS_SYMBOLS: [Symbol; 18] = [
     Symbol::TypeAuto,
 ];
 
-pub const VALID_EXPRESSION_TOKENS: [Symbol; 13] = [
+pub const VALID_EXPRESSION_TOKENS: [Symbol; 16] = [
     Symbol::Value,
     Symbol::OpPlus,
     Symbol::OpMinus,
@@ -130,6 +151,9 @@ pub const VALID_EXPRESSION_TOKENS: [Symbol; 13] = [
     Symbol::At,
     Symbol::ParenOpen,
     Symbol::ParenClose,
+    Symbol::Comma,
+    Symbol::Range,
+    Symbol::RangeInclusive,
 ];
 
 /// A token is a symbol and its context in the source code
@@ -152,35 +176,136 @@ impl Token {
     }
 }
 
+/// Push a word, splitting it around a range operator first (so `0..=2` becomes `0`, `..=`, `2`)
+fn push_word(tokens: &mut Vec<Token>, word: &str, line: usize, word_index: &mut usize) {
+    let split = match word.find("..") {
+        Some(position) if !word.contains('"') && word.len() > 2 => Some(position),
+        _ => None,
+    };
+    match split {
+        Some(position) => {
+            let operator_length = if word[position..].starts_with("..=") {
+                3
+            } else {
+                2
+            };
+            for part in [
+                &word[..position],
+                &word[position..position + operator_length],
+                &word[position + operator_length..],
+            ] {
+                if !part.is_empty() {
+                    tokens.push(Token::new(part, line, *word_index));
+                    *word_index += 1;
+                }
+            }
+        }
+        None => {
+            tokens.push(Token::new(word, line, *word_index));
+            *word_index += 1;
+        }
+    }
+}
+
+/// Split a line on whitespace, except inside string literals (so `"a b"` stays one word)
+fn split_words(line: &str) -> Vec<&str> {
+    let mut words: Vec<&str> = Vec::new();
+    let mut start = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (position, char) in line.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ' ' | '\t' | '\r' if !in_string => {
+                words.push(&line[start..position]);
+                start = position + 1;
+            }
+            _ => {}
+        }
+    }
+    words.push(&line[start..]);
+    words
+}
+
+/// Where the string literals in a word start and end: before the first `"` and after the last, or `None` without strings
+fn string_bounds(word: &str) -> Option<(usize, usize)> {
+    Some((word.find('"')?, word.rfind('"')? + 1))
+}
+
+/// A name followed by a string in parentheses, with nothing in between
+fn is_scoped_permission(word: &str) -> bool {
+    match word.split_once("(\"") {
+        Some((name, rest)) => {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && rest.ends_with("\")")
+        }
+        None => false,
+    }
+}
+
 /// Process a code string and return a vector of tokens
+///
+/// `permission` is only a keyword at the start of a top-level line, so it can still name variables and functions
 pub fn lex(input: &str) -> Vec<Token> {
     let mut tokens: Vec<Token> = Vec::new();
+    // How many braces are open, to tell top-level lines apart
+    let mut depth: usize = 0;
     // Analyze line by line (delegates issue of deciding what constitutes a new line)
     for (line_index, line) in input.lines().enumerate() {
-        // Split on some standard whitespace
-        let words = line.split(&[' ', '\t', '\r']);
-        // Handle special cases w.r.t. line breaks
-        let mut words_p = words.clone().peekable();
+        // Split on some standard whitespace (outside of string literals)
+        let words = split_words(line);
         // Skip commented out lines
-        if *words_p.peek().unwrap_or(&"\n") == "//" {
+        if words.first() == Some(&"//") {
             tokens.push(Token::new("//", line_index, 0));
             tokens.push(Token::new("\n", line_index, 0));
             continue;
         }
         // Using `for (word_index, word) in words.enumerate()` gives the wrong indices
         let mut word_index: usize = 0;
+        let line_start = tokens.len();
         for word in words {
+            // Split off trailing commas (tuple separators), unless they're inside a string literal
+            let string_end = string_bounds(word).map_or(0, |(_, end)| end);
+            let stripped = word.trim_end_matches(',');
+            let stripped = &word[..stripped.len().max(string_end)];
+            let trailing_commas = word.len() - stripped.len();
+            let mut word = stripped;
+            // Split off the brackets around a list of permissions, like `[ReadFile WriteFile("/tmp/*")]`
+            let mut closing_brackets = 0usize;
+            let unbracketed = word.trim_matches(['[', ']']);
+            if !word.contains('"') || is_scoped_permission(unbracketed) {
+                while let Some(rest) = word.strip_prefix('[') {
+                    tokens.push(Token::new("[", line_index, word_index));
+                    word_index += 1;
+                    word = rest;
+                }
+                while let Some(rest) = word.strip_suffix(']').filter(|r| !r.contains('[')) {
+                    closing_brackets += 1;
+                    word = rest;
+                }
+            }
             // Handle exceptions to the "partition by space" rule
             if word.is_empty() {
                 // Skip empty lines
-                continue;
+            } else if is_scoped_permission(word) {
+                // A scoped permission, like `ReadFile("/etc/app/*")`, is a single word
+                tokens.push(Token::new(word, line_index, word_index));
+                word_index += 1;
             } else if (word.starts_with('(') || word.ends_with(')')) && word.len() > 1 {
                 // Handle parenthesis
                 let mut offset_start = 0usize;
                 let mut offset_end = word.len();
                 let mut deferred_closing_parens = 0usize;
                 // ASSUME that '(' always appears at beginning, ')' appears at end
-                for char in word.chars() {
+                // Parentheses inside a string literal are part of it
+                let outside = match string_bounds(word) {
+                    Some((start, end)) => format!("{}{}", &word[..start], &word[end..]),
+                    None => word.to_string(),
+                };
+                for char in outside.chars() {
                     if char == '(' {
                         offset_start += 1;
                         tokens.push(Token::new("(", line_index, word_index));
@@ -191,24 +316,45 @@ pub fn lex(input: &str) -> Vec<Token> {
                         deferred_closing_parens += 1;
                     }
                 }
-                // Push that word stripped of parens
-                tokens.push(Token::new(
-                    &word[offset_start..offset_end],
-                    line_index,
-                    word_index,
-                ));
-                // Push any trailing '('s
-                word_index += 1;
+                // Push that word stripped of parens (it may be empty if it was something like `((`)
+                if offset_start < offset_end {
+                    push_word(
+                        &mut tokens,
+                        &word[offset_start..offset_end],
+                        line_index,
+                        &mut word_index,
+                    );
+                }
+                // Push any trailing ')'s
                 for _ in 0..deferred_closing_parens {
                     tokens.push(Token::new(")", line_index, word_index));
                     word_index += 1;
                 }
             } else {
                 // Default case
-                tokens.push(Token::new(word, line_index, word_index));
+                push_word(&mut tokens, word, line_index, &mut word_index);
+            }
+            for _ in 0..closing_brackets {
+                tokens.push(Token::new("]", line_index, word_index));
+                word_index += 1;
+            }
+            for _ in 0..trailing_commas {
+                tokens.push(Token::new(",", line_index, word_index));
                 word_index += 1;
             }
         }
+        if let Some(first) = tokens.get_mut(line_start) {
+            if depth == 0 && first.text == "permission" {
+                first.symbol = Symbol::PermissionDeclare;
+            }
+        }
+        for token in tokens[line_start..].iter() {
+            match token.symbol {
+                Symbol::BraceOpen => depth += 1,
+                Symbol::BraceClose => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
         // Add new line separator token
         if let Some(t) = tokens.last() {
             tokens.push(Token::new("\n", line_index, t.word + 1));
@@ -254,13 +400,14 @@ mod tests {
             Symbol::Newline,
             Symbol::Value,
             Symbol::Value,
-            Symbol::Value,
             Symbol::Newline,
             Symbol::BraceClose,
         ];
         let tokens = lex(program);
         let actual = tokens.iter().map(|t| t.symbol).collect::<Vec<Symbol>>();
         assert_eq!(actual, expected);
+        // A string literal is one token, spaces and all
+        assert_eq!(tokens[5].text, "\"Hello, world\"");
     }
 
     #[test]
@@ -274,11 +421,6 @@ mod tests {
             Symbol::Value,
             Symbol::RightArrow,
             Symbol::Value,
-            Symbol::Value,
-            Symbol::Value,
-            Symbol::Value,
-            Symbol::Value,
-            Symbol::Value,
         ];
         let tokens = lex(program);
         let actual = tokens.iter().map(|t| t.symbol).collect::<Vec<Symbol>>();
@@ -327,4 +469,102 @@ mod tests {
         assert_eq!(tokens[3].line, 1);
         assert_eq!(tokens[3].word, 1);
     }
+
+    #[test]
+    fn tuple_commas() {
+        let program: &str = "let (q, r) :: (int, int) = (1, 2)";
+        let expected: Vec<Symbol> = vec![
+            Symbol::Let,
+            Symbol::ParenOpen,
+            Symbol::Value,
+            Symbol::Comma,
+            Symbol::Value,
+            Symbol::ParenClose,
+            Symbol::DoubleColon,
+            Symbol::ParenOpen,
+            Symbol::TypeInt,
+            Symbol::Comma,
+            Symbol::TypeInt,
+            Symbol::ParenClose,
+            Symbol::EqualSign,
+            Symbol::ParenOpen,
+            Symbol::Value,
+            Symbol::Comma,
+            Symbol::Value,
+            Symbol::ParenClose,
+        ];
+        let tokens = lex(program);
+        let actual = tokens.iter().map(|t| t.symbol).collect::<Vec<Symbol>>();
+        assert_eq!(actual, expected);
+        assert_eq!(tokens[2].word, 2);
+        assert_eq!(tokens[3].word, 3);
+
+        let tokens = lex("return (\"a\", \"b c\")");
+        let text: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(text, vec!["return", "(", "\"a\"", ",", "\"b c\"", ")"]);
+    }
+
+    #[test]
+    fn index_and_ranges() {
+        let program: &str = "set data @ 0..=2 = @ other 1..3";
+        let expected: Vec<Symbol> = vec![
+            Symbol::Set,
+            Symbol::Value,
+            Symbol::At,
+            Symbol::Value,
+            Symbol::RangeInclusive,
+            Symbol::Value,
+            Symbol::EqualSign,
+            Symbol::At,
+            Symbol::Value,
+            Symbol::Value,
+            Symbol::Range,
+            Symbol::Value,
+        ];
+        let tokens = lex(program);
+        let actual = tokens.iter().map(|t| t.symbol).collect::<Vec<Symbol>>();
+        assert_eq!(actual, expected);
+        assert_eq!(tokens[5].text, "2");
+        assert_eq!(tokens[5].word, 5);
+    }
+
+    #[test]
+    fn scoped_permissions() {
+        let program: &str =
+            "#Permissions :: ReadFile(\"/etc/app/*\") WriteNetwork(\"localhost:*\") (print \"x\")";
+        let tokens = lex(program);
+        let text: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "#Permissions",
+                "::",
+                "ReadFile(\"/etc/app/*\")",
+                "WriteNetwork(\"localhost:*\")",
+                "(",
+                "print",
+                "\"x\"",
+                ")"
+            ]
+        );
+        assert_eq!(tokens[3].word, 3);
+    }
+
+    #[test]
+    fn permission_keyword() {
+        let program: &str = "permission Telemetry
+fn permission :: permission str -> str {
+    let permission :: str = permission
+    return permission
+}";
+        let tokens = lex(program);
+        let declarations: Vec<(usize, usize)> = tokens
+            .iter()
+            .filter(|t| t.symbol == Symbol::PermissionDeclare)
+            .map(|t| (t.line, t.word))
+            .collect();
+        // Only the top-level declaration, every other `permission` is a name
+        assert_eq!(declarations, vec![(0, 0)]);
+        assert_eq!(tokens.iter().filter(|t| t.text == "permission").count(), 6);
+    }
 }
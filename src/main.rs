@@ -3,66 +3,161 @@
 use std::env;
 use std::error::Error;
 use std::fs;
+use std::path::Path;
 use std::time::Instant;
 
+mod attenuation;
+mod audit;
+mod call_graph;
 mod codegen_c;
 mod compiler_errors;
+mod determinism;
+mod dot;
 mod grammars;
 mod lex;
+mod modules;
+mod names;
 mod parse;
 mod parse_expressions;
+mod permission_variables;
 mod permissions;
+mod policy;
 mod properties;
+mod purity;
+mod sandbox;
+mod stdlib;
+mod trusted;
+mod type_check;
+mod why;
 
 use crate::{
-    codegen_c::emit_c_header,
-    parse::{compute_scopes, populate_function_table},
+    audit::{audit, audit_json, audit_summary},
+    call_graph::CallGraph,
+    codegen_c::{build_c_source, c_file_stem, emit_c_header, emit_c_source, CodegenOptions},
+    dot::{call_graph_dot, DotOptions},
+    modules::{search_path, Options, Program},
+    permissions::Permissions,
+    policy::POLICY_FILE,
+    sandbox::runtime_permissions,
+    type_check::inferred_types,
+    why::{explain, find_function},
 };
-use compiler_errors::{display_problem, CompilerProblem, ProblemClass};
+use compiler_errors::{apply_fixes, display_problem, CompilerProblem, ProblemClass};
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Initialize logging level
     let log_level: ProblemClass = ProblemClass::Lint;
-    // Capture command line
-    let args: Vec<String> = env::args().collect();
-    let file: &str = if args.len() == 1 {
-        "main.iona"
-    } else {
-        &args[1]
+    // Capture command line: flags start with `--`, the other arguments are an optional command and then the file to compile
+    let args: Vec<String> = env::args().skip(1).collect();
+    let positional: Vec<&str> = args
+        .iter()
+        .filter(|a| !a.starts_with("--"))
+        .map(|a| a.as_str())
+        .collect();
+    // `why` takes a function and a permission before the file
+    let (command, positional) = match positional.split_first() {
+        Some((&"audit", rest)) => (Some("audit"), rest),
+        Some((&"graph", rest)) => (Some("graph"), rest),
+        Some((&"why", rest)) if rest.len() >= 2 => (Some("why"), &rest[2..]),
+        Some((&"why", _)) => return Err("usage: why <function> <permission> [file] [--all]".into()),
+        _ => (None, positional.as_slice()),
     };
-    // Try to open linked file
-    let maybe_text = fs::read_to_string(file);
-    let program_root: String;
-    if maybe_text.is_err() {
-        return Err(format!("unable to find file {}, aborting compilation", file).into());
-    } else {
-        program_root = maybe_text.unwrap();
+    let show_types = args.iter().any(|a| a == "--show-types");
+    let fix = args.iter().any(|a| a == "--fix");
+    let no_sandbox = args.iter().any(|a| a == "--no-sandbox");
+    let trace_permissions = args.iter().any(|a| a == "--trace-permissions");
+    let mut options = Options::default();
+    if let Some(level) = args.iter().find_map(|a| a.strip_prefix("--shadowing=")) {
+        options.shadowing = level.parse()?;
     }
+    let file: &str = positional.first().copied().unwrap_or("main.iona");
+    let entry = Path::new(file);
     // Start timer
     let now = Instant::now();
-    // Debug: print the file
-    // println!("input file is: \n{}", program_root);
-    // Lex the file
-    let tokens = lex::lex(&program_root);
-    // Parse the file
-    let (mut nodes, mut errors) = parse::parse(tokens);
+    // Lex, parse, and post-process the file and every module it imports
+    // Post-processing (scopes, function tables, expressions, etc.) happens per module once imports are linked
+    let program = Program::load_with_options(entry, search_path(entry), options)?;
+    match command {
+        Some("audit") => return run_audit(&program, args.iter().any(|a| a == "--json")),
+        Some("why") => {
+            let (function, permission) = (args_after(&args, "why", 0), args_after(&args, "why", 1));
+            return run_why(
+                &program,
+                function,
+                permission,
+                args.iter().any(|a| a == "--all"),
+            );
+        }
+        Some("graph") => return run_graph(&program, &args),
+        _ => {}
+    }
     let elapsed = now.elapsed();
     println!("Finished compiling in {:.2?}", elapsed);
-    // Do post-processing on the AST -- just stick all errors onto the parse list and print all at once
-    // 1) Compute scopes (we MUST do this before trying to build function table)
-    errors.extend(compute_scopes(&mut nodes));
-    // 2) Build a function table
-    let function_table = populate_function_table(&nodes);
-    if function_table.is_err() {
-        errors.extend(function_table.as_ref().unwrap_err().clone());
+    // Display parsing errors, module by module
+    let mut okay = true;
+    for module in program.compiled_modules() {
+        okay &= display_error_list(module, &module.problems, log_level);
+    }
+    if let Some(policy) = &program.policy {
+        for problem in policy.problems.iter() {
+            okay &= problem.class != ProblemClass::Error;
+            if problem.class >= log_level {
+                display_problem(&policy.source, &format!("issue in {POLICY_FILE}"), problem);
+            }
+        }
+    }
+    // Rewrite the source files with every machine-applicable fix, unless errors may have hidden some of the code
+    if fix && !okay {
+        eprintln!("not applying fixes, since the program has errors");
+    } else if fix {
+        for module in program.compiled_modules() {
+            let (fixed, count) = apply_fixes(&module.source, &module.problems);
+            if let (Some(path), true) = (&module.path, count > 0) {
+                fs::write(path, fixed)?;
+                println!("applied {count} fix(es) to {}", path.display());
+            }
+        }
+    }
+    // Show the types inference worked out
+    if show_types {
+        for module in program.compiled_modules() {
+            for (line, name, data_type) in inferred_types(&module.nodes) {
+                println!(
+                    "{}:{}: `{name}` :: {data_type}",
+                    module.display_name(),
+                    line + 1
+                );
+            }
+        }
     }
-    // Display parsing errors
-    let okay = display_error_list(&program_root, &errors, log_level);
     // Final output
     if okay {
-        // Write out the header
-        if function_table.is_ok() {
-            let _ = emit_c_header(&function_table.as_ref().unwrap());
+        // An executable restricts itself to what `main` can reach, in case linked C code does more than it declares
+        let sandbox = match no_sandbox {
+            true => None,
+            false => runtime_permissions(&program),
+        };
+        for module in program.compiled_modules() {
+            // Lower the function bodies first, so we don't write a header for a program we can't generate
+            let options = CodegenOptions {
+                sandbox: sandbox.clone().filter(|_| module.name == program.entry),
+                trace_permissions,
+            };
+            let source = match build_c_source(
+                &module.name,
+                &module.nodes,
+                &module.function_table,
+                &options,
+            ) {
+                Ok(source) => source,
+                Err(problems) => {
+                    display_error_list(module, &problems, log_level);
+                    return Err("program failed during code generation".into());
+                }
+            };
+            // Write out the header and the source
+            let _ = emit_c_header(&module.name, &module.function_table);
+            let _ = emit_c_source(&module.name, &source);
         }
         Ok(())
     } else {
@@ -70,18 +165,91 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 }
 
+/// Print the permission audit, as JSON or as a summary grouped by permission
+///
+/// Problems aren't displayed (so `--json` output can be piped into other tools), but a program with errors may be missing calls
+fn run_audit(program: &Program, json: bool) -> Result<(), Box<dyn Error>> {
+    let errors = program
+        .compiled_modules()
+        .flat_map(|m| m.problems.iter())
+        .filter(|p| p.class == ProblemClass::Error)
+        .count();
+    if errors > 0 {
+        eprintln!("warning: the program has {errors} error(s), so this audit may be incomplete");
+    }
+    let audits = audit(program);
+    if json {
+        println!("{}", audit_json(&program.entry, &audits));
+    } else {
+        print!("{}", audit_summary(&audits));
+    }
+    Ok(())
+}
+
+/// Write the call graph as a DOT file, by default next to the generated C as `<entry>.dot`
+fn run_graph(program: &Program, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut options = DotOptions {
+        collapse_stdlib: args.iter().any(|a| a == "--collapse-std"),
+        ..DotOptions::default()
+    };
+    if let Some(name) = args.iter().find_map(|a| a.strip_prefix("--highlight=")) {
+        let permission = Permissions::from_str(name);
+        if !program.permissions.is_known(&permission) {
+            return Err(format!("unknown permission `{permission}`").into());
+        }
+        options.highlight = Some(permission);
+    }
+    let path = match args.iter().find_map(|a| a.strip_prefix("--output=")) {
+        Some(path) => path.to_string(),
+        None => {
+            fs::create_dir_all("./codegen")?;
+            format!("./codegen/{}.dot", c_file_stem(&program.entry))
+        }
+    };
+    fs::write(&path, call_graph_dot(program, &options))?;
+    println!("wrote call graph to {path}");
+    Ok(())
+}
+
+/// The positional argument a number of places after a command
+fn args_after<'a>(args: &'a [String], command: &str, offset: usize) -> &'a str {
+    args.iter()
+        .filter(|a| !a.starts_with("--"))
+        .skip_while(|a| *a != command)
+        .nth(offset + 1)
+        .map_or("", |a| a.as_str())
+}
+
+/// Print the call chains that make a function require a permission
+fn run_why(
+    program: &Program,
+    function: &str,
+    permission: &str,
+    all: bool,
+) -> Result<(), Box<dyn Error>> {
+    let permission = Permissions::from_str(permission);
+    if !program.permissions.is_known(&permission) {
+        return Err(format!("unknown permission `{permission}`").into());
+    }
+    let graph = CallGraph::build(program);
+    let function = find_function(program, &graph, function)?;
+    print!("{}", explain(program, &graph, &function, &permission, all));
+    Ok(())
+}
+
 fn display_error_list(
-    program_text: &str,
+    module: &modules::Module,
     errors: &Vec<CompilerProblem>,
     log_level: ProblemClass,
 ) -> bool {
     let mut okay = true;
+    let context = format!("issue in {}", module.display_name());
     for err in errors {
         if err.class == ProblemClass::Error {
             okay = false;
         }
         if err.class >= log_level {
-            display_problem(&program_text, "issue during parsing", err);
+            display_problem(&module.source, &context, err);
         }
     }
     okay
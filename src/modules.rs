@@ -0,0 +1,991 @@
+//! Resolves `import` statements: finds the file behind every module, compiles each reachable module once, and links imported functions into the importing module's function table
+//!
+//! A dotted module name maps onto the directory tree, so `import area from geometry.shapes` loads `geometry/shapes.iona` from the first directory on the search path that has it. The search path is the directory holding the entry file (the project root), followed by any directories in `IONA_PATH`. Modules under `std` are provided by the compiler, see `stdlib`.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::attenuation::{check_attenuation, expand_attenuation};
+use crate::call_graph::{check_permissions, over_privilege_lints, CallGraph};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::determinism::check_determinism;
+use crate::grammars::{Grammar, ImportItem};
+use crate::lex::{lex, Token};
+use crate::names::{resolve_names, Shadowing};
+use crate::parse::{
+    check_destructuring, check_indexing, check_mutability, compute_scopes, function_calls, parse,
+    parse_node_expressions, populate_function_table, FunctionData, Node, Origin,
+};
+use crate::permission_variables::check_permission_variables;
+use crate::permissions::{check_permission_names, resolve_permission_names, PermissionHierarchy};
+use crate::policy::{check_policy, Policy};
+use crate::properties::Properties;
+use crate::purity::{check_purity, pure_lints};
+use crate::stdlib;
+use crate::trusted::trusted_warnings;
+use crate::type_check::{check_types, infer_types};
+
+/// Environment variable holding extra directories to search for modules
+pub const SEARCH_PATH_VARIABLE: &str = "IONA_PATH";
+
+/// A single `import` (or `export ... from`) line
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub module: String,
+    /// The imported items, empty for wildcard and whole-module imports
+    pub items: Vec<ImportItem>,
+    pub wildcard: bool,
+    pub reexport: bool,
+    pub line: usize,
+    /// Position of the module name within the line
+    pub word: usize,
+}
+
+impl Import {
+    /// A whole-module import makes the module's functions available as `<last part of module name>.<function>`
+    pub fn qualifier(&self) -> &str {
+        self.module.rsplit('.').next().unwrap_or(&self.module)
+    }
+
+    pub fn is_whole_module(&self) -> bool {
+        self.items.is_empty() && !self.wildcard
+    }
+}
+
+/// One compiled source file (or standard library module)
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    /// `None` for modules built into the compiler
+    pub path: Option<PathBuf>,
+    pub source: String,
+    pub nodes: Vec<Node>,
+    pub function_table: BTreeMap<String, FunctionData>,
+    pub imports: Vec<Import>,
+    pub problems: Vec<CompilerProblem>,
+}
+
+impl Module {
+    /// Lex and parse a module, and build the table of the functions it defines
+    fn compile(name: &str, path: Option<PathBuf>, source: String) -> Module {
+        let (mut nodes, mut problems) = parse(lex(&source));
+        problems.extend(compute_scopes(&mut nodes));
+        let function_table = match populate_function_table(&nodes) {
+            Ok(table) => table,
+            Err(e) => {
+                problems.extend(e);
+                BTreeMap::new()
+            }
+        };
+        let imports = nodes
+            .iter()
+            .filter_map(|node| match &node.grammar {
+                Grammar::Import(g) => Some(Import {
+                    module: g.file.clone(),
+                    items: g.items.clone(),
+                    wildcard: g.wildcard,
+                    reexport: g.reexport,
+                    line: node.source_line,
+                    word: g.file_word,
+                }),
+                _ => None,
+            })
+            .collect();
+        Module {
+            name: name.to_string(),
+            path,
+            source,
+            nodes,
+            function_table,
+            imports,
+            problems,
+        }
+    }
+
+    /// A standard library module has no source, just a function table
+    fn builtin(name: &str) -> Module {
+        Module {
+            name: name.to_string(),
+            path: None,
+            source: String::new(),
+            nodes: Vec::new(),
+            function_table: stdlib::function_table(name),
+            imports: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        self.path.is_none()
+    }
+
+    /// How the module is referred to in messages
+    pub fn display_name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Every module reachable from the entry file
+#[derive(Debug)]
+pub struct Program {
+    /// The name of the module holding the entry file
+    pub entry: String,
+    pub modules: BTreeMap<String, Module>,
+    /// Module names, each after every module it imports (apart from cycles)
+    pub order: Vec<String>,
+    search_path: Vec<PathBuf>,
+    options: Options,
+    /// The custom permissions declared by every module
+    pub permissions: PermissionHierarchy,
+    /// The project's permission budgets, if it has an `iona-policy.toml`
+    pub policy: Option<Policy>,
+}
+
+/// Settings for the checks run on every module
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    pub shadowing: Shadowing,
+}
+
+/// The directories modules are loaded from, in priority order
+pub fn search_path(entry: &Path) -> Vec<PathBuf> {
+    let root = match entry.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut directories = vec![root];
+    if let Some(extra) = env::var_os(SEARCH_PATH_VARIABLE) {
+        directories.extend(env::split_paths(&extra).filter(|p| !p.as_os_str().is_empty()));
+    }
+    directories
+}
+
+/// `a.b.c` lives at `a/b/c.iona`
+pub fn module_relative_path(module: &str) -> PathBuf {
+    let mut path: PathBuf = module.split('.').collect();
+    path.set_extension("iona");
+    path
+}
+
+impl Program {
+    /// Load the entry file and every module it (transitively) imports, then link and check them all
+    ///
+    /// Only failing to read the entry file is fatal, all other problems are recorded on the module they occur in
+    pub fn load(entry: &Path, search_path: Vec<PathBuf>) -> Result<Program, String> {
+        Program::load_with_options(entry, search_path, Options::default())
+    }
+
+    /// `load`, with non-default settings for the checks
+    pub fn load_with_options(
+        entry: &Path,
+        search_path: Vec<PathBuf>,
+        options: Options,
+    ) -> Result<Program, String> {
+        let source = fs::read_to_string(entry).map_err(|_| {
+            format!(
+                "unable to find file {}, aborting compilation",
+                entry.display()
+            )
+        })?;
+        let name = entry
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "main".to_string());
+        let mut program = Program {
+            entry: name.clone(),
+            modules: BTreeMap::new(),
+            order: Vec::new(),
+            search_path,
+            options,
+            permissions: PermissionHierarchy::default(),
+            policy: None,
+        };
+        // The policy lives in the project root, which is first on the search path
+        program.policy = program
+            .search_path
+            .first()
+            .and_then(|root| Policy::load(root));
+        let module = Module::compile(&name, Some(entry.to_path_buf()), source);
+        program.load_imports(module, &mut Vec::new());
+        program.link();
+        program.check();
+        Ok(program)
+    }
+
+    /// Where a module's file is, if any directory on the search path has it
+    fn find(&self, module: &str) -> Option<PathBuf> {
+        let relative = module_relative_path(module);
+        self.search_path
+            .iter()
+            .map(|directory| directory.join(&relative))
+            .find(|path| path.is_file())
+    }
+
+    /// Depth-first: every import is loaded before the module that imports it is added to `order`
+    ///
+    /// `stack` holds the modules currently being loaded, so an import of one of them is a cycle
+    fn load_imports(&mut self, mut module: Module, stack: &mut Vec<String>) {
+        stack.push(module.name.clone());
+        for import in module.imports.clone() {
+            if let Some(position) = stack.iter().position(|m| *m == import.module) {
+                let mut cycle = stack[position..].to_vec();
+                cycle.push(import.module.clone());
+                module.problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("import cycle: {}", cycle.join(" -> ")),
+                    "modules cannot import each other, move the shared functions into a module of their own",
+                    import.line,
+                    import.word,
+                ));
+                continue;
+            }
+            if self.modules.contains_key(&import.module) {
+                continue;
+            }
+            if stdlib::is_stdlib_module(&import.module) {
+                if stdlib::module_exists(&import.module) {
+                    self.add(Module::builtin(&import.module));
+                } else {
+                    module.problems.push(CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("the standard library has no module `{}`", import.module),
+                        "check the spelling of the module name",
+                        import.line,
+                        import.word,
+                    ));
+                }
+                continue;
+            }
+            match self.find(&import.module) {
+                Some(path) => match fs::read_to_string(&path) {
+                    Ok(source) => {
+                        let imported = Module::compile(&import.module, Some(path), source);
+                        self.load_imports(imported, stack);
+                    }
+                    Err(e) => module.problems.push(CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!(
+                            "unable to read module `{}` from {}: {e}",
+                            import.module,
+                            path.display()
+                        ),
+                        "check the file's permissions",
+                        import.line,
+                        import.word,
+                    )),
+                },
+                None => {
+                    let searched = self
+                        .search_path
+                        .iter()
+                        .map(|d| {
+                            d.join(module_relative_path(&import.module))
+                                .display()
+                                .to_string()
+                        })
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    module.problems.push(CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("unable to find module `{}`", import.module),
+                        &format!("looked for {searched}, you can add directories to search with {SEARCH_PATH_VARIABLE}"),
+                        import.line,
+                        import.word,
+                    ));
+                }
+            }
+        }
+        stack.pop();
+        self.add(module);
+    }
+
+    fn add(&mut self, module: Module) {
+        self.order.push(module.name.clone());
+        self.modules.insert(module.name.clone(), module);
+    }
+
+    /// Copy imported functions into each module's function table, marking where they came from
+    ///
+    /// Two imports binding the same name to different functions, or an import binding the name of a function defined in the module, is an error. The prelude never collides: anything else takes priority over it.
+    fn link(&mut self) {
+        for name in self.order.clone() {
+            let module = &self.modules[&name];
+            if module.is_builtin() {
+                continue;
+            }
+            let mut bindings: BTreeMap<String, (FunctionData, String)> = BTreeMap::new();
+            let mut problems: Vec<CompilerProblem> = Vec::new();
+            for import in module.imports.iter() {
+                // Missing modules were already reported while loading
+                let Some(imported) = self.modules.get(&import.module) else {
+                    continue;
+                };
+                // (name to bind, function, where to report problems)
+                let mut candidates: Vec<(String, FunctionData, usize, usize)> = Vec::new();
+                if import.is_whole_module() {
+                    for (function_name, data) in exported_functions(imported) {
+                        let qualified = format!("{}.{function_name}", import.qualifier());
+                        candidates.push((qualified, data, import.line, import.word));
+                    }
+                } else if import.wildcard {
+                    for (function_name, data) in exported_functions(imported) {
+                        candidates.push((function_name, data, import.line, import.word));
+                    }
+                }
+                for item in import.items.iter() {
+                    let binding = item.binding();
+                    match importable_function(imported, &item.name) {
+                        Ok(data) => candidates.push((
+                            binding.text.clone(),
+                            data,
+                            binding.line,
+                            binding.word,
+                        )),
+                        Err(problem) => problems.push(problem),
+                    }
+                }
+                for (binding, mut data, line, word) in candidates {
+                    if let Some(origin) = data.origin.as_mut() {
+                        origin.reexported = import.reexport;
+                    }
+                    if module.function_table.contains_key(&binding) {
+                        problems.push(CompilerProblem::new(
+                            ProblemClass::Error,
+                            &format!("`{binding}` is defined in this module, and also imported from `{}`", imported.name),
+                            &format!("import it under another name, like `import {binding} as other_{binding} from {}`", imported.name),
+                            line,
+                            word,
+                        ));
+                        continue;
+                    }
+                    match bindings.get_mut(&binding) {
+                        // Importing the same function twice is harmless
+                        Some((existing, _)) if same_function(existing, &data) => {
+                            if let (Some(a), Some(b)) = (existing.origin.as_mut(), &data.origin) {
+                                a.reexported |= b.reexported;
+                            }
+                        }
+                        Some((_, via)) => problems.push(CompilerProblem::new(
+                            ProblemClass::Error,
+                            &format!(
+                                "`{binding}` is imported from both `{via}` and `{}`",
+                                imported.name
+                            ),
+                            "use `as` to import one of them under another name",
+                            line,
+                            word,
+                        )),
+                        None => {
+                            bindings.insert(binding, (data, imported.name.clone()));
+                        }
+                    }
+                }
+            }
+            // Everyone gets the prelude
+            let prelude = Module::builtin(stdlib::PRELUDE);
+            let module = self.modules.get_mut(&name).unwrap();
+            for (function_name, data) in exported_functions(&prelude) {
+                bindings
+                    .entry(function_name)
+                    .or_insert((data, prelude.name.clone()));
+            }
+            for (function_name, (data, _)) in bindings {
+                module.function_table.entry(function_name).or_insert(data);
+            }
+            module.problems.extend(problems);
+        }
+    }
+
+    /// Post-processing that needs the linked function tables
+    fn check(&mut self) {
+        let shadowing = self.options.shadowing;
+        // Custom permissions can be named from every module, as `module.Name` outside their own
+        let (permissions, duplicates) = PermissionHierarchy::declare(
+            &self.entry,
+            self.compiled_modules()
+                .map(|m| (m.name.as_str(), m.nodes.as_slice())),
+        );
+        self.permissions = permissions;
+        for (name, problem) in duplicates {
+            if let Some(module) = self.modules.get_mut(&name) {
+                module.problems.push(problem);
+            }
+        }
+        for module in self.modules.values_mut().filter(|m| !m.is_builtin()) {
+            // Names are checked as written, then rewritten to the names they're stored under
+            module.problems.extend(check_permission_names(
+                &module.name,
+                &module.nodes,
+                &self.permissions,
+            ));
+            resolve_permission_names(
+                &module.name,
+                &mut module.nodes,
+                &mut module.function_table,
+                &self.permissions,
+            );
+            let table = &module.function_table;
+            module
+                .problems
+                .extend(parse_node_expressions(&mut module.nodes, table));
+            module
+                .problems
+                .extend(resolve_names(&module.nodes, table, shadowing));
+            module
+                .problems
+                .extend(infer_types(&mut module.nodes, table));
+            module
+                .problems
+                .extend(check_destructuring(&module.nodes, table));
+            module.problems.extend(check_indexing(&module.nodes, table));
+            module.problems.extend(check_mutability(&module.nodes));
+            expand_attenuation(&mut module.nodes, &self.permissions);
+            module.problems.extend(check_types(&module.nodes, table));
+        }
+        let lints: Vec<(String, CompilerProblem)> = self
+            .modules
+            .values()
+            .flat_map(|module| {
+                self.wildcard_lints(module)
+                    .into_iter()
+                    .map(|lint| (module.name.clone(), lint))
+            })
+            .chain(self.unused_export_lints())
+            .chain(self.permission_problems())
+            .chain(self.purity_problems())
+            .collect();
+        for (name, lint) in lints {
+            if let Some(module) = self.modules.get_mut(&name) {
+                module.problems.push(lint);
+            }
+        }
+        let policy_problems = self.policy.as_ref().map(|p| p.check(self));
+        if let (Some(policy), Some(problems)) = (self.policy.as_mut(), policy_problems) {
+            policy.problems.extend(problems);
+        }
+    }
+
+    /// Calls made without the permissions the callee requires, and permissions nobody needs, paired with their module
+    fn permission_problems(&self) -> Vec<(String, CompilerProblem)> {
+        let graph = CallGraph::build(self);
+        let required = graph.required_permissions();
+        let mut problems = check_permissions(&graph, &required);
+        problems.extend(check_permission_variables(self, &graph, &required));
+        problems.extend(check_attenuation(self, &graph, &required));
+        problems.extend(trusted_warnings(&graph, &required));
+        if let Some(policy) = &self.policy {
+            problems.extend(check_policy(self, policy, &graph, &required));
+        }
+        problems.extend(over_privilege_lints(self, &graph, &required));
+        problems
+    }
+
+    /// `Pure` functions with side effects, functions that could be `Pure`, and nondeterministic `Deterministic` functions, paired with their module
+    fn purity_problems(&self) -> Vec<(String, CompilerProblem)> {
+        let graph = CallGraph::build(self);
+        let mut problems = check_purity(self, &graph);
+        problems.extend(pure_lints(self, &graph));
+        problems.extend(check_determinism(&graph));
+        problems
+    }
+
+    /// `Export` functions that no module in the project calls, paired with the module defining them
+    fn unused_export_lints(&self) -> Vec<(String, CompilerProblem)> {
+        // (defining module, name) of every function called anywhere
+        let mut used: Vec<(String, String)> = Vec::new();
+        for module in self.compiled_modules() {
+            for (_, name, _) in function_calls(&module.nodes) {
+                match module.function_table.get(&name).map(|f| &f.origin) {
+                    Some(Some(origin)) => used.push((origin.module.clone(), origin.name.clone())),
+                    Some(None) => used.push((module.name.clone(), name)),
+                    None => {}
+                }
+            }
+        }
+        let mut lints: Vec<(String, CompilerProblem)> = Vec::new();
+        for module in self.compiled_modules() {
+            for node in module.nodes.iter() {
+                let Grammar::Function(g) = &node.grammar else {
+                    continue;
+                };
+                let exported = module.function_table.get(&g.fn_name).is_some_and(|f| {
+                    f.origin.is_none() && f.properties.contains(&Properties::Export)
+                });
+                let key = (module.name.clone(), g.fn_name.clone());
+                if exported && g.fn_name != "main" && !used.contains(&key) {
+                    lints.push((
+                        module.name.clone(),
+                        CompilerProblem::new(
+                            ProblemClass::Lint,
+                            &format!("`{}` is exported, but no module in this project uses it", g.fn_name),
+                            "remove `Export` if it's only meant for this module, or remove the function if it's not needed",
+                            node.source_line,
+                            1,
+                        ),
+                    ));
+                }
+            }
+        }
+        lints
+    }
+
+    /// Wildcard imports hide where names come from, so suggest importing by name instead
+    ///
+    /// Re-exporting everything with `export * from` is deliberate, so it isn't linted
+    fn wildcard_lints(&self, module: &Module) -> Vec<CompilerProblem> {
+        let used: Vec<String> = function_calls(&module.nodes)
+            .into_iter()
+            .map(|(_, name, _)| name)
+            .collect();
+        let mut lints: Vec<CompilerProblem> = Vec::new();
+        for import in module.imports.iter().filter(|i| i.wildcard && !i.reexport) {
+            let Some(imported) = self.modules.get(&import.module) else {
+                continue;
+            };
+            let used_here: Vec<String> = exported_functions(imported)
+                .into_iter()
+                .filter(|(function_name, data)| {
+                    used.contains(function_name)
+                        && module
+                            .function_table
+                            .get(function_name)
+                            .is_some_and(|bound| same_function(bound, data))
+                })
+                .map(|(function_name, _)| function_name)
+                .collect();
+            let hint = if used_here.is_empty() {
+                format!(
+                    "nothing from `{}` is used, so this import can be removed",
+                    import.module
+                )
+            } else {
+                format!(
+                    "import what you use by name: `import {} from {}`",
+                    used_here.join(" "),
+                    import.module
+                )
+            };
+            lints.push(CompilerProblem::new(
+                ProblemClass::Lint,
+                &format!(
+                    "wildcard import from `{}` makes it hard to tell where names come from",
+                    import.module
+                ),
+                &hint,
+                import.line,
+                0,
+            ));
+        }
+        lints
+    }
+
+    /// The `import` line that binds a name in a module, or `None` for local and prelude functions
+    pub fn import_of<'a>(&self, module: &'a Module, name: &str) -> Option<&'a Import> {
+        let named = module.imports.iter().find(|import| {
+            import.items.iter().any(|item| item.binding().text == name)
+                || (import.is_whole_module()
+                    && name
+                        .strip_prefix(import.qualifier())
+                        .is_some_and(|rest| rest.starts_with('.')))
+        });
+        named.or_else(|| {
+            module.imports.iter().find(|import| {
+                import.wildcard
+                    && self
+                        .modules
+                        .get(&import.module)
+                        .is_some_and(|m| m.function_table.contains_key(name))
+            })
+        })
+    }
+
+    /// The modules to generate code for, each after the modules it imports
+    pub fn compiled_modules(&self) -> impl Iterator<Item = &Module> {
+        self.order
+            .iter()
+            .map(|name| &self.modules[name])
+            .filter(|m| !m.is_builtin())
+    }
+}
+
+/// Stamp where an imported function comes from, keeping the original definition's origin if it was re-exported
+fn imported_from(module: &Module, name: &str, data: &FunctionData) -> FunctionData {
+    let mut data = data.clone();
+    if data.origin.is_none() {
+        data.origin = Some(Origin {
+            module: module.name.clone(),
+            name: name.to_string(),
+            reexported: false,
+        });
+    }
+    data
+}
+
+/// A function another module may import by name: an `Export` function defined in the module, or one it re-exports
+///
+/// Otherwise returns the problem to report at the imported name
+fn importable_function(module: &Module, item: &Token) -> Result<FunctionData, CompilerProblem> {
+    let problem = |message: String, hint: String| {
+        CompilerProblem::new(ProblemClass::Error, &message, &hint, item.line, item.word)
+    };
+    let name = &item.text;
+    match module.function_table.get(name) {
+        Some(data) => {
+            match &data.origin {
+                Some(origin) if !origin.reexported => Err(problem(
+                    format!(
+                        "module `{}` imports `{name}` from `{}`, but doesn't re-export it",
+                        module.name, origin.module
+                    ),
+                    format!("import it from `{}` instead", origin.module),
+                )),
+                None if !data.properties.contains(&Properties::Export) => {
+                    let visibility = if data.properties.contains(&Properties::Public) {
+                        "is `Public`, so it's only visible within"
+                    } else {
+                        "isn't exported by"
+                    };
+                    Err(problem(
+                    format!("`{name}` {visibility} module `{}`", module.name),
+                    format!("add `Export` to the properties of `{name}` to use it from other modules"),
+                ))
+                }
+                _ => Ok(imported_from(module, name, data)),
+            }
+        }
+        None => Err(problem(
+            format!("module `{}` has no function `{name}`", module.name),
+            "check the spelling, and that the function is defined (or re-exported) by that module"
+                .to_string(),
+        )),
+    }
+}
+
+/// The functions wildcard and whole-module imports bring in: exported functions, and re-exported ones
+fn exported_functions(module: &Module) -> Vec<(String, FunctionData)> {
+    module
+        .function_table
+        .iter()
+        .filter(|(_, data)| match &data.origin {
+            None => data.properties.contains(&Properties::Export),
+            Some(origin) => origin.reexported,
+        })
+        .map(|(name, data)| (name.clone(), imported_from(module, name, data)))
+        .collect()
+}
+
+/// Do two imported functions refer to the same definition?
+fn same_function(a: &FunctionData, b: &FunctionData) -> bool {
+    match (&a.origin, &b.origin) {
+        (Some(a), Some(b)) => a.module == b.module && a.name == b.name,
+        _ => false,
+    }
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A temporary directory of source files, deleted when the test is done with it
+    pub(crate) struct Project(PathBuf);
+
+    impl std::ops::Deref for Project {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A fresh directory holding the given files
+    pub(crate) fn project(test_name: &str, files: &[(&str, &str)]) -> Project {
+        let root = env::temp_dir().join(format!("iona-{test_name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, text) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        Project(root)
+    }
+
+    pub(crate) fn messages(program: &Program, module: &str) -> Vec<String> {
+        program.modules[module]
+            .problems
+            .iter()
+            .map(|p| p.message.clone())
+            .collect()
+    }
+
+    fn origin_module<'a>(table: &'a BTreeMap<String, FunctionData>, name: &str) -> Option<&'a str> {
+        table[name].origin.as_ref().map(|o| o.module.as_str())
+    }
+
+    #[test]
+    fn load_nested_module() {
+        let root = project(
+            "nested",
+            &[
+                (
+                    "main.iona",
+                    "import area from geometry.shapes\nimport write_file from std.files\n\nfn main {\n    let a :: int = area 2 3\n}",
+                ),
+                (
+                    "geometry/shapes.iona",
+                    "fn area :: w int -> h int -> int {\n    #Properties :: Export\n    return * w h\n}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert_eq!(program.order, vec!["geometry.shapes", "std.files", "main"]);
+        assert!(messages(&program, "main").is_empty());
+        let table = &program.modules["main"].function_table;
+        assert_eq!(origin_module(table, "area"), Some("geometry.shapes"));
+        assert_eq!(origin_module(table, "write_file"), Some("std.files"));
+        // The prelude is always available
+        assert_eq!(origin_module(table, "println"), Some("std.io"));
+        assert_eq!(table["main"].origin, None);
+    }
+
+    #[test]
+    fn load_search_path() {
+        let root = project("search", &[("app/main.iona", "import lib.helpers\n")]);
+        let library = project(
+            "search-lib",
+            &[(
+                "lib/helpers.iona",
+                "fn help {\n    #Properties :: Export\n}\nfn hidden {\n}",
+            )],
+        );
+        let program = Program::load(
+            &root.join("app/main.iona"),
+            vec![root.join("app"), library.clone()],
+        )
+        .unwrap();
+        assert!(messages(&program, "main").is_empty());
+        // A whole-module import only brings in exported functions, qualified by the module name
+        let table = &program.modules["main"].function_table;
+        assert!(table.contains_key("helpers.help"));
+        assert!(!table.contains_key("help"));
+        assert!(!table.contains_key("helpers.hidden"));
+    }
+
+    #[test]
+    fn load_single_segment_module() {
+        let root = project(
+            "single-segment",
+            &[
+                (
+                    "main.iona",
+                    "import shapes\nimport area perimeter\nfn main {\n    let a :: int = shapes.area 2 3\n}",
+                ),
+                (
+                    "shapes.iona",
+                    "fn area :: w int -> h int -> int {\n    #Properties :: Export\n    return * w h\n}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        // `import shapes` is a whole-module import, and a list of items without `from` fails once
+        assert_eq!(
+            messages(&program, "main"),
+            vec!["this import ended before naming the module to import from"]
+        );
+        assert!(program.modules["main"]
+            .function_table
+            .contains_key("shapes.area"));
+    }
+
+    #[test]
+    fn load_unknown_module_and_name() {
+        let root = project(
+            "unknown",
+            &[
+                ("main.iona", "import nothing from missing.module\nimport read_fiel from std.files\nimport x from std.nope"),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems = &program.modules["main"].problems;
+        assert_eq!(problems.len(), 3);
+        assert_eq!(
+            problems[0].message,
+            "unable to find module `missing.module`"
+        );
+        assert_eq!((problems[0].line, problems[0].word_index), (0, 3));
+        assert_eq!(
+            problems[1].message,
+            "the standard library has no module `std.nope`"
+        );
+        assert_eq!(
+            problems[2].message,
+            "module `std.files` has no function `read_fiel`"
+        );
+        assert_eq!((problems[2].line, problems[2].word_index), (1, 1));
+    }
+
+    #[test]
+    fn load_import_cycle() {
+        let root = project(
+            "cycle",
+            &[
+                ("main.iona", "import a from pkg.one"),
+                (
+                    "pkg/one.iona",
+                    "import b from pkg.two\nfn a {\n    #Properties :: Export\n}",
+                ),
+                (
+                    "pkg/two.iona",
+                    "import a from pkg.one\nfn b {\n    #Properties :: Export\n}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let errors: Vec<&str> = program.modules["pkg.two"]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .map(|p| p.message.as_str())
+            .collect();
+        assert_eq!(errors, vec!["import cycle: pkg.one -> pkg.two -> pkg.one"]);
+        assert!(messages(&program, "main").is_empty());
+    }
+
+    #[test]
+    fn load_alias_and_qualified() {
+        let root = project(
+            "alias",
+            &[(
+                "main.iona",
+                "import read_file as rf from std.files\nimport std.files\n\nfn main {\n    #Permissions :: ReadFile WriteFile\n    let a :: str = rf \"a.txt\"\n    files.write_file a \"b.txt\"\n}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert!(messages(&program, "main").is_empty());
+        let module = &program.modules["main"];
+        let rf = module.function_table["rf"].origin.as_ref().unwrap();
+        assert_eq!(
+            (rf.module.as_str(), rf.name.as_str()),
+            ("std.files", "read_file")
+        );
+        assert!(module.function_table.contains_key("files.write_file"));
+        assert!(!module.function_table.contains_key("read_file"));
+        let source = crate::codegen_c::build_c_source(
+            "main",
+            &module.nodes,
+            &module.function_table,
+            &Default::default(),
+        )
+        .unwrap();
+        assert!(source.contains("char* a = read_file(\"a.txt\");"));
+        assert!(source.contains("    write_file(a, \"b.txt\");"));
+    }
+
+    #[test]
+    fn load_name_collisions() {
+        let root = project(
+            "collide",
+            &[
+                (
+                    "main.iona",
+                    "import area from shapes.square\nimport area from shapes.circle\nimport perimeter from shapes.square\n\nfn perimeter {\n}",
+                ),
+                (
+                    "shapes/square.iona",
+                    "fn area {\n    #Properties :: Export\n}\nfn perimeter {\n    #Properties :: Export\n}",
+                ),
+                ("shapes/circle.iona", "fn area {\n    #Properties :: Export\n}"),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert_eq!(
+            messages(&program, "main"),
+            vec![
+                "`area` is imported from both `shapes.square` and `shapes.circle`",
+                "`perimeter` is defined in this module, and also imported from `shapes.square`",
+            ]
+        );
+    }
+
+    #[test]
+    fn load_reexport() {
+        let root = project(
+            "reexport",
+            &[
+                ("main.iona", "import area read_file from shapes\nimport * from shapes"),
+                (
+                    "shapes.iona",
+                    "export area from shapes.square\nexport read_file from std.files\nimport perimeter from shapes.square",
+                ),
+                (
+                    "shapes/square.iona",
+                    "fn area {\n    #Properties :: Export\n}\nfn perimeter {\n    #Properties :: Export\n}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let main = &program.modules["main"];
+        // The re-exported function still comes from the module that defines it
+        let area = main.function_table["area"].origin.as_ref().unwrap();
+        assert_eq!(area.module, "shapes.square");
+        assert_eq!(
+            origin_module(&main.function_table, "read_file"),
+            Some("std.files")
+        );
+        // `perimeter` was only imported by `shapes`, not re-exported
+        assert!(!main.function_table.contains_key("perimeter"));
+        // Nothing the wildcard brings in is used
+        assert_eq!(
+            messages(&program, "main"),
+            vec!["wildcard import from `shapes` makes it hard to tell where names come from"]
+        );
+        assert_eq!(main.problems[0].class, ProblemClass::Lint);
+    }
+
+    #[test]
+    fn load_visibility() {
+        let root = project(
+            "visibility",
+            &[
+                (
+                    "main.iona",
+                    "import shared private internal from lib\n\nfn main {\n    shared\n}",
+                ),
+                (
+                    "lib.iona",
+                    "fn shared {\n    #Properties :: Export\n}\nfn private {\n}\nfn internal {\n    #Properties :: Public\n}\nfn unused {\n    #Properties :: Export\n}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert_eq!(
+            messages(&program, "main"),
+            vec![
+                "`private` isn't exported by module `lib`",
+                "`internal` is `Public`, so it's only visible within module `lib`",
+            ]
+        );
+        assert_eq!(program.modules["main"].problems[1].word_index, 3);
+        // `shared` is used by `main`, `unused` isn't used anywhere
+        let lints = &program.modules["lib"].problems;
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].class, ProblemClass::Lint);
+        assert_eq!(
+            lints[0].message,
+            "`unused` is exported, but no module in this project uses it"
+        );
+        assert_eq!(lints[0].line, 8);
+    }
+}
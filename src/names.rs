@@ -0,0 +1,378 @@
+//! Name resolution: every name used as a value must be bound by an argument, a `let`, or a function (defined here or imported)
+//!
+//! Names live in a stack of scopes. The bottom scope holds the module's function table (including imports), and each function body gets a scope of its own holding its arguments and `let` bindings, in the order they're declared.
+
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::{AssignmentTypes, Grammar};
+use crate::parse::{rhs_node, FunctionData, Node};
+use crate::parse_expressions::Location;
+
+/// How to report a binding that shadows another name in scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shadowing {
+    Allow,
+    #[default]
+    Lint,
+    Deny,
+}
+
+impl FromStr for Shadowing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Shadowing::Allow),
+            "lint" => Ok(Shadowing::Lint),
+            "deny" => Ok(Shadowing::Deny),
+            _ => Err(format!(
+                "unknown shadowing level `{s}`, expected `allow`, `lint`, or `deny`"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Function,
+    Import,
+    Argument,
+    Variable,
+}
+
+impl NameKind {
+    fn describe(&self) -> &'static str {
+        match self {
+            NameKind::Function => "function",
+            NameKind::Import => "imported function",
+            NameKind::Argument => "argument",
+            NameKind::Variable => "variable",
+        }
+    }
+}
+
+/// Where a name in a scope was bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameBinding {
+    pub kind: NameKind,
+    /// `None` for imports, whose declaration is in another module
+    pub line: Option<usize>,
+    pub word: usize,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    names: BTreeMap<String, NameBinding>,
+}
+
+/// The names visible at one point in a module, innermost scope last
+#[derive(Debug)]
+pub struct SymbolTable {
+    scopes: Vec<Scope>,
+}
+
+impl SymbolTable {
+    /// A table holding just the module scope
+    pub fn new(nodes: &[Node], function_table: &BTreeMap<String, FunctionData>) -> SymbolTable {
+        let mut module = Scope::default();
+        for (name, data) in function_table {
+            let binding = match data.origin {
+                Some(_) => NameBinding {
+                    kind: NameKind::Import,
+                    line: None,
+                    word: 0,
+                },
+                None => NameBinding {
+                    kind: NameKind::Function,
+                    line: function_line(nodes, name),
+                    word: 1,
+                },
+            };
+            module.names.insert(name.clone(), binding);
+        }
+        SymbolTable {
+            scopes: vec![module],
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Leave the innermost scope (the module scope is never left)
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&NameBinding> {
+        self.scopes.iter().rev().find_map(|s| s.names.get(name))
+    }
+
+    /// Bind a name in the innermost scope, returning the binding it shadows (if any)
+    pub fn bind(&mut self, name: &str, binding: NameBinding) -> Option<NameBinding> {
+        let shadowed = self.lookup(name).copied();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.names.insert(name.to_string(), binding);
+        }
+        shadowed
+    }
+
+    /// The visible name closest to a misspelled one, if any is close enough to be a likely typo
+    pub fn suggest(&self, name: &str) -> Option<String> {
+        closest_name(name, self.scopes.iter().flat_map(|s| s.names.keys()))
+    }
+}
+
+/// The candidate closest to a misspelled name, if any is close enough to be a likely typo
+pub fn closest_name<'a>(
+    name: &str,
+    candidates: impl Iterator<Item = &'a String>,
+) -> Option<String> {
+    let threshold = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min()
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// The line a function in this module is declared on
+fn function_line(nodes: &[Node], name: &str) -> Option<usize> {
+    nodes.iter().find_map(|node| match &node.grammar {
+        Grammar::Function(g) if g.fn_name == name => Some(node.source_line),
+        _ => None,
+    })
+}
+
+/// Levenshtein distance between two names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Resolve every name used as a value, and report bindings that shadow another name
+pub fn resolve_names(
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+    shadowing: Shadowing,
+) -> Vec<CompilerProblem> {
+    let mut problems: Vec<CompilerProblem> = Vec::new();
+    let mut table = SymbolTable::new(nodes, function_table);
+    // Right hand sides of `let`s are resolved before the names they bind
+    let mut resolved: HashSet<usize> = HashSet::new();
+    let mut in_function = false;
+    for (index, node) in nodes.iter().enumerate() {
+        if node.parent_node_line.is_none() && in_function {
+            table.pop_scope();
+            in_function = false;
+        }
+        match &node.grammar {
+            Grammar::Function(g) => {
+                table.push_scope();
+                in_function = true;
+                for (position, argument) in g.arguments.iter().enumerate() {
+                    let binding = NameBinding {
+                        kind: NameKind::Argument,
+                        line: Some(node.source_line),
+                        word: g.argument_words.get(position).copied().unwrap_or(0),
+                    };
+                    let shadowed = table.bind(&argument.name, binding);
+                    problems.extend(shadowing_problem(
+                        &argument.name,
+                        binding,
+                        shadowed,
+                        shadowing,
+                    ));
+                }
+            }
+            Grammar::VariableAssignment(g) => {
+                if let Some(position) = &g.index {
+                    problems.extend(unresolved_names(&table, position.symbols()));
+                }
+                if g.assignment_type != AssignmentTypes::Initialize {
+                    continue;
+                }
+                if let Some(object) = rhs_node(nodes, index).and_then(|n| n.expression.as_ref()) {
+                    problems.extend(unresolved_names(&table, object.symbols()));
+                    resolved.insert(index + 1);
+                }
+                let names = match &g.destructure {
+                    Some(names) => names.clone(),
+                    None => vec![g.name.clone()],
+                };
+                for name in names {
+                    let binding = NameBinding {
+                        kind: NameKind::Variable,
+                        line: Some(node.source_line),
+                        word: 1,
+                    };
+                    let shadowed = table.bind(&name, binding);
+                    problems.extend(shadowing_problem(&name, binding, shadowed, shadowing));
+                }
+            }
+            _ => {
+                if resolved.contains(&index) {
+                    continue;
+                }
+                if let Some(object) = &node.expression {
+                    problems.extend(unresolved_names(&table, object.symbols()));
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Report the names that aren't in scope, suggesting similar names that are
+fn unresolved_names(table: &SymbolTable, symbols: Vec<(String, Location)>) -> Vec<CompilerProblem> {
+    symbols
+        .into_iter()
+        .filter(|(name, _)| table.lookup(name).is_none())
+        .map(|(name, location)| {
+            let hint = match table.suggest(&name) {
+                Some(suggestion) => format!("did you mean `{suggestion}`?"),
+                None => {
+                    format!("declare `{name}` with `let`, take it as an argument, or import it")
+                }
+            };
+            CompilerProblem::new(
+                ProblemClass::Error,
+                &format!("cannot find `{name}` in this scope"),
+                &hint,
+                location.line,
+                location.word,
+            )
+        })
+        .collect()
+}
+
+fn shadowing_problem(
+    name: &str,
+    binding: NameBinding,
+    shadowed: Option<NameBinding>,
+    shadowing: Shadowing,
+) -> Option<CompilerProblem> {
+    let class = match shadowing {
+        Shadowing::Allow => return None,
+        Shadowing::Lint => ProblemClass::Lint,
+        Shadowing::Deny => ProblemClass::Error,
+    };
+    let shadowed = shadowed?;
+    let problem = CompilerProblem::new(
+        class,
+        &format!("`{name}` shadows the {} `{name}`", shadowed.kind.describe()),
+        "rename one of them, or pass `--shadowing=allow` to allow shadowing",
+        binding.line.unwrap_or(0),
+        binding.word,
+    );
+    Some(match shadowed.line {
+        Some(line) => problem.with_related(
+            &format!("the {} `{name}` is bound here", shadowed.kind.describe()),
+            line,
+            shadowed.word,
+        ),
+        None => problem,
+    })
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::{compute_scopes, parse, parse_node_expressions, populate_function_table};
+
+    fn resolve(code: &str, shadowing: Shadowing) -> Vec<CompilerProblem> {
+        let (mut nodes, _) = parse(lex(code));
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        resolve_names(&nodes, &function_table, shadowing)
+    }
+
+    #[test]
+    fn resolve_names_undefined() {
+        let code: &str = "fn add :: a int -> b int -> int {
+            #Properties :: Pure
+            let total :: int = + a b
+            return + totl c
+        }";
+        let problems = resolve(code, Shadowing::Lint);
+        println!("{:#?}", problems);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].message, "cannot find `totl` in this scope");
+        assert_eq!(problems[0].line, 3);
+        assert_eq!(problems[1].message, "cannot find `c` in this scope");
+        assert_eq!(problems[1].word_index, 3);
+    }
+
+    #[test]
+    fn resolve_names_in_order() {
+        // A `let` can't use the name it binds, or names bound after it
+        let code: &str = "fn main :: void {
+            let a :: int = + a 1
+            let b :: int = c
+            let c :: int = 2
+        }
+        fn other :: void {
+            let d :: int = c
+        }";
+        let problems = resolve(code, Shadowing::Lint);
+        println!("{:#?}", problems);
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "cannot find `a` in this scope",
+                "cannot find `c` in this scope",
+                "cannot find `c` in this scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_names_shadowing() {
+        let code: &str = "fn helper :: int {
+            return 1
+        }
+        fn main :: x int -> void {
+            let x :: int = 2
+            let helper :: int = 3
+        }";
+        let problems = resolve(code, Shadowing::Lint);
+        println!("{:#?}", problems);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].class, ProblemClass::Lint);
+        assert_eq!(problems[0].message, "`x` shadows the argument `x`");
+        assert_eq!(problems[0].related.as_ref().unwrap().line, 3);
+        assert_eq!(
+            problems[1].message,
+            "`helper` shadows the function `helper`"
+        );
+        assert!(resolve(code, Shadowing::Allow).is_empty());
+        assert!(resolve(code, Shadowing::Deny)
+            .iter()
+            .all(|p| p.class == ProblemClass::Error));
+    }
+
+    #[test]
+    fn suggest_names() {
+        assert_eq!(edit_distance("totl", "total"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -4,14 +4,19 @@
 //!
 //! We represent our AST as a flat list of `Nodes`, and each `Node` is assigned a Grammar and some metadata.
 
-use std::collections::BTreeMap;
-use std::fmt::Debug;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Debug, Display};
 
 use crate::compiler_errors::{CompilerProblem, ProblemClass};
-use crate::grammars::Grammar;
+use crate::grammars::{
+    AssignmentTypes, Grammar, GrammarAttenuation, GrammarFunctionDeclaration,
+    GrammarVariableAssignments,
+};
 use crate::lex::{Symbol, Token, VALID_EXPRESSION_TOKENS};
-use crate::parse_expressions::Object;
-use crate::permissions::Permissions;
+use crate::parse_expressions::{
+    parse_expression, parse_index, Expression, Literal, Location, Object, Operator,
+};
+use crate::permissions::{Permissions, Trusted};
 use crate::properties::Properties;
 
 /// Nodes are objects corresponding to an IR, and each node has exactly one type (each line of code has one effect, or "role" to play).
@@ -20,25 +25,31 @@ use crate::properties::Properties;
 ///
 /// - FunctionDeclaration: a function declaration is its name and type signature
 /// - PropertyDeclaration: a list of properties required by the function
+/// - TrustedDeclaration: permissions the function absorbs, so its callers don't need them (`#Trusted`)
 /// - ContractDeclaration: some runtime behavior the fn must obey
+/// - Attenuation: opening a `with_permissions [..] {` block, which restricts what the calls inside it may do
 /// - VariableAssignment: initializing or changing a variable with `let` / `set`
 /// - FunctionInvocation: calling some function
 /// - TypeDeclaration: creating a new type
 /// - EffectualFunctionInvocation: some fn call without let/set/return (i.e. it exists only for whatever side effect is triggered by calling it)
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeType {
-    Comment,                     // done
-    FunctionDeclaration,         // done
-    PropertyDeclaration,         // done
-    PermissionsDeclaration,      // done
-    ContractDeclaration,         // TODO
-    VariableAssignment,          // done
-    TypeDeclaration,             // newtype, TODO
-    Expression,                  // TODO
-    ImportStatement,             // done
-    ReturnStatement,             // done
-    CloseScope,                  // done
-    Empty,                       // done
+    Comment,                // done
+    FunctionDeclaration,    // done
+    PropertyDeclaration,    // done
+    PermissionsDeclaration, // done
+    TrustedDeclaration,     // done
+    CustomPermission,       // done
+    ContractDeclaration,    // TODO
+    VariableAssignment,     // done
+    TypeDeclaration,        // newtype, TODO
+    Expression,             // TODO
+    ImportStatement,        // done
+    ReturnStatement,        // done
+    CloseScope,             // done
+    Attenuation,            // done
+    CloseBlock,             // done
+    Empty,                  // done
 }
 
 /// Primitive data types (i.e. types not held in a container or struct)
@@ -63,13 +74,138 @@ impl PrimitiveDataType {
         }
     }
 
-    pub fn to_str(&self) -> &str {
+    /// The type's name in generated C code (`int` is 64-bit, like its literals)
+    pub fn to_str(self) -> &'static str {
         match self {
             PrimitiveDataType::Void => "void",
             PrimitiveDataType::Bool => "bool",
-            PrimitiveDataType::Int => "int",
+            PrimitiveDataType::Int => "long",
             PrimitiveDataType::Float => "float",
-            PrimitiveDataType::Str => "char",
+            PrimitiveDataType::Str => "char*",
+        }
+    }
+
+    /// The type's name in Iona source code
+    pub fn name(self) -> &'static str {
+        match self {
+            PrimitiveDataType::Int => "int",
+            PrimitiveDataType::Str => "str",
+            _ => self.to_str(),
+        }
+    }
+}
+
+/// Data types, including compound types built out of primitives
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DataType {
+    Primitive(PrimitiveDataType),
+    Tuple(Vec<DataType>),
+    /// Only arguments can be functions, see `FunctionType`
+    Function(Box<FunctionType>),
+}
+
+/// The type of a function passed as an argument, like `(int -> str -> bool :: P)`
+///
+/// The permission variable stands for whatever the function passed in requires. A function taking it must declare the variable (`#Permissions :: P`) to call it, which makes its callers need the passed function's permissions instead.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FunctionType {
+    pub args: Vec<DataType>,
+    pub return_type: DataType,
+    /// `None` if the function can't require any permissions
+    pub permissions: Option<String>,
+}
+
+impl DataType {
+    /// Number of values held by this type (1 for everything except tuples)
+    pub fn arity(&self) -> usize {
+        match self {
+            DataType::Primitive(_) | DataType::Function(_) => 1,
+            DataType::Tuple(elements) => elements.len(),
+        }
+    }
+
+    /// The permission variable of a function type
+    pub fn permission_variable(&self) -> Option<&str> {
+        match self {
+            DataType::Function(function) => function.permissions.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether a value of type `other` can be used where this type is expected
+    ///
+    /// Function types only need the same arguments and return type: what permission variables stand for is checked with the permissions
+    pub fn accepts(&self, other: &DataType) -> bool {
+        match (self, other) {
+            (DataType::Function(a), DataType::Function(b)) => {
+                a.return_type == b.return_type && a.args == b.args
+            }
+            _ => self == other,
+        }
+    }
+
+    /// A name that is unique per type and safe to use as an identifier in generated code
+    pub fn mangle(&self) -> String {
+        match self {
+            DataType::Primitive(p) => p.name().to_string(),
+            DataType::Tuple(elements) => format!(
+                "tuple{}_{}",
+                elements.len(),
+                elements
+                    .iter()
+                    .map(|e| e.mangle())
+                    .collect::<Vec<String>>()
+                    .join("_")
+            ),
+            DataType::Function(function) => format!(
+                "fn{}_{}_{}",
+                function.args.len(),
+                function
+                    .args
+                    .iter()
+                    .map(|a| a.mangle())
+                    .collect::<Vec<String>>()
+                    .join("_"),
+                function.return_type.mangle()
+            ),
+        }
+    }
+}
+
+impl From<PrimitiveDataType> for DataType {
+    fn from(p: PrimitiveDataType) -> Self {
+        DataType::Primitive(p)
+    }
+}
+
+impl PartialEq<PrimitiveDataType> for DataType {
+    fn eq(&self, other: &PrimitiveDataType) -> bool {
+        *self == DataType::Primitive(*other)
+    }
+}
+
+/// Prints types the way they are written in Iona source code, e.g. `(int, str)`
+impl Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Primitive(p) => write!(f, "{}", p.name()),
+            DataType::Tuple(elements) => write!(
+                f,
+                "({})",
+                elements
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            DataType::Function(function) => {
+                let args: Vec<String> = function.args.iter().map(|a| a.to_string()).collect();
+                write!(f, "({} -> {}", args.join(" -> "), function.return_type)?;
+                match &function.permissions {
+                    Some(variable) => write!(f, " :: {variable})"),
+                    None => write!(f, ")"),
+                }
+            }
         }
     }
 }
@@ -111,7 +247,7 @@ impl Node {
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String,
-    pub data_type: PrimitiveDataType,
+    pub data_type: DataType,
     pub value: Option<Box<dyn Data>>,
 }
 
@@ -140,7 +276,7 @@ pub fn parse(tokens: Vec<Token>) -> (Vec<Node>, Vec<CompilerProblem>) {
         // Map the appropriate grammar to that line of tokens, and accumulate any errors
         let mut grammar: Grammar = match token.symbol {
             // Handle imports
-            Symbol::Import => {
+            Symbol::Import | Symbol::Export => {
                 node_type = NodeType::ImportStatement;
                 Grammar::new(token.symbol)
             }
@@ -159,6 +295,16 @@ pub fn parse(tokens: Vec<Token>) -> (Vec<Node>, Vec<CompilerProblem>) {
                 node_type = NodeType::PermissionsDeclaration;
                 Grammar::new(token.symbol)
             }
+            // Handle permissions a function absorbs
+            Symbol::TrustedDeclaration => {
+                node_type = NodeType::TrustedDeclaration;
+                Grammar::new(token.symbol)
+            }
+            // Handle declarations of custom permissions
+            Symbol::PermissionDeclare => {
+                node_type = NodeType::CustomPermission;
+                Grammar::new(token.symbol)
+            }
             // Handle variable declarations
             Symbol::Set | Symbol::Let => {
                 node_type = NodeType::VariableAssignment;
@@ -169,6 +315,11 @@ pub fn parse(tokens: Vec<Token>) -> (Vec<Node>, Vec<CompilerProblem>) {
                 node_type = NodeType::ContractDeclaration;
                 Grammar::new(token.symbol)
             }
+            // Handle blocks restricting permissions
+            Symbol::WithPermissions => {
+                node_type = NodeType::Attenuation;
+                Grammar::new(token.symbol)
+            }
             // Handle return statements
             Symbol::Return => {
                 node_type = NodeType::ReturnStatement;
@@ -201,34 +352,32 @@ pub fn parse(tokens: Vec<Token>) -> (Vec<Node>, Vec<CompilerProblem>) {
         // We will get 1 "error" per token (error can be None!)
         let mut errors: Vec<Option<CompilerProblem>> = Vec::new();
         // The expression parser needs to see our current value
-        match grammar {
-            Grammar::Expression(ref mut g) => {
-                errors.push(g.step(token));
-            },
-            _ => {}
+        if let Grammar::Expression(ref mut g) = grammar {
+            errors.push(g.step(token));
         }
+        // Count how many tokens past the current one the grammar consumed
+        let mut consumed: usize = 0;
         let future = iterator.clone().peekable();
         for t in future {
             // Loop until the grammar finishes
             if !grammar.is_done() {
                 errors.push(grammar.step(t));
+                consumed += 1;
             } else {
                 break;
             }
         }
         // Then force the iterator to catch up
-        if errors.len() > 1 {
-            iterator.nth(errors.len().saturating_sub(1));
+        if consumed > 0 {
+            iterator.nth(consumed - 1);
         }
         // Check for errors (this happens after skip because consumes iterator)
         let mut okay = true;
-        for e in errors {
-            if let Some(problem) = e {
-                if problem.class == ProblemClass::Error {
-                    okay = false;
-                }
-                error_list.push(problem);
+        for problem in errors.into_iter().flatten() {
+            if problem.class == ProblemClass::Error {
+                okay = false;
             }
+            error_list.push(problem);
         }
         if okay {
             nodes.push(Node::new(node_type, grammar, token.line));
@@ -239,21 +388,41 @@ pub fn parse(tokens: Vec<Token>) -> (Vec<Node>, Vec<CompilerProblem>) {
 }
 
 /// Data contained within the function table for easy type checking
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FunctionData {
     pub args: Vec<Variable>,
-    pub return_type: PrimitiveDataType,
+    pub return_type: DataType,
     pub properties: Vec<Properties>,
     pub permissions: Vec<Permissions>,
+    /// The permission variables of its function arguments that it declares, so it may call those functions
+    pub permission_variables: Vec<String>,
+    /// Permissions it holds without passing them on to its callers, see `trusted`
+    pub trusted: Vec<Trusted>,
+    /// Where this function was imported from, or `None` if it's defined in this module
+    pub origin: Option<Origin>,
+}
+
+/// Where an imported function is defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The module defining the function (not the module re-exporting it)
+    pub module: String,
+    /// The function's name in that module, which differs from its name here when it's imported under an alias
+    pub name: String,
+    /// Whether this module re-exports the function to the modules importing it
+    pub reexported: bool,
 }
 
 impl FunctionData {
     pub fn new() -> FunctionData {
         FunctionData {
             args: Vec::new(),
-            return_type: PrimitiveDataType::Void,
+            return_type: DataType::Primitive(PrimitiveDataType::Void),
             properties: Vec::new(),
             permissions: Vec::new(),
+            permission_variables: Vec::new(),
+            trusted: Vec::new(),
+            origin: None,
         }
     }
 
@@ -262,15 +431,72 @@ impl FunctionData {
     }
 }
 
+/// Whether a name in a `#Permissions` list is a permission variable bound by one of the function's arguments
+pub fn is_permission_variable(args: &[Variable], permission: &Permissions) -> bool {
+    match permission {
+        Permissions::Custom(name) => args
+            .iter()
+            .any(|arg| arg.data_type.permission_variable() == Some(name.as_str())),
+        _ => false,
+    }
+}
+
+/// The arguments of a function that are functions themselves, as entries for a function table so they can be called
+pub fn callable_arguments(args: &[Variable]) -> Vec<(String, FunctionData)> {
+    args.iter()
+        .filter_map(|arg| match &arg.data_type {
+            DataType::Function(function) => {
+                let mut data = FunctionData::new();
+                data.args = function
+                    .args
+                    .iter()
+                    .enumerate()
+                    .map(|(position, data_type)| Variable {
+                        name: format!("_{position}"),
+                        data_type: data_type.clone(),
+                        value: None,
+                    })
+                    .collect();
+                data.return_type = function.return_type.clone();
+                Some((arg.name.clone(), data))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 // -------------------- AST Post Processing --------------------
 
 /// Get the scopes of various objects in the AST
 pub fn compute_scopes(nodes: &mut Vec<Node>) -> Vec<CompilerProblem> {
     let mut scope_depth: usize = 0;
     let mut last_seen_scope_line: usize = 0;
+    // Blocks open inside the current function (they aren't scopes for names, so their nodes still belong to the function)
+    let mut open_blocks: usize = 0;
     let mut errors: Vec<CompilerProblem> = Vec::new();
     for node in nodes {
         match node.node_type {
+            NodeType::Attenuation => {
+                if scope_depth == 0 {
+                    errors.push(CompilerProblem::new(
+                        ProblemClass::Error,
+                        "`with_permissions` blocks can only be used inside a function",
+                        "move the block into the function making the calls",
+                        node.source_line,
+                        0,
+                    ));
+                } else {
+                    node.parent_node_line = Some(last_seen_scope_line);
+                    open_blocks += 1;
+                    scope_depth += 1;
+                }
+            }
+            NodeType::CloseScope if open_blocks > 0 => {
+                node.node_type = NodeType::CloseBlock;
+                node.parent_node_line = Some(last_seen_scope_line);
+                open_blocks -= 1;
+                scope_depth -= 1;
+            }
             NodeType::FunctionDeclaration => {
                 if scope_depth > 0 {
                     errors.push(CompilerProblem::new(ProblemClass::Error, "issue with function declaration: either there's an unclosed scope or you tried to declare one function inside another", "check for missing braces `}`, and don't try to declare a nested function", node.source_line, 0));
@@ -305,13 +531,10 @@ pub fn populate_function_table(
         if node.node_type == NodeType::FunctionDeclaration {
             data = Some(FunctionData::new());
             function_line = node.source_line;
-            match &node.grammar {
-                Grammar::Function(fg) => {
-                    data.as_mut().unwrap().args = fg.arguments.clone();
-                    data.as_mut().unwrap().return_type = fg.return_type;
-                    function_name = Some(fg.fn_name.clone());
-                }
-                _ => {}
+            if let Grammar::Function(fg) = &node.grammar {
+                data.as_mut().unwrap().args = fg.arguments.clone();
+                data.as_mut().unwrap().return_type = fg.return_type.clone();
+                function_name = Some(fg.fn_name.clone());
             }
         } else {
             // We can assume every property is declared after a fn unless there's a syntax error
@@ -328,8 +551,17 @@ pub fn populate_function_table(
                         ));
                     }
                 },
+                // Names bound by the function's arguments are permission variables, not permissions
                 Grammar::Permission(pg) => match data {
-                    Some(ref mut d) => d.permissions = pg.p_list.clone(),
+                    Some(ref mut d) => {
+                        let (variables, permissions): (Vec<Permissions>, Vec<Permissions>) = pg
+                            .p_list
+                            .iter()
+                            .cloned()
+                            .partition(|p| is_permission_variable(&d.args, p));
+                        d.permissions = permissions;
+                        d.permission_variables = variables.iter().map(|p| p.to_string()).collect();
+                    }
                     None => {
                         errors.push(CompilerProblem::new(
                             ProblemClass::Error,
@@ -340,27 +572,513 @@ pub fn populate_function_table(
                         ));
                     }
                 },
+                Grammar::Trusted(tg) => match data {
+                    Some(ref mut d) => d.trusted.push(Trusted {
+                        permissions: tg.permissions.clone(),
+                        justification: tg.justification.clone(),
+                        line: node.source_line,
+                    }),
+                    None => {
+                        errors.push(CompilerProblem::new(
+                            ProblemClass::Error,
+                            "trusted permissions declared outside of function",
+                            "make sure `#Trusted` is inside the function absorbing the permissions",
+                            node.source_line,
+                            0,
+                        ));
+                    }
+                },
                 _ => {}
             }
             // If we see a scope closure corresponding to our function, then package up our data
             if node.node_type == NodeType::CloseScope
                 && node.parent_node_line == Some(function_line)
             {
-                if data.is_some() {
-                    table.insert(function_name.clone().unwrap(), data.unwrap());
+                if let Some(d) = data {
+                    table.insert(function_name.clone().unwrap(), d);
                 }
                 data = None;
                 function_name = None;
             }
         }
     }
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         Err(errors)
     } else {
         Ok(table)
     }
 }
 
+/// Parse the expression held by every expression node (and the index of every `set x @ i`)
+///
+/// This has to wait for the function table, because we need to know how many arguments each function takes
+pub fn parse_node_expressions(
+    nodes: &mut [Node],
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Vec<CompilerProblem> {
+    let mut errors: Vec<CompilerProblem> = Vec::new();
+    // Arguments that are functions can be called inside their function, like any other function
+    // The table extended with them is built once per parent, not once per node
+    let mut current: Option<usize> = None;
+    let mut extended: Option<BTreeMap<String, FunctionData>> = None;
+    for index in 0..nodes.len() {
+        let parent_line = nodes[index].parent_node_line;
+        if parent_line != current {
+            current = parent_line;
+            let callable = enclosing_function(nodes, index)
+                .map(|f| callable_arguments(&f.arguments))
+                .unwrap_or_default();
+            extended = match callable.is_empty() {
+                true => None,
+                false => Some(function_table.clone().into_iter().chain(callable).collect()),
+            };
+        }
+        let function_table = extended.as_ref().unwrap_or(function_table);
+        let node = &mut nodes[index];
+        match &mut node.grammar {
+            Grammar::Expression(g) => match parse_expression(&g.tokens, function_table) {
+                Ok(object) => node.expression = Some(object),
+                Err(e) => errors.push(e),
+            },
+            Grammar::VariableAssignment(g) if !g.index_tokens.is_empty() => {
+                match parse_index(&g.index_tokens, function_table) {
+                    Ok(index) => g.index = Some(index),
+                    Err(e) => errors.push(e),
+                }
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// Get the node holding the right hand side of a `let`, `set`, or `return` node
+///
+/// The grammar for those lines stops at `=` (or `return`), so the rest of the line is parsed into the following node
+pub fn rhs_node(nodes: &[Node], index: usize) -> Option<&Node> {
+    let node = nodes.get(index)?;
+    let next = nodes.get(index + 1)?;
+    if next.source_line == node.source_line && next.node_type == NodeType::Expression {
+        Some(next)
+    } else {
+        None
+    }
+}
+
+/// Check that destructuring `let`s bind one name per element of the value they destructure
+///
+/// The declared type is checked by the grammar, here we check the value itself (tuple literals, and calls to functions that return tuples)
+pub fn check_destructuring(
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Vec<CompilerProblem> {
+    let mut errors: Vec<CompilerProblem> = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let (pattern, names) = match &node.grammar {
+            Grammar::VariableAssignment(g) => match &g.destructure {
+                Some(names) => (&g.name, names),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let value = match rhs_node(nodes, index) {
+            Some(v) => v,
+            None => {
+                errors.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("`{pattern}` is never given a value"),
+                    &format!("destructure a tuple, like this: `let {pattern} = ...`"),
+                    node.source_line,
+                    0,
+                ));
+                continue;
+            }
+        };
+        // Values that failed to parse have already been reported
+        let found: Option<(usize, String)> = match &value.expression {
+            Some(Object::Tuple(elements, _)) => Some((
+                elements.len(),
+                format!("the tuple has {} elements", elements.len()),
+            )),
+            Some(Object::Operation(Expression::Prefix {
+                op: Operator::Function { name },
+                ..
+            })) => function_table.get(name).map(|f| {
+                (
+                    f.return_type.arity(),
+                    format!("`{name}` returns `{}`", f.return_type),
+                )
+            }),
+            // Names are tuples if they were declared (or inferred) as one
+            Some(Object::Value(Literal::Symbol(name), _)) => declared_type(nodes, index, name)
+                .map(|t| (t.arity(), format!("`{name}` has type `{t}`"))),
+            Some(Object::Value(..)) => Some((1, "a single value is not a tuple".to_string())),
+            _ => None,
+        };
+        if let Some((arity, explanation)) = found {
+            if arity != names.len() {
+                let location = value.expression.as_ref().map(|e| e.location());
+                errors.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!(
+                        "`{pattern}` binds {} names, but the value has {arity} element(s)",
+                        names.len()
+                    ),
+                    &format!("{explanation}, bind exactly one name per element"),
+                    location.map_or(node.source_line, |l| l.line),
+                    location.map_or(0, |l| l.word),
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// Find the declaration of the function a node belongs to
+pub fn enclosing_function(nodes: &[Node], index: usize) -> Option<&GrammarFunctionDeclaration> {
+    let parent_line = nodes.get(index)?.parent_node_line?;
+    nodes[..index].iter().rev().find_map(|n| match &n.grammar {
+        Grammar::Function(g) if n.source_line == parent_line => Some(g),
+        _ => None,
+    })
+}
+
+/// The `with_permissions` blocks a node is inside, innermost first
+pub fn enclosing_blocks(nodes: &[Node], index: usize) -> Vec<(&Node, &GrammarAttenuation)> {
+    let mut blocks: Vec<(&Node, &GrammarAttenuation)> = Vec::new();
+    // Blocks closed before the node don't contain it
+    let mut closed: usize = 0;
+    for node in nodes[..index].iter().rev() {
+        match (&node.node_type, &node.grammar) {
+            (NodeType::FunctionDeclaration, _) => break,
+            (NodeType::CloseBlock, _) => closed += 1,
+            (NodeType::Attenuation, Grammar::Attenuation(g)) => {
+                if closed == 0 {
+                    blocks.push((node, g));
+                } else {
+                    closed -= 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Every function call in a module, with the index of the node it's made from
+///
+/// Call `enclosing_function` with the node index to find out which function makes the call
+pub fn function_calls(nodes: &[Node]) -> Vec<(usize, String, Location)> {
+    let mut calls: Vec<(usize, String, Location)> = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let objects = [
+            node.expression.as_ref(),
+            match &node.grammar {
+                Grammar::VariableAssignment(g) => g.index.as_ref(),
+                _ => None,
+            },
+        ];
+        for object in objects.into_iter().flatten() {
+            for (name, location) in object.function_calls() {
+                calls.push((index, name, location));
+            }
+        }
+    }
+    calls
+}
+
+/// The arguments of a call found by `function_calls`
+pub fn call_arguments(nodes: &[Node], index: usize, location: Location) -> Option<&[Object]> {
+    let node = nodes.get(index)?;
+    let index_object = match &node.grammar {
+        Grammar::VariableAssignment(g) => g.index.as_ref(),
+        _ => None,
+    };
+    [node.expression.as_ref(), index_object]
+        .into_iter()
+        .flatten()
+        .find_map(|object| object.call_arguments(location))
+}
+
+/// Where a name visible from a node was bound: a function argument, or an earlier `let`
+#[derive(Debug, Clone)]
+pub struct Binding {
+    /// `None` if the type was left to inference
+    pub data_type: Option<DataType>,
+    /// The line of the `let`, or of the function declaration for arguments
+    pub line: usize,
+    pub mutable: bool,
+    pub is_argument: bool,
+    /// The type was worked out by inference rather than written down
+    pub inferred: bool,
+}
+
+/// Find the binding a name refers to from a node (later bindings shadow earlier ones)
+pub fn find_binding(nodes: &[Node], index: usize, name: &str) -> Option<Binding> {
+    let function = enclosing_function(nodes, index)?;
+    let parent_line = nodes[index].parent_node_line;
+    let mut found: Option<Binding> =
+        function
+            .arguments
+            .iter()
+            .find(|arg| arg.name == name)
+            .map(|arg| Binding {
+                data_type: Some(arg.data_type.clone()),
+                line: parent_line.unwrap_or(0),
+                mutable: false,
+                is_argument: true,
+                inferred: false,
+            });
+    for node in nodes[..index].iter() {
+        if node.parent_node_line != parent_line {
+            continue;
+        }
+        if let Grammar::VariableAssignment(g) = &node.grammar {
+            if g.assignment_type != AssignmentTypes::Initialize {
+                continue;
+            }
+            let declared = if g.type_provided || g.inferred {
+                Some(g.data_type.clone())
+            } else {
+                None
+            };
+            let data_type = match &g.destructure {
+                Some(names) => match names.iter().position(|n| n == name) {
+                    Some(position) => match declared {
+                        Some(DataType::Tuple(elements)) => elements.get(position).cloned(),
+                        _ => None,
+                    },
+                    None => continue,
+                },
+                None if g.name == name => declared,
+                None => continue,
+            };
+            found = Some(Binding {
+                data_type,
+                line: node.source_line,
+                mutable: g.mutable,
+                is_argument: false,
+                inferred: g.inferred,
+            });
+        }
+    }
+    found
+}
+
+/// Look up the declared type of a name visible from a node: a function argument, or an earlier `let` with a type
+///
+/// Returns `None` if the name is unknown or its type was left to inference
+pub fn declared_type(nodes: &[Node], index: usize, name: &str) -> Option<DataType> {
+    find_binding(nodes, index, name)?.data_type
+}
+
+/// Check that the thing being indexed can be indexed by that index
+///
+/// `str` can be indexed by ints and ranges at runtime, while tuples must be indexed by an int literal we can bounds check now
+fn check_index_target(
+    target: &str,
+    target_type: &DataType,
+    index: &Object,
+) -> Option<CompilerProblem> {
+    let location = index.location();
+    let problem = |message: String, hint: &str| {
+        Some(CompilerProblem::new(
+            ProblemClass::Error,
+            &message,
+            hint,
+            location.line,
+            location.word,
+        ))
+    };
+    match target_type {
+        DataType::Primitive(PrimitiveDataType::Str) => None,
+        DataType::Primitive(_) | DataType::Function(_) => problem(
+            format!("cannot index into `{target}`, which has type `{target_type}`"),
+            "only `str` values and tuples can be indexed",
+        ),
+        DataType::Tuple(elements) => match index {
+            Object::Value(Literal::Int(n), _) => {
+                if (*n as usize) < elements.len() {
+                    None
+                } else {
+                    problem(
+                        format!(
+                            "index {n} is out of bounds for `{target}`, which has type `{target_type}` with {} elements",
+                            elements.len()
+                        ),
+                        &format!("the last element is at index {}", elements.len() - 1),
+                    )
+                }
+            }
+            Object::Range { .. } => problem(
+                format!("cannot take a range of the tuple `{target}`"),
+                "index each element of the tuple separately",
+            ),
+            _ => problem(
+                format!("the tuple `{target}` can only be indexed by a literal int"),
+                "tuple elements can have different types, so the index must be known at compile time",
+            ),
+        },
+    }
+}
+
+/// Recursively check every index operation inside an object
+fn check_indices_in_object(
+    nodes: &[Node],
+    index: usize,
+    function_table: &BTreeMap<String, FunctionData>,
+    object: &Object,
+    errors: &mut Vec<CompilerProblem>,
+) {
+    match object {
+        Object::Operation(Expression::Prefix { op, args, .. }) => {
+            if let (Operator::Index, [target, position]) = (op, args.as_slice()) {
+                let target_type = match target {
+                    Object::Value(Literal::Symbol(name), _) => {
+                        declared_type(nodes, index, name).map(|t| (name.clone(), t))
+                    }
+                    Object::Operation(Expression::Prefix {
+                        op: Operator::Function { name },
+                        ..
+                    }) => function_table
+                        .get(name)
+                        .map(|f| (name.clone(), f.return_type.clone())),
+                    _ => None,
+                };
+                if let Some((name, t)) = target_type {
+                    errors.extend(check_index_target(&name, &t, position));
+                }
+            }
+            for arg in args {
+                check_indices_in_object(nodes, index, function_table, arg, errors);
+            }
+        }
+        Object::Tuple(elements, _) => {
+            for element in elements {
+                check_indices_in_object(nodes, index, function_table, element, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check reads (`@ data 0`) and writes (`set data @ 0 = ...`) of indexed values
+pub fn check_indexing(
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Vec<CompilerProblem> {
+    let mut errors: Vec<CompilerProblem> = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        if let Grammar::VariableAssignment(g) = &node.grammar {
+            if let (Some(position), Some(t)) = (&g.index, declared_type(nodes, index, &g.name)) {
+                errors.extend(check_index_target(&g.name, &t, position));
+            }
+        }
+        if let Some(object) = &node.expression {
+            check_indices_in_object(nodes, index, function_table, object, &mut errors);
+        }
+    }
+    errors
+}
+
+/// The fix-it form of a `let`, with or without `mut`
+fn let_suggestion(g: &GrammarVariableAssignments, mutable: bool) -> String {
+    let data_type = if g.type_provided {
+        g.data_type.to_string()
+    } else {
+        "auto".to_string()
+    };
+    let mutability = if mutable { " mut" } else { "" };
+    format!("let {} :: {data_type}{mutability} = ...", g.name)
+}
+
+/// Check every `set` targets a declared, mutable variable, and lint `mut` variables that are never `set`
+pub fn check_mutability(nodes: &[Node]) -> Vec<CompilerProblem> {
+    let mut problems: Vec<CompilerProblem> = Vec::new();
+    let mut mutated: HashSet<usize> = HashSet::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let Grammar::VariableAssignment(g) = &node.grammar else {
+            continue;
+        };
+        if g.assignment_type != AssignmentTypes::Mutate || node.parent_node_line.is_none() {
+            continue;
+        }
+        let binding = match find_binding(nodes, index, &g.name) {
+            Some(binding) => binding,
+            None => {
+                problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("cannot assign to `{}`, which hasn't been declared", g.name),
+                    &format!("declare it first: `let {} :: auto mut = ...`", g.name),
+                    node.source_line,
+                    1,
+                ));
+                continue;
+            }
+        };
+        if binding.is_argument {
+            problems.push(
+                CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("cannot assign to the argument `{}`", g.name),
+                    &format!(
+                        "copy it into a mutable variable first: `let {}_copy :: auto mut = {}`",
+                        g.name, g.name
+                    ),
+                    node.source_line,
+                    1,
+                )
+                .with_related(
+                    &format!("`{}` is an argument of this function", g.name),
+                    binding.line,
+                    1,
+                ),
+            );
+        } else if !binding.mutable {
+            let hint = match nodes.iter().find(|n| n.source_line == binding.line) {
+                Some(Node {
+                    grammar: Grammar::VariableAssignment(declaration),
+                    ..
+                }) => format!("make it mutable: `{}`", let_suggestion(declaration, true)),
+                _ => "declare it with `mut`".to_string(),
+            };
+            problems.push(
+                CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("cannot assign to `{}`, which isn't mutable", g.name),
+                    &hint,
+                    node.source_line,
+                    1,
+                )
+                .with_related(
+                    &format!("`{}` is declared here", g.name),
+                    binding.line,
+                    1,
+                ),
+            );
+        } else {
+            mutated.insert(binding.line);
+        }
+    }
+    for node in nodes {
+        if let Grammar::VariableAssignment(g) = &node.grammar {
+            if g.assignment_type == AssignmentTypes::Initialize
+                && g.mutable
+                && !mutated.contains(&node.source_line)
+            {
+                problems.push(CompilerProblem::new(
+                    ProblemClass::Lint,
+                    &format!("`{}` is declared `mut`, but is never mutated", g.name),
+                    &format!("remove `mut`: `{}`", let_suggestion(g, false)),
+                    node.source_line,
+                    1,
+                ));
+            }
+        }
+    }
+    problems
+}
+
 // -------------------- Unit Tests --------------------
 
 #[cfg(test)]
@@ -393,9 +1111,8 @@ mod tests {
         assert_eq!(nodes[2].node_type, NodeType::ReturnStatement);
         assert_eq!(nodes[3].node_type, NodeType::Expression);
         assert_eq!(nodes[4].node_type, NodeType::CloseScope);
-        match &nodes[1].grammar {
-            Grammar::Function(g) => assert_eq!(g.fn_name, "five"),
-            _ => {}
+        if let Grammar::Function(g) = &nodes[1].grammar {
+            assert_eq!(g.fn_name, "five");
         }
     }
 
@@ -434,10 +1151,102 @@ mod tests {
         for (name, data) in function_table.iter() {
             println!("{name}: {:#?}", data);
         }
-        assert!(function_table.get("add").is_some());
+        assert!(function_table.contains_key("add"));
         assert_eq!(
             function_table.get("add").unwrap().return_type,
             PrimitiveDataType::Int
         );
     }
+
+    #[test]
+    fn check_destructuring_1() {
+        let code: &str = "fn divmod :: a int -> b int -> (int, int) {
+            return (1, 2)
+        }
+        fn main {
+            let (q, r) :: (int, int) = divmod 7 2
+            let (x, y, z) = divmod 7 2
+            let (u, v) = (1, 2, 3)
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let errors = check_destructuring(&nodes, &function_table);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 5);
+        assert_eq!(
+            errors[0].message,
+            "`(x, y, z)` binds 3 names, but the value has 2 element(s)"
+        );
+        assert_eq!(errors[1].line, 6);
+    }
+
+    #[test]
+    fn check_indexing_1() {
+        let code: &str = "fn pair :: (int, str) {
+            return (1, \"a\")
+        }
+        fn main :: name str -> count int -> void {
+            let p :: (int, str) = pair
+            let first :: str = @ name 0..2
+            let second :: str = @ p 1
+            let third :: int = @ p 2
+            set count @ 0 = 1
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let errors = check_indexing(&nodes, &function_table);
+        println!("{:#?}", errors);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].message,
+            "index 2 is out of bounds for `p`, which has type `(int, str)` with 2 elements"
+        );
+        assert_eq!(errors[0].line, 7);
+        assert_eq!(
+            errors[1].message,
+            "cannot index into `count`, which has type `int`"
+        );
+    }
+
+    #[test]
+    fn check_mutability_1() {
+        let code: &str = "fn main :: count int -> void {
+            let total :: int mut = 0
+            let fixed :: int = 1
+            let unused :: int mut = 2
+            set total = 3
+            set fixed = 4
+            set count = 5
+            set missing = 6
+        }";
+        let tokens = lex(code);
+        let (mut nodes, _) = parse(tokens);
+        compute_scopes(&mut nodes);
+        let problems = check_mutability(&nodes);
+        println!("{:#?}", problems);
+        assert_eq!(problems.len(), 4);
+        assert_eq!(
+            problems[0].message,
+            "cannot assign to `fixed`, which isn't mutable"
+        );
+        assert_eq!(problems[0].line, 5);
+        assert_eq!(problems[0].related.as_ref().unwrap().line, 2);
+        assert_eq!(problems[1].message, "cannot assign to the argument `count`");
+        assert_eq!(
+            problems[2].message,
+            "cannot assign to `missing`, which hasn't been declared"
+        );
+        assert_eq!(problems[3].class, ProblemClass::Lint);
+        assert_eq!(
+            problems[3].message,
+            "`unused` is declared `mut`, but is never mutated"
+        );
+        assert_eq!(problems[3].line, 3);
+    }
 }
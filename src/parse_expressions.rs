@@ -12,6 +12,7 @@ use std::collections::BTreeMap;
 
 use crate::compiler_errors::{CompilerProblem, ProblemClass};
 use crate::lex::{Symbol, Token};
+use crate::names::closest_name;
 use crate::parse::FunctionData;
 
 #[derive(Debug)]
@@ -22,6 +23,7 @@ pub enum Operator {
     Divide,
     Negate,
     Inverse,
+    Index,
     Function { name: String },
 }
 
@@ -34,6 +36,7 @@ impl Operator {
             Self::Divide => 30,
             Self::Negate => 40,
             Self::Inverse => 40,
+            Self::Index => 50,
             _ => 10,
         }
     }
@@ -44,6 +47,7 @@ impl Operator {
             Symbol::OpMinus => Some(Operator::Subtract),
             Symbol::OpMul => Some(Operator::Multiply),
             Symbol::OpDiv => Some(Operator::Divide),
+            Symbol::At => Some(Operator::Index),
             _ => None,
         }
     }
@@ -51,7 +55,11 @@ impl Operator {
 
 #[derive(Debug)]
 pub enum Expression {
-    Prefix { op: Operator, args: Vec<Object> },
+    Prefix {
+        op: Operator,
+        args: Vec<Object>,
+        location: Location,
+    },
     // Infix {
     //     left: Box<Object>,
     //     op: Operator,
@@ -75,6 +83,8 @@ pub enum Literal {
     Bool(bool),
     Str(String),
     Symbol(String),
+    /// A function passed by name rather than called, like `&sqrt`
+    Function(String),
 }
 
 impl Literal {
@@ -91,23 +101,31 @@ impl Literal {
                 return Ok(Literal::Str(text.to_string()));
             } else {
                 return Err(
-                    CompilerProblem::new(ProblemClass::Error, "a string literal had an unclosed parenthesis", "if this isn't a string, remove the opening parenthesis, otherwise, close the parenthesis", 0, 0).into()
+                    CompilerProblem::new(ProblemClass::Error, "a string literal had an unclosed parenthesis", "if this isn't a string, remove the opening parenthesis, otherwise, close the parenthesis", 0, 0)
                 );
             }
         } else if text.ends_with("\"") {
             return Err(
-                CompilerProblem::new(ProblemClass::Error, "a string literal had an unopened parenthesis", "if this isn't a string, remove the closing parenthesis, otherwise, close the parenthesis", 0, 0).into()
+                CompilerProblem::new(ProblemClass::Error, "a string literal had an unopened parenthesis", "if this isn't a string, remove the closing parenthesis, otherwise, close the parenthesis", 0, 0)
             );
         }
         // Handle integers
-        let maybe_int = text.parse::<i64>();
-        if maybe_int.is_ok() {
-            return Ok(Literal::Int(maybe_int.unwrap()));
+        if let Ok(int) = text.parse::<i64>() {
+            return Ok(Literal::Int(int));
         }
         // Handle floating point numbers
-        let maybe_float = text.parse::<f64>();
-        if maybe_float.is_ok() {
-            return Ok(Literal::Float(maybe_float.unwrap()));
+        if let Ok(float) = text.parse::<f64>() {
+            return Ok(Literal::Float(float));
+        }
+        // Handle functions passed to other functions, like `&shapes.area`
+        if let Some(name) = text.strip_prefix('&') {
+            if name.split('.').all(is_identifier) {
+                return Ok(Literal::Function(name.to_string()));
+            }
+        }
+        // Handle names (of variables, for instance), including qualified names like `shapes.area`
+        if text.split('.').all(is_identifier) {
+            return Ok(Literal::Symbol(text.to_string()));
         }
         // Return an error for everything else
         Err(CompilerProblem::new(
@@ -116,26 +134,251 @@ impl Literal {
             "check for syntax errors",
             0,
             0,
-        )
-        .into())
+        ))
+    }
+}
+
+/// Is this text usable as the name of a variable or function?
+pub fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Where an object starts in the source code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub word: usize,
+}
+
+impl Location {
+    pub fn of(token: &Token) -> Location {
+        Location {
+            line: token.line,
+            word: token.word,
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum Object {
     Operation(Expression),
-    Value(Literal),
+    Value(Literal, Location),
+    Tuple(Vec<Object>, Location),
+    /// `start..end` or `start..=end`, only valid as an index for now
+    Range {
+        start: Box<Object>,
+        end: Box<Object>,
+        inclusive: bool,
+        location: Location,
+    },
 }
 
+impl Object {
+    pub fn location(&self) -> Location {
+        match self {
+            Object::Operation(Expression::Prefix { location, .. }) => *location,
+            Object::Value(_, location) => *location,
+            Object::Tuple(_, location) => *location,
+            Object::Range { location, .. } => *location,
+        }
+    }
+
+    /// Every function called within this object, outermost first
+    ///
+    /// Functions passed by name (like `&sqrt`) count as calls too, since the function they're passed to may call them
+    pub fn function_calls(&self) -> Vec<(String, Location)> {
+        let mut calls: Vec<(String, Location)> = Vec::new();
+        self.collect_function_calls(&mut calls);
+        calls
+    }
+
+    /// The arguments of the function call made at a location inside this object
+    pub fn call_arguments(&self, at: Location) -> Option<&[Object]> {
+        match self {
+            Object::Operation(Expression::Prefix { op, args, location }) => {
+                if *location == at && matches!(op, Operator::Function { .. }) {
+                    return Some(args);
+                }
+                args.iter().find_map(|arg| arg.call_arguments(at))
+            }
+            Object::Tuple(elements, _) => elements.iter().find_map(|e| e.call_arguments(at)),
+            Object::Range { start, end, .. } => {
+                start.call_arguments(at).or_else(|| end.call_arguments(at))
+            }
+            Object::Value(..) => None,
+        }
+    }
+
+    /// Every name used as a value inside this object, with where it's used
+    pub fn symbols(&self) -> Vec<(String, Location)> {
+        let mut symbols: Vec<(String, Location)> = Vec::new();
+        self.collect_symbols(&mut symbols);
+        symbols
+    }
+
+    fn collect_symbols(&self, symbols: &mut Vec<(String, Location)>) {
+        match self {
+            Object::Value(Literal::Symbol(name) | Literal::Function(name), location) => {
+                symbols.push((name.clone(), *location));
+            }
+            Object::Value(..) => {}
+            Object::Operation(Expression::Prefix { args, .. }) => {
+                for arg in args {
+                    arg.collect_symbols(symbols);
+                }
+            }
+            Object::Tuple(elements, _) => {
+                for element in elements {
+                    element.collect_symbols(symbols);
+                }
+            }
+            Object::Range { start, end, .. } => {
+                start.collect_symbols(symbols);
+                end.collect_symbols(symbols);
+            }
+        }
+    }
+
+    fn collect_function_calls(&self, calls: &mut Vec<(String, Location)>) {
+        match self {
+            Object::Operation(Expression::Prefix { op, args, location }) => {
+                if let Operator::Function { name } = op {
+                    calls.push((name.clone(), *location));
+                }
+                for arg in args {
+                    arg.collect_function_calls(calls);
+                }
+            }
+            Object::Tuple(elements, _) => {
+                for element in elements {
+                    element.collect_function_calls(calls);
+                }
+            }
+            Object::Range { start, end, .. } => {
+                start.collect_function_calls(calls);
+                end.collect_function_calls(calls);
+            }
+            Object::Value(Literal::Function(name), location) => {
+                calls.push((name.clone(), *location));
+            }
+            Object::Value(..) => {}
+        }
+    }
+}
+
+/// Indices must be ints (or ranges of ints), which we can partially check while parsing
+pub fn check_index_value(index: &Object) -> Option<CompilerProblem> {
+    let location = index.location();
+    let found = match index {
+        Object::Value(Literal::Int(n), _) if *n < 0 => {
+            return Some(CompilerProblem::new(
+                ProblemClass::Error,
+                &format!("index cannot be negative, found `{n}`"),
+                "indices count up from 0",
+                location.line,
+                location.word,
+            ));
+        }
+        Object::Value(Literal::Float(_), _) => "a float",
+        Object::Value(Literal::Bool(_), _) => "a bool",
+        Object::Value(Literal::Str(_), _) => "a str",
+        Object::Tuple(..) => "a tuple",
+        _ => return None,
+    };
+    Some(CompilerProblem::new(
+        ProblemClass::Error,
+        &format!("expected an int or a range of ints as an index, found {found}"),
+        "indices should be either a number `37` or a range `0..2`",
+        location.line,
+        location.word,
+    ))
+}
+
+/// Build a range out of its two bounds, checking any literal bounds
+fn make_range(start: Object, end: Object, operator: &Token) -> Result<Object, CompilerProblem> {
+    for bound in [&start, &end] {
+        if !matches!(
+            bound,
+            Object::Value(Literal::Int(_), _) | Object::Value(Literal::Symbol(_), _)
+        ) {
+            let location = bound.location();
+            return Err(CompilerProblem::new(
+                ProblemClass::Error,
+                "the bounds of a range must be ints or the names of ints",
+                "ranges look like this: `0..2` (excludes 2) or `0..=2` (includes 2)",
+                location.line,
+                location.word,
+            ));
+        }
+    }
+    let inclusive = operator.symbol == Symbol::RangeInclusive;
+    if let (Object::Value(Literal::Int(a), _), Object::Value(Literal::Int(b), _)) = (&start, &end) {
+        if a > b || (a == b && !inclusive) {
+            return Err(CompilerProblem::new(
+                ProblemClass::Error,
+                &format!("the range `{a}{}{b}` is empty", operator.text),
+                "the start of a range must come before its end",
+                operator.line,
+                operator.word,
+            ));
+        }
+    }
+    Ok(Object::Range {
+        location: start.location(),
+        start: Box::new(start),
+        end: Box::new(end),
+        inclusive,
+    })
+}
+
+/// Ranges are only meaningful as indices (for now), so reject them anywhere else
+fn check_range_placement(object: &Object, is_index: bool) -> Option<CompilerProblem> {
+    match object {
+        Object::Range { location, .. } if !is_index => Some(CompilerProblem::new(
+            ProblemClass::Error,
+            "ranges can only be used to index into a collection",
+            "try something like `@ data 0..2`",
+            location.line,
+            location.word,
+        )),
+        Object::Operation(Expression::Prefix { op, args, .. }) => {
+            args.iter().enumerate().find_map(|(position, arg)| {
+                check_range_placement(arg, matches!(op, Operator::Index) && position == 1)
+            })
+        }
+        Object::Tuple(elements, _) => elements
+            .iter()
+            .find_map(|element| check_range_placement(element, false)),
+        _ => None,
+    }
+}
+
+/// Bookkeeping for a parenthesized group while we walk the tokens backwards
+struct ParenGroup {
+    /// Height of the stack when the group's `)` was seen
+    start: usize,
+    commas: usize,
+    line: usize,
+    word: usize,
+}
+
+/// `floor` is the lowest stack position a function may take arguments from (so calls can't reach outside their parentheses or tuple element)
 fn push_fn_to_stack(
     token: &Token,
     op: Operator,
     arg_count: usize,
+    floor: usize,
     stack: &mut Vec<Object>,
 ) -> Option<CompilerProblem> {
     let mut args: Vec<Object> = Vec::with_capacity(arg_count);
     // Do we have enough objects on the stack to satisfy the fn call?
-    if stack.len() < arg_count {
+    if stack.len().saturating_sub(floor) < arg_count {
         return Some(CompilerProblem::new(
             ProblemClass::Error,
             &format!("not enough arguments when calling function {}", &token.text),
@@ -145,16 +388,46 @@ fn push_fn_to_stack(
         ));
     }
     // Pop the last N objects off the stack and move them into the function's arguments (N == fn.args.len)
-    args.extend(stack.drain(stack.len() - arg_count..));
+    // The stack was built right-to-left, so reverse it to get the arguments back in source order
+    args.extend(stack.drain(stack.len() - arg_count..).rev());
+    if matches!(op, Operator::Index) {
+        if let Some(e) = check_index_value(&args[1]) {
+            return Some(e);
+        }
+    }
     // Finally, push this fn onto the stack
-    stack.push(Object::Operation(Expression::Prefix { op: op, args: args }));
+    stack.push(Object::Operation(Expression::Prefix {
+        op,
+        args,
+        location: Location::of(token),
+    }));
     None
 }
 
-/// Currently only supports prefix operations
+/// Currently only supports prefix operations, plus parentheses for grouping, tuple literals like `(1, 2)`, and ranges like `0..2`
 pub fn parse_expression(
-    tokens: &Vec<Token>,
+    tokens: &[Token],
+    fn_table: &BTreeMap<String, FunctionData>,
+) -> Result<Object, CompilerProblem> {
+    parse_tokens(tokens, fn_table, false)
+}
+
+/// Parse the index of an assignment like `set data @ 0..2 = ...`, which (unlike other expressions) may be a range
+pub fn parse_index(
+    tokens: &[Token],
     fn_table: &BTreeMap<String, FunctionData>,
+) -> Result<Object, CompilerProblem> {
+    let index = parse_tokens(tokens, fn_table, true)?;
+    match check_index_value(&index) {
+        Some(e) => Err(e),
+        None => Ok(index),
+    }
+}
+
+fn parse_tokens(
+    tokens: &[Token],
+    fn_table: &BTreeMap<String, FunctionData>,
+    is_index: bool,
 ) -> Result<Object, CompilerProblem> {
     // Sanity check
     if tokens.is_empty() {
@@ -168,30 +441,146 @@ pub fn parse_expression(
     }
     // We will push and pop objects/expressions onto a stack
     let mut stack: Vec<Object> = Vec::with_capacity(tokens.len());
-    // Iterate backwards over the tokens
-    for token in tokens.iter().rev() {
-        println!("token: {:#?}", token);
+    // Open parentheses (we see the `)` first since we iterate backwards) and the current floor of the stack
+    let mut groups: Vec<ParenGroup> = Vec::new();
+    let mut floor: usize = 0;
+    // Iterate backwards over the tokens (by position, since ranges consume the token to their left)
+    let mut position = tokens.len();
+    while position > 0 {
+        position -= 1;
+        let token = &tokens[position];
         match token.symbol {
+            Symbol::Range | Symbol::RangeInclusive => {
+                // Ranges are infix, and each bound is a single token: the end is already on the stack, the start is to our left
+                let end = match stack.pop() {
+                    Some(end) if stack.len() >= floor => end,
+                    _ => {
+                        return Err(CompilerProblem::new(
+                            ProblemClass::Error,
+                            &format!("the range `{}` has no end", token.text),
+                            "ranges look like this: `0..2` (excludes 2) or `0..=2` (includes 2)",
+                            token.line,
+                            token.word,
+                        ));
+                    }
+                };
+                let start_token = match position.checked_sub(1).map(|p| &tokens[p]) {
+                    Some(t) if t.symbol == Symbol::Value && !fn_table.contains_key(&t.text) => t,
+                    _ => {
+                        return Err(CompilerProblem::new(
+                            ProblemClass::Error,
+                            &format!("the range `{}` has no start", token.text),
+                            "ranges look like this: `0..2` (excludes 2) or `0..=2` (includes 2)",
+                            token.line,
+                            token.word,
+                        ));
+                    }
+                };
+                position -= 1;
+                let start = match Literal::from_str(&start_token.text) {
+                    Ok(lit) => Object::Value(lit, Location::of(start_token)),
+                    Err(mut e) => {
+                        e.line = start_token.line;
+                        e.word_index = start_token.word;
+                        return Err(e);
+                    }
+                };
+                stack.push(make_range(start, end, token)?);
+            }
+            Symbol::At => {
+                let outcome = push_fn_to_stack(token, Operator::Index, 2, floor, &mut stack);
+                if let Some(e) = outcome {
+                    return Err(e);
+                }
+            }
+            Symbol::ParenClose => {
+                groups.push(ParenGroup {
+                    start: stack.len(),
+                    commas: 0,
+                    line: token.line,
+                    word: token.word,
+                });
+                floor = stack.len();
+            }
+            Symbol::Comma => match groups.last_mut() {
+                Some(group) => {
+                    // Every element of a tuple must be exactly one value
+                    if stack.len() != group.start + group.commas + 1 {
+                        return Err(CompilerProblem::new(
+                            ProblemClass::Error,
+                            "each element of a tuple must be a single value",
+                            "check for a missing value or a missing function argument before this comma",
+                            token.line,
+                            token.word,
+                        ));
+                    }
+                    group.commas += 1;
+                    floor = stack.len();
+                }
+                None => {
+                    return Err(CompilerProblem::new(
+                        ProblemClass::Error,
+                        "found a `,` outside of parentheses",
+                        "tuple literals must be wrapped in parentheses, like this: `(1, 2)`",
+                        token.line,
+                        token.word,
+                    ));
+                }
+            },
+            Symbol::ParenOpen => {
+                let group = match groups.pop() {
+                    Some(g) => g,
+                    None => {
+                        return Err(CompilerProblem::new(
+                            ProblemClass::Error,
+                            "found a `(` without a matching `)`",
+                            "close this parenthesis",
+                            token.line,
+                            token.word,
+                        ));
+                    }
+                };
+                let count = stack.len() - group.start;
+                if count != group.commas + 1 {
+                    return Err(CompilerProblem::new(
+                        ProblemClass::Error,
+                        "parentheses must contain exactly one value per element",
+                        "check for a missing value, or a missing function argument",
+                        token.line,
+                        token.word,
+                    ));
+                }
+                if group.commas > 0 {
+                    // The stack holds the elements right-to-left
+                    let elements: Vec<Object> = stack.drain(group.start..).rev().collect();
+                    stack.push(Object::Tuple(elements, Location::of(token)));
+                }
+                // Restore the floor of the enclosing group (or element of the enclosing tuple)
+                floor = match groups.last() {
+                    Some(outer) => outer.start + outer.commas,
+                    None => 0,
+                };
+            }
             Symbol::OpPlus => {
-                let outcome = push_fn_to_stack(token, Operator::Add, 2, &mut stack);
+                let outcome = push_fn_to_stack(token, Operator::Add, 2, floor, &mut stack);
                 if let Some(e) = outcome {
                     return Err(e);
                 }
             }
             Symbol::OpMinus => {
-                let outcome = push_fn_to_stack(token, Operator::Subtract, 2, &mut stack);
+                let outcome = push_fn_to_stack(token, Operator::Subtract, 2, floor, &mut stack);
                 if let Some(e) = outcome {
                     return Err(e);
                 }
             }
             Symbol::OpMul => {
-                let outcome = push_fn_to_stack(token, Operator::Multiply, 2, &mut stack);
+                let outcome = push_fn_to_stack(token, Operator::Multiply, 2, floor, &mut stack);
                 if let Some(e) = outcome {
                     return Err(e);
                 }
             }
             Symbol::OpDiv => {
-                let outcome = push_fn_to_stack(token, Operator::Divide, 2, &mut stack);
+                let outcome = push_fn_to_stack(token, Operator::Divide, 2, floor, &mut stack);
                 if let Some(e) = outcome {
                     return Err(e);
                 }
@@ -207,6 +596,7 @@ pub fn parse_expression(
                             name: token.text.clone(),
                         },
                         arg_count,
+                        floor,
                         &mut stack,
                     );
                     if let Some(e) = outcome {
@@ -216,7 +606,7 @@ pub fn parse_expression(
                     // If not, it must be a value
                     match Literal::from_str(&token.text) {
                         Ok(lit) => {
-                            stack.push(Object::Value(lit));
+                            stack.push(Object::Value(lit, Location::of(token)));
                         }
                         // If it's not a value, throw an error
                         Err(mut e) => {
@@ -237,35 +627,62 @@ pub fn parse_expression(
                 ))
             }
         }
-        println!("stack: {:?}", stack);
+    }
+    if let Some(group) = groups.last() {
+        return Err(CompilerProblem::new(
+            ProblemClass::Error,
+            "found a `)` without a matching `(`",
+            "check your parentheses",
+            group.line,
+            group.word,
+        ));
     }
     if stack.is_empty() {
         let line_no: usize;
         let word: usize;
-        if let Some(t) = tokens.get(0) {
+        if let Some(t) = tokens.first() {
             line_no = t.line;
             word = t.word;
         } else {
             line_no = 0;
             word = 0;
         }
-        return Err(CompilerProblem::new(
+        Err(CompilerProblem::new(
             ProblemClass::Error,
             "empty expression",
             "make sure to provide a value or call a function here",
             line_no,
             word,
-        ));
+        ))
     } else if stack.len() == 1 {
-        return Ok(stack.pop().unwrap());
+        let object = stack.pop().unwrap();
+        match check_range_placement(&object, is_index) {
+            Some(e) => Err(e),
+            None => Ok(object),
+        }
+    } else if let Some(Object::Value(Literal::Symbol(name), location)) = stack.last() {
+        // The leftmost value has arguments after it, so it was probably meant to be a function
+        let hint = match closest_name(name, fn_table.keys()) {
+            Some(suggestion) => format!("did you mean `{suggestion}`?"),
+            None => {
+                format!("only functions take arguments, so declare `{name}` with `fn` or import it")
+            }
+        };
+        Err(CompilerProblem::new(
+            ProblemClass::Error,
+            &format!("cannot find function `{name}` in this scope"),
+            &hint,
+            location.line,
+            location.word,
+        ))
     } else {
-        return Err(CompilerProblem::new(
+        Err(CompilerProblem::new(
             ProblemClass::Error,
             "too many objects left on the expression stack after parsing",
             "you probably have passed too many arguments to a function",
             tokens.last().unwrap().line,
             tokens.last().unwrap().word,
-        ));
+        ))
     }
 }
 
@@ -274,11 +691,11 @@ pub fn parse_expression(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::matches;
     use crate::grammars::Grammar;
     use crate::lex::lex;
     use crate::parse::{compute_scopes, parse, populate_function_table, NodeType};
-    
+    use std::matches;
+
     #[test]
     fn parse_expression_1() {
         let code: &str = "// This function adds two numbers
@@ -303,16 +720,149 @@ mod tests {
                         assert!(maybe_obj.is_ok());
                         let obj = maybe_obj.unwrap();
                         assert!(matches!(obj, Object::Operation(..)));
-                        match obj {
-                            Object::Operation(e) => {
-                                assert!(matches!(e, Expression::Prefix { .. }));
-                            },
-                            _ => {}
+                        if let Object::Operation(e) = obj {
+                            assert!(matches!(e, Expression::Prefix { .. }));
                         }
-                    },
-                    _ => assert!(false)
+                    }
+                    _ => panic!("expression node did not hold an expression grammar"),
                 }
             }
         }
     }
+
+    #[test]
+    fn parse_expression_tuple() {
+        let tokens = lex("(+ 1 2, 3, (4, 5))");
+        let function_table = BTreeMap::new();
+        let obj = parse_expression(&tokens, &function_table).unwrap();
+        match obj {
+            Object::Tuple(elements, _) => {
+                assert_eq!(elements.len(), 3);
+                assert!(matches!(elements[0], Object::Operation(..)));
+                assert!(matches!(elements[1], Object::Value(Literal::Int(3), _)));
+                assert!(matches!(&elements[2], Object::Tuple(inner, _) if inner.len() == 2));
+            }
+            _ => panic!("expected a tuple, found {:?}", obj),
+        }
+        // Strings inside tuples, with commas, spaces and parentheses of their own
+        for (code, first, second) in [
+            ("(\"a\", \"b\")", "\"a\"", "\"b\""),
+            ("(\"a b\", 2)", "\"a b\"", "2"),
+            ("(\"x, (y)\", \"z)\")", "\"x, (y)\"", "\"z)\""),
+        ] {
+            let obj = parse_expression(&lex(code), &function_table).unwrap();
+            let Object::Tuple(elements, _) = &obj else {
+                panic!("expected a tuple, found {:?}", obj);
+            };
+            let text: Vec<String> = elements
+                .iter()
+                .map(|e| match e {
+                    Object::Value(Literal::Str(text), _) => text.clone(),
+                    Object::Value(Literal::Int(int), _) => int.to_string(),
+                    _ => panic!("expected a literal, found {:?}", e),
+                })
+                .collect();
+            assert_eq!(text, vec![first, second], "{code}");
+        }
+    }
+
+    #[test]
+    fn parse_expression_argument_order() {
+        let tokens = lex("/ 6 3");
+        let function_table = BTreeMap::new();
+        let obj = parse_expression(&tokens, &function_table).unwrap();
+        match obj {
+            Object::Operation(Expression::Prefix { args, .. }) => {
+                assert!(matches!(args[0], Object::Value(Literal::Int(6), _)));
+                assert!(matches!(args[1], Object::Value(Literal::Int(3), _)));
+            }
+            _ => panic!("expected an operation, found {:?}", obj),
+        }
+    }
+
+    #[test]
+    fn parse_expression_tuple_element_not_single_value() {
+        let tokens = lex("(1 2, 3)");
+        let function_table = BTreeMap::new();
+        let outcome = parse_expression(&tokens, &function_table);
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn parse_expression_index_range() {
+        let tokens = lex("@ data 1..=i");
+        let function_table = BTreeMap::new();
+        let obj = parse_expression(&tokens, &function_table).unwrap();
+        match obj {
+            Object::Operation(Expression::Prefix {
+                op: Operator::Index,
+                args,
+                ..
+            }) => {
+                assert!(
+                    matches!(&args[0], Object::Value(Literal::Symbol(name), _) if name == "data")
+                );
+                assert!(matches!(
+                    &args[1],
+                    Object::Range {
+                        inclusive: true,
+                        ..
+                    }
+                ));
+            }
+            _ => panic!("expected an index operation, found {:?}", obj),
+        }
+    }
+
+    #[test]
+    fn parse_expression_bad_indices() {
+        let function_table = BTreeMap::new();
+        for (code, message) in [
+            (
+                "@ data \"a\"",
+                "expected an int or a range of ints as an index, found a str",
+            ),
+            ("@ data -1", "index cannot be negative, found `-1`"),
+            ("@ data 3..1", "the range `3..1` is empty"),
+            (
+                "+ 1 0..2",
+                "ranges can only be used to index into a collection",
+            ),
+        ] {
+            let outcome = parse_expression(&lex(code), &function_table);
+            assert_eq!(outcome.unwrap_err().message, message);
+        }
+    }
+
+    #[test]
+    fn parse_expression_misspelled_call() {
+        let code: &str = "fn add :: a int -> b int -> int {
+            return + a b
+        }";
+        let (mut nodes, _) = parse(lex(code));
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        let outcome = parse_expression(&lex("ad 1 2"), &function_table);
+        assert_eq!(
+            outcome.unwrap_err(),
+            CompilerProblem::new(
+                ProblemClass::Error,
+                "cannot find function `ad` in this scope",
+                "did you mean `add`?",
+                0,
+                0,
+            )
+        );
+        // Nothing is close enough to suggest, but only a function could take those arguments
+        let outcome = parse_expression(&lex("frobnicate 1 2"), &function_table);
+        assert_eq!(
+            outcome.unwrap_err().message,
+            "cannot find function `frobnicate` in this scope"
+        );
+        let outcome = parse_expression(&lex("1 2"), &function_table);
+        assert_eq!(
+            outcome.unwrap_err().message,
+            "too many objects left on the expression stack after parsing"
+        );
+    }
 }
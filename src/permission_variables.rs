@@ -0,0 +1,233 @@
+//! Permission variables: functions that take other functions require whatever those functions require
+//!
+//! An argument with a function type like `f (int -> int :: P)` binds the permission variable `P`, which stands for the permissions of the function passed in. A function calling `f` must declare `P` (`#Permissions :: P`), but doesn't require anything for it: passing a function with `&g` counts as calling it (see `Object::function_calls`), so the caller passing `g` needs `g`'s permissions instead. An argument without a permission variable can only be passed functions that require nothing.
+
+use std::collections::BTreeSet;
+
+use crate::call_graph::{english_list, CallGraph, RequiredPermissions};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::GrammarFunctionDeclaration;
+use crate::modules::Program;
+use crate::parse::{call_arguments, enclosing_function, function_calls, DataType, FunctionType};
+use crate::parse_expressions::{Literal, Location, Object};
+
+/// The type of a function's argument, if it's a function
+fn function_argument<'a>(
+    function: &'a GrammarFunctionDeclaration,
+    name: &str,
+) -> Option<&'a FunctionType> {
+    function
+        .arguments
+        .iter()
+        .find(|arg| arg.name == name)
+        .and_then(|arg| match &arg.data_type {
+            DataType::Function(function) => Some(function.as_ref()),
+            _ => None,
+        })
+}
+
+/// Calls to functions passed in without their permission variable, and functions passed to arguments that can't require what they do, paired with their module
+pub fn check_permission_variables(
+    program: &Program,
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    for module in program.compiled_modules() {
+        let nodes = &module.nodes;
+        let mut reported: BTreeSet<(String, String)> = BTreeSet::new();
+        for (index, name, location) in function_calls(nodes) {
+            let Some(caller) = enclosing_function(nodes, index) else {
+                continue;
+            };
+            // Functions passed by name are checked with the call they're passed to
+            let Some(args) = call_arguments(nodes, index, location) else {
+                continue;
+            };
+            let caller_data = module.function_table.get(&caller.fn_name);
+            let holds = |variable: &str| {
+                caller_data.is_some_and(|d| d.permission_variables.iter().any(|v| v == variable))
+            };
+            let mut report = |message: String, hint: String, at: Location| {
+                problems.push((
+                    module.name.clone(),
+                    CompilerProblem::new(ProblemClass::Error, &message, &hint, at.line, at.word),
+                ));
+            };
+            let add_variable = |variable: &str| {
+                format!(
+                    "add it to `{}`: `#Permissions :: {}`",
+                    caller.fn_name,
+                    caller_data
+                        .iter()
+                        .flat_map(|d| {
+                            d.permissions
+                                .iter()
+                                .filter(|p| graph.hierarchy.is_known(p))
+                                .map(|p| p.to_string())
+                                .chain(d.permission_variables.iter().cloned())
+                        })
+                        .chain([variable.to_string()])
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                )
+            };
+
+            // Calling a function passed in needs its permission variable (and its arguments can't be functions)
+            if let Some(function) = function_argument(caller, &name) {
+                if let Some(variable) = &function.permissions {
+                    if !holds(variable) && reported.insert((caller.fn_name.clone(), name.clone())) {
+                        report(
+                            format!(
+                                "`{}` calls `{name}`, which requires `{variable}`, but `{}` doesn't have that permission",
+                                caller.fn_name, caller.fn_name
+                            ),
+                            add_variable(variable),
+                            location,
+                        );
+                    }
+                }
+                continue;
+            }
+            let Some(callee) = module.function_table.get(&name) else {
+                continue;
+            };
+            for (arg, parameter) in args.iter().zip(callee.args.iter()) {
+                let Object::Value(Literal::Function(passed), at) = arg else {
+                    continue;
+                };
+                // Anything else is a type error
+                let DataType::Function(expected) = &parameter.data_type else {
+                    continue;
+                };
+                let with_variable = DataType::Function(Box::new(FunctionType {
+                    permissions: Some("P".to_string()),
+                    ..expected.as_ref().clone()
+                }));
+                let cannot_require = format!(
+                    "give `{}` a permission variable, like `{} {with_variable}`",
+                    parameter.name, parameter.name
+                );
+                // A function passed in stands for its permission variable
+                if let Some(function) = function_argument(caller, passed) {
+                    let Some(variable) = &function.permissions else {
+                        continue;
+                    };
+                    match &expected.permissions {
+                        Some(own) if callee.permission_variables.contains(own) => {
+                            if !holds(variable) {
+                                report(
+                                    format!(
+                                        "`{name}` may call `{passed}`, which requires `{variable}`, but `{}` doesn't have that permission",
+                                        caller.fn_name
+                                    ),
+                                    add_variable(variable),
+                                    *at,
+                                );
+                            }
+                        }
+                        Some(_) => {}
+                        None => report(
+                            format!(
+                                "`{passed}` may require `{variable}`, but `{name}` takes a function that can't require permissions"
+                            ),
+                            cannot_require,
+                            *at,
+                        ),
+                    }
+                    continue;
+                }
+                // Otherwise the call graph knows what the function passed requires
+                if expected.permissions.is_some() {
+                    continue;
+                }
+                let Some(reference) = graph.calls.iter().find(|call| {
+                    call.caller.module == module.name
+                        && call.caller.name == caller.fn_name
+                        && (call.line, call.word) == (at.line, at.word)
+                }) else {
+                    continue;
+                };
+                let needs: BTreeSet<String> = reference
+                    .needed(required)
+                    .map(|p| format!("`{p}`"))
+                    .collect();
+                if !needs.is_empty() {
+                    report(
+                        format!(
+                            "`{passed}` requires {}, but `{name}` takes a function that can't require permissions",
+                            english_list(needs.into_iter().collect())
+                        ),
+                        cannot_require,
+                        *at,
+                    );
+                }
+            }
+        }
+    }
+    problems
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_errors::ProblemClass;
+    use crate::modules::tests::project;
+    use crate::modules::Program;
+
+    #[test]
+    fn permission_variables_checked() {
+        let root = project(
+            "permission_variables",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn main :: void {
+    let a :: str = apply &load \"a.txt\"
+    let b :: str = apply &shout \"b\"
+    let c :: str = each &load \"c\"
+}
+fn apply :: f (str -> str :: P) -> x str -> str {
+    #Permissions :: P
+    return twice &f x
+}
+fn twice :: g (str -> str :: Q) -> x str -> str {
+    let once :: str = g x
+    return each &g once
+}
+fn each :: h (str -> str) -> x str -> str {
+    return h x
+}
+fn load :: path str -> str {
+    #Permissions :: ReadFile
+    return read_file path
+}
+fn shout :: text str -> str {
+    return text
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let errors: Vec<(&str, usize)> = program.modules["main"]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .map(|p| (p.message.as_str(), p.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("`main` calls `load`, which requires `ReadFile` (through `read_file` from `std.files`), but `main` doesn't have that permission", 2),
+                ("`load` requires `ReadFile`, but `each` takes a function that can't require permissions", 4),
+                ("`twice` calls `g`, which requires `Q`, but `twice` doesn't have that permission", 11),
+                ("`g` may require `Q`, but `each` takes a function that can't require permissions", 12),
+            ]
+        );
+        // Passing `&shout` needs nothing, and `apply` only needs `P` to pass `f` on to `twice`
+        assert!(!program.modules["main"]
+            .problems
+            .iter()
+            .any(|p| p.line == 3 || p.line == 8));
+    }
+}
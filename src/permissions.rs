@@ -1,22 +1,553 @@
 //! Function Permissions increase program security
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::{Grammar, GrammarCustomPermission};
+use crate::names::closest_name;
+use crate::parse::{enclosing_function, is_permission_variable, FunctionData, Node};
+use crate::parse_expressions::is_identifier;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Permissions {
     ReadFile,
     WriteFile,
     ReadNetwork,
     WriteNetwork,
-    Custom,
+    /// Reading standard input
+    ReadConsole,
+    /// Writing to standard output or standard error
+    WriteConsole,
+    /// Reading environment variables
+    ReadEnvironment,
+    /// Running other programs
+    SpawnProcess,
+    /// Reading the time
+    ReadClock,
+    /// Drawing random numbers
+    ReadRandom,
+    /// Calling C code the compiler can't check (nothing in the standard library needs it yet)
+    CallForeign,
+    /// A permission declared with `permission Name`
+    Custom(String),
+    /// A file or network permission limited to some paths or hosts, like `ReadFile("/etc/app/*")`
+    Scoped(Box<Permissions>, String),
+}
+
+/// For error messages
+pub const PERMISSION_LIST: [&str; 11] = [
+    "ReadFile",
+    "WriteFile",
+    "ReadNetwork",
+    "WriteNetwork",
+    "ReadConsole",
+    "WriteConsole",
+    "ReadEnvironment",
+    "SpawnProcess",
+    "ReadClock",
+    "ReadRandom",
+    "CallForeign",
+];
+
+/// Permissions a function absorbs with `#Trusted`: it may use them, but its callers don't need them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trusted {
+    pub permissions: Vec<Permissions>,
+    /// Why hiding the permissions is safe, for reviewers
+    pub justification: String,
+    /// The `#Trusted` line
+    pub line: usize,
 }
 
 impl Permissions {
     pub fn from_str(input: &str) -> Self {
+        if let Some((name, scope)) = input
+            .strip_suffix("\")")
+            .and_then(|rest| rest.split_once("(\""))
+        {
+            return Self::Scoped(Box::new(Self::from_str(name)), scope.to_string());
+        }
         match input {
             "ReadFile" => Self::ReadFile,
             "WriteFile" => Self::WriteFile,
             "ReadNetwork" => Self::ReadNetwork,
             "WriteNetwork" => Self::WriteNetwork,
-            _ => Self::Custom,
+            "ReadConsole" => Self::ReadConsole,
+            "WriteConsole" => Self::WriteConsole,
+            "ReadEnvironment" => Self::ReadEnvironment,
+            "SpawnProcess" => Self::SpawnProcess,
+            "ReadClock" => Self::ReadClock,
+            "ReadRandom" => Self::ReadRandom,
+            "CallForeign" => Self::CallForeign,
+            _ => Self::Custom(input.to_string()),
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Self::Custom(_))
+    }
+
+    /// File permissions can be limited to paths, and network permissions to hosts
+    pub fn is_scopable(&self) -> bool {
+        matches!(
+            self,
+            Self::ReadFile | Self::WriteFile | Self::ReadNetwork | Self::WriteNetwork
+        )
+    }
+
+    /// The permission without its scope
+    pub fn unscoped(&self) -> &Permissions {
+        match self {
+            Self::Scoped(permission, _) => permission,
+            _ => self,
+        }
+    }
+
+    /// Whether this is a permission, or that permission limited to a scope
+    pub fn narrows(&self, other: &Permissions) -> bool {
+        self == other || self.unscoped() == other
+    }
+
+    /// Whether holding this permission means holding another, looking only at scopes (custom permissions are expanded by `PermissionHierarchy`)
+    pub fn covers(&self, other: &Permissions) -> bool {
+        if self.unscoped() != other.unscoped() {
+            return false;
+        }
+        match (self, other) {
+            (Self::Scoped(permission, held), Self::Scoped(_, needed)) => {
+                let pattern = scope_pattern(permission, held);
+                glob_match(&pattern, &scope_pattern(permission, needed))
+            }
+            (Self::Scoped(..), _) => false,
+            _ => true,
+        }
+    }
+
+    /// The scope a call to a builtin needs, from the literal path or URL it's given
+    ///
+    /// Paths have their `.` and `..` segments resolved (see `normalise_path`). Network scopes are `host:port`, with the port taken from the URL's scheme if it isn't written
+    pub fn resource(&self, literal: &str) -> Option<String> {
+        match self {
+            Self::ReadFile | Self::WriteFile => normalise_path(literal),
+            Self::ReadNetwork | Self::WriteNetwork => {
+                let (scheme, rest) = literal.split_once("://").unwrap_or(("", literal));
+                let authority = rest.split(['/', '?', '#']).next()?;
+                let host = authority.rsplit('@').next()?;
+                if host.is_empty() {
+                    return None;
+                }
+                if host.contains(':') {
+                    return Some(host.to_string());
+                }
+                match scheme {
+                    "http" | "ws" => Some(format!("{host}:80")),
+                    "https" | "wss" => Some(format!("{host}:443")),
+                    _ => Some(format!("{host}:*")),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A host scope without a port allows any port
+fn scope_pattern(permission: &Permissions, scope: &str) -> String {
+    match permission {
+        Permissions::ReadNetwork | Permissions::WriteNetwork if !scope.contains(':') => {
+            format!("{scope}:*")
+        }
+        _ => scope.to_string(),
+    }
+}
+
+/// Resolves the `.` and `..` segments of a path without touching the file system, so `/etc/app/../shadow` is `/etc/shadow`
+///
+/// Returns `None` if a `..` climbs above the start of the path (like `../x`, or `/..`), since nothing says where that ends up. Symbolic links aren't followed
+fn normalise_path(path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let joined = segments.join("/");
+    match (path.starts_with('/'), joined.is_empty()) {
+        (true, _) => Some(format!("/{joined}")),
+        (false, true) => Some(".".to_string()),
+        (false, false) => Some(joined),
+    }
+}
+
+/// One piece of a scope: `*` stands for any run of characters except `/`, and `**` for any run at all
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Glob {
+    Char(char),
+    Star,
+    AnyDepth,
+}
+
+fn glob_pieces(text: &str) -> Vec<Glob> {
+    let mut pieces: Vec<Glob> = Vec::new();
+    for c in text.chars() {
+        match (c, pieces.last()) {
+            ('*', Some(Glob::Star)) => *pieces.last_mut().unwrap() = Glob::AnyDepth,
+            ('*', Some(Glob::AnyDepth)) => {}
+            ('*', _) => pieces.push(Glob::Star),
+            _ => pieces.push(Glob::Char(c)),
+        }
+    }
+    pieces
+}
+
+/// Match text against a pattern where `*` stands for any run of characters within one path segment, and `**` for any run of characters across segments
+///
+/// A `*` in the text is only matched by a `*` or `**` in the pattern, and a `**` only by a `**`, so this also says whether one pattern covers another
+fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_pieces(&glob_pieces(pattern), &glob_pieces(text))
+}
+
+fn glob_match_pieces(pattern: &[Glob], text: &[Glob]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((Glob::AnyDepth, rest)) => {
+            (0..=text.len()).any(|i| glob_match_pieces(rest, &text[i..]))
+        }
+        Some((Glob::Star, rest)) => {
+            let segment = text
+                .iter()
+                .take_while(|g| matches!(g, Glob::Star) || matches!(g, Glob::Char(c) if *c != '/'))
+                .count();
+            (0..=segment).any(|i| glob_match_pieces(rest, &text[i..]))
+        }
+        Some((Glob::Char(c), rest)) => {
+            text.first() == Some(&Glob::Char(*c)) && glob_match_pieces(rest, &text[1..])
+        }
+    }
+}
+
+impl Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Custom(name) => write!(f, "{name}"),
+            Self::Scoped(permission, scope) => write!(f, "{permission}(\"{scope}\")"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// The custom permissions declared across a program, and the permissions each one implies
+///
+/// Holding a permission means holding everything it implies, transitively: with `permission FileIO :: ReadFile WriteFile`, a function declaring `FileIO` can call a function that needs `ReadFile`
+///
+/// Each custom permission belongs to the module declaring it. It's written `Name` there, and `module.Name` everywhere else, so a dependency can't widen a permission the program relies on by declaring one with the same name. Permissions are stored under the name the entry module would use
+#[derive(Debug, Clone, Default)]
+pub struct PermissionHierarchy {
+    pub implies: BTreeMap<String, Vec<Permissions>>,
+    /// The module declaring each custom permission, and its name there
+    pub homes: BTreeMap<String, (String, String)>,
+    /// The entry module, whose permissions go by their own name
+    pub entry: String,
+}
+
+impl PermissionHierarchy {
+    /// Collect the `permission` declarations of every module, reporting duplicates
+    ///
+    /// A name can only be declared by one module, so reusing a name from another module is an error too
+    pub fn declare<'a>(
+        entry: &str,
+        modules: impl Iterator<Item = (&'a str, &'a [Node])>,
+    ) -> (PermissionHierarchy, Vec<(String, CompilerProblem)>) {
+        let mut hierarchy = PermissionHierarchy {
+            entry: entry.to_string(),
+            ..PermissionHierarchy::default()
+        };
+        let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+        // Find every name first, since a permission can imply one declared further down (or in another module)
+        let mut declarations: Vec<(&str, &GrammarCustomPermission)> = Vec::new();
+        let mut homes: BTreeMap<&str, Vec<(&str, usize)>> = BTreeMap::new();
+        for (module, nodes) in modules {
+            let mut declared: BTreeMap<&str, usize> = BTreeMap::new();
+            for node in nodes {
+                let Grammar::CustomPermission(g) = &node.grammar else {
+                    continue;
+                };
+                let problem = if PERMISSION_LIST.contains(&g.name.as_str()) {
+                    CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("`{}` is a built-in permission", g.name),
+                        "give your permission a different name",
+                        node.source_line,
+                        1,
+                    )
+                } else if !is_identifier(&g.name) {
+                    CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("`{}` isn't a valid permission name", g.name),
+                        "use letters, digits and underscores (permissions from other modules are written `module.Name` when they're used, not declared)",
+                        node.source_line,
+                        1,
+                    )
+                } else if let Some(first) = declared.insert(&g.name, node.source_line) {
+                    CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("the permission `{}` is declared twice", g.name),
+                        "remove one of the declarations",
+                        node.source_line,
+                        1,
+                    )
+                    .with_related("first declared here", first, 1)
+                } else {
+                    homes
+                        .entry(&g.name)
+                        .or_default()
+                        .push((module, node.source_line));
+                    hierarchy.homes.insert(
+                        hierarchy.name_in(module, &g.name),
+                        (module.to_string(), g.name.clone()),
+                    );
+                    declarations.push((module, g));
+                    continue;
+                };
+                problems.push((module.to_string(), problem));
+            }
+        }
+        // Each module declaring a name gets an error naming the others, rather than sharing the permission
+        for (name, declared) in homes.iter().filter(|(_, d)| d.len() > 1) {
+            for (module, line) in declared.iter() {
+                let others: Vec<String> = declared
+                    .iter()
+                    .filter(|(other, _)| other != module)
+                    .map(|(other, _)| format!("`{other}`"))
+                    .collect();
+                problems.push((
+                    module.to_string(),
+                    CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!(
+                            "the permission `{name}` is also declared in {}",
+                            others.join(", ")
+                        ),
+                        "permission names must be unique across the program, so rename one of them",
+                        *line,
+                        1,
+                    ),
+                ));
+            }
+        }
+        for (module, g) in declarations {
+            let implied: Vec<Permissions> = g
+                .implies
+                .iter()
+                .map(|p| hierarchy.resolve(module, p).unwrap_or_else(|| p.clone()))
+                .collect();
+            let implies = hierarchy
+                .implies
+                .entry(hierarchy.name_in(module, &g.name))
+                .or_default();
+            for permission in implied {
+                if !implies.contains(&permission) {
+                    implies.push(permission);
+                }
+            }
+        }
+        (hierarchy, problems)
+    }
+
+    /// The name a custom permission declared in a module is stored under: `Name` for the entry module, `module.Name` otherwise
+    fn name_in(&self, module: &str, name: &str) -> String {
+        match module == self.entry {
+            true => name.to_string(),
+            false => format!("{module}.{name}"),
+        }
+    }
+
+    /// The permission a name written in a module refers to, or `None` if it isn't declared where it's looked for
+    ///
+    /// `Name` is looked for in the module itself, `module.Name` in that module
+    pub fn resolve(&self, module: &str, permission: &Permissions) -> Option<Permissions> {
+        match permission {
+            Permissions::Custom(name) => {
+                let (home, local) = name.rsplit_once('.').unwrap_or((module, name));
+                self.homes
+                    .iter()
+                    .find(|(_, (m, n))| m == home && n == local)
+                    .map(|(stored, _)| Permissions::Custom(stored.clone()))
+            }
+            Permissions::Scoped(scoped, _) if !scoped.is_scopable() => None,
+            _ => Some(permission.clone()),
+        }
+    }
+
+    /// The custom permissions a module can name, the way it names them
+    pub fn visible_from(&self, module: &str) -> Vec<String> {
+        self.homes
+            .values()
+            .map(|(home, name)| match home == module {
+                true => name.clone(),
+                false => format!("{home}.{name}"),
+            })
+            .collect()
+    }
+
+    /// Built-in permissions are always known, custom ones once they're declared
+    pub fn is_known(&self, permission: &Permissions) -> bool {
+        match permission {
+            Permissions::Custom(name) => self.implies.contains_key(name),
+            Permissions::Scoped(permission, _) => permission.is_scopable(),
+            _ => true,
+        }
+    }
+
+    /// A permission and everything it implies, transitively
+    pub fn implied(&self, permission: &Permissions) -> BTreeSet<Permissions> {
+        let mut implied: BTreeSet<Permissions> = BTreeSet::new();
+        let mut pending: Vec<Permissions> = vec![permission.clone()];
+        while let Some(next) = pending.pop() {
+            if let Permissions::Custom(name) = &next {
+                pending.extend(
+                    self.implies
+                        .get(name)
+                        .into_iter()
+                        .flatten()
+                        .filter(|p| !implied.contains(*p))
+                        .cloned(),
+                );
+            }
+            implied.insert(next);
+        }
+        implied
+    }
+
+    /// Whether holding a list of permissions means holding another
+    pub fn covers(&self, held: &[Permissions], permission: &Permissions) -> bool {
+        held.iter()
+            .any(|p| self.implied(p).iter().any(|i| i.covers(permission)))
+    }
+
+    /// A custom permission that implies nothing stands for an effect the compiler can't see
+    pub fn is_opaque(&self, permission: &Permissions) -> bool {
+        match permission {
+            Permissions::Custom(name) => self.implies.get(name).is_none_or(|i| i.is_empty()),
+            _ => false,
+        }
+    }
+}
+
+/// Report permission names in `#Permissions` and `#Trusted` lists, `permission` declarations and `with_permissions` blocks that a module can't see
+///
+/// This looks at the names as written, so it has to run before `resolve_permission_names`
+pub fn check_permission_names(
+    module: &str,
+    nodes: &[Node],
+    hierarchy: &PermissionHierarchy,
+) -> Vec<CompilerProblem> {
+    let candidates: Vec<String> = PERMISSION_LIST
+        .iter()
+        .map(|p| p.to_string())
+        .chain(hierarchy.visible_from(module))
+        .collect();
+    let mut problems: Vec<CompilerProblem> = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        // Permission names start after `#Permissions ::`, `#Trusted ::`, `permission Name ::` or `with_permissions [`
+        let (listed, first_word) = match &node.grammar {
+            Grammar::Permission(g) => (&g.p_list, 2),
+            Grammar::Attenuation(g) => (&g.permissions, 2),
+            Grammar::Trusted(g) => (&g.permissions, 2),
+            Grammar::CustomPermission(g) => (&g.implies, 3),
+            _ => continue,
+        };
+        let arguments = match (&node.grammar, enclosing_function(nodes, index)) {
+            (Grammar::Permission(_) | Grammar::Attenuation(_), Some(function)) => {
+                function.arguments.as_slice()
+            }
+            _ => &[],
+        };
+        for (position, permission) in listed.iter().enumerate() {
+            if hierarchy.resolve(module, permission).is_some()
+                || is_permission_variable(arguments, permission)
+            {
+                continue;
+            }
+            if let Permissions::Scoped(scoped, _) = permission {
+                if hierarchy.resolve(module, scoped).is_some() {
+                    problems.push(CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("`{scoped}` can't be limited to a scope"),
+                        "only `ReadFile` and `WriteFile` (to paths) and `ReadNetwork` and `WriteNetwork` (to hosts) can",
+                        node.source_line,
+                        first_word + position,
+                    ));
+                    continue;
+                }
+            }
+            let permission = permission.unscoped();
+            let elsewhere: Vec<&String> = candidates
+                .iter()
+                .filter(|c| {
+                    c.rsplit_once('.')
+                        .is_some_and(|(_, n)| *n == permission.to_string())
+                })
+                .collect();
+            let hint = match (
+                elsewhere.first(),
+                closest_name(&permission.to_string(), candidates.iter()),
+            ) {
+                (Some(qualified), _) => {
+                    format!("it's declared in another module, so write `{qualified}`")
+                }
+                (None, Some(suggestion)) => format!("did you mean `{suggestion}`?"),
+                (None, None) => format!("declare it with `permission {permission}`"),
+            };
+            problems.push(CompilerProblem::new(
+                ProblemClass::Error,
+                &format!("unknown permission `{permission}`"),
+                &hint,
+                node.source_line,
+                first_word + position,
+            ));
+        }
+    }
+    problems
+}
+
+/// Rewrite the custom permissions a module names into the names they're stored under (see `PermissionHierarchy`)
+///
+/// Functions imported from other modules are resolved in the module defining them. Names that don't resolve are left alone, `check_permission_names` reports them
+pub fn resolve_permission_names(
+    module: &str,
+    nodes: &mut [Node],
+    function_table: &mut BTreeMap<String, FunctionData>,
+    hierarchy: &PermissionHierarchy,
+) {
+    let resolve_in = |home: &str, permissions: &mut Vec<Permissions>| {
+        for permission in permissions.iter_mut() {
+            if let Some(resolved) = hierarchy.resolve(home, permission) {
+                *permission = resolved;
+            }
+        }
+    };
+    for node in nodes.iter_mut() {
+        match &mut node.grammar {
+            Grammar::Permission(g) => resolve_in(module, &mut g.p_list),
+            Grammar::Attenuation(g) => resolve_in(module, &mut g.permissions),
+            Grammar::Trusted(g) => resolve_in(module, &mut g.permissions),
+            Grammar::CustomPermission(g) => resolve_in(module, &mut g.implies),
+            _ => {}
+        }
+    }
+    for data in function_table.values_mut() {
+        let home = data
+            .origin
+            .as_ref()
+            .map_or(module.to_string(), |o| o.module.clone());
+        resolve_in(&home, &mut data.permissions);
+        for trusted in data.trusted.iter_mut() {
+            resolve_in(&home, &mut trusted.permissions);
         }
     }
 }
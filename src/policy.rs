@@ -0,0 +1,516 @@
+//! The project's permission policy: `iona-policy.toml` gives modules a budget of permissions they may use
+//!
+//! The policy sits in the project root, next to the entry file. Each key is a module name and each value the permissions its functions may require (or be trusted with):
+//!
+//! ```toml
+//! deps.sketchy_library = []
+//! app.net = ["WriteNetwork", 'ReadFile("/etc/app/*")']
+//! ```
+//!
+//! A budget also covers the modules under it (`app` covers `app.net`), with the most specific key winning. Modules without a budget are unrestricted. Only the part of TOML a policy needs is understood: comments, `[table]` headers, dotted or quoted keys, and arrays of strings (which may span lines).
+
+use std::fs;
+use std::path::Path;
+
+use crate::call_graph::{english_list, CallGraph, FunctionId, RequiredPermissions};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::Grammar;
+use crate::modules::Program;
+use crate::names::closest_name;
+use crate::permissions::{PermissionHierarchy, Permissions, PERMISSION_LIST};
+
+/// The name of the policy file, in the project root
+pub const POLICY_FILE: &str = "iona-policy.toml";
+
+/// The permissions a module (and the modules under it) may use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Budget {
+    pub module: String,
+    pub permissions: Vec<Permissions>,
+    /// The line setting the budget
+    pub line: usize,
+}
+
+/// A parsed `iona-policy.toml`
+#[derive(Debug, Default)]
+pub struct Policy {
+    pub source: String,
+    pub budgets: Vec<Budget>,
+    /// Problems with the policy file itself
+    pub problems: Vec<CompilerProblem>,
+}
+
+/// Cut a line at its comment, unless the `#` is inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (position, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..position],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// The strings in a TOML array, like `["ReadFile", 'WriteFile("/tmp/*")']`
+fn parse_array(value: &str) -> Result<Vec<String>, String> {
+    let inner = value
+        .trim()
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or_else(|| {
+            format!(
+                "expected a list of permissions, like `[\"ReadFile\"]`, but found `{}`",
+                value.trim()
+            )
+        })?;
+    let mut items: Vec<String> = Vec::new();
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let Some(quote) = chars.next() else {
+            break;
+        };
+        if quote != '"' && quote != '\'' {
+            return Err(format!(
+                "permissions should be quoted, like `\"ReadFile\"`, but found `{quote}`"
+            ));
+        }
+        let mut item = String::new();
+        let mut closed = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if quote == '"' => item.extend(chars.next()),
+                c if c == quote => {
+                    closed = true;
+                    break;
+                }
+                c => item.push(c),
+            }
+        }
+        if !closed {
+            return Err(format!(
+                "the string `{quote}{item}` is missing its closing `{quote}`"
+            ));
+        }
+        items.push(item);
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.next() {
+            Some(',') | None => {}
+            Some(c) => {
+                return Err(format!(
+                    "expected a `,` between permissions, but found `{c}`"
+                ))
+            }
+        }
+    }
+    Ok(items)
+}
+
+impl Policy {
+    /// Read the policy from the project root, if there is one
+    pub fn load(root: &Path) -> Option<Policy> {
+        fs::read_to_string(root.join(POLICY_FILE))
+            .ok()
+            .map(|source| Policy::parse(&source))
+    }
+
+    pub fn parse(source: &str) -> Policy {
+        let mut policy = Policy {
+            source: source.to_string(),
+            ..Policy::default()
+        };
+        let error = |message: String, line: usize| {
+            CompilerProblem::new(
+                ProblemClass::Error,
+                &message,
+                "a budget should look like this: `app.net = [\"WriteNetwork\"]`",
+                line,
+                0,
+            )
+        };
+        let mut table: Option<String> = None;
+        // An entry whose array continues onto later lines: (key, text so far, first line)
+        let mut pending: Option<(String, String, usize)> = None;
+        for (number, line) in source.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            let (key, value, first_line) = match pending.take() {
+                Some((key, text, first_line)) => (key, format!("{text} {line}"), first_line),
+                None if line.is_empty() => continue,
+                None => {
+                    if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                        table = Some(header.trim().replace(['"', '\''], ""));
+                        continue;
+                    }
+                    let Some((key, value)) = line.split_once('=') else {
+                        policy.problems.push(error(
+                            format!("expected `module = [..]`, but found `{line}`"),
+                            number,
+                        ));
+                        continue;
+                    };
+                    let key: Vec<String> = key
+                        .split('.')
+                        .map(|part| part.trim().trim_matches(['"', '\'']).to_string())
+                        .collect();
+                    let key = match &table {
+                        Some(table) => format!("{table}.{}", key.join(".")),
+                        None => key.join("."),
+                    };
+                    (key, value.trim().to_string(), number)
+                }
+            };
+            if value.starts_with('[') && !value.ends_with(']') {
+                pending = Some((key, value, first_line));
+                continue;
+            }
+            match parse_array(&value) {
+                Ok(_) if policy.budgets.iter().any(|b| b.module == key) => {
+                    policy.problems.push(error(
+                        format!("`{key}` is given a budget more than once"),
+                        first_line,
+                    ));
+                }
+                Ok(items) => policy.budgets.push(Budget {
+                    module: key,
+                    permissions: items.iter().map(|p| Permissions::from_str(p)).collect(),
+                    line: first_line,
+                }),
+                Err(message) => policy.problems.push(error(message, first_line)),
+            }
+        }
+        if let Some((key, _, first_line)) = pending {
+            policy.problems.push(error(
+                format!("the list for `{key}` is missing its closing `]`"),
+                first_line,
+            ));
+        }
+        policy
+    }
+
+    /// The budget a module has to keep to: the one for the module itself, or for the closest module above it
+    pub fn budget(&self, module: &str) -> Option<&Budget> {
+        self.budgets
+            .iter()
+            .filter(|b| {
+                module == b.module
+                    || module
+                        .strip_prefix(&b.module)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+            .max_by_key(|b| b.module.len())
+    }
+
+    /// The text of a line of the policy
+    fn line(&self, line: usize) -> String {
+        self.source.lines().nth(line).unwrap_or("").to_string()
+    }
+
+    /// Unknown permissions in budgets, and budgets for modules that aren't in the program
+    pub fn check(&self, program: &Program) -> Vec<CompilerProblem> {
+        let hierarchy: &PermissionHierarchy = &program.permissions;
+        let permissions: Vec<String> = PERMISSION_LIST
+            .iter()
+            .map(|p| p.to_string())
+            .chain(hierarchy.implies.keys().cloned())
+            .collect();
+        let modules: Vec<String> = program.compiled_modules().map(|m| m.name.clone()).collect();
+        let mut problems: Vec<CompilerProblem> = Vec::new();
+        for budget in self.budgets.iter() {
+            for permission in budget.permissions.iter() {
+                if hierarchy.is_known(permission) {
+                    continue;
+                }
+                let name = permission.unscoped().to_string();
+                let hint = match closest_name(&name, permissions.iter()) {
+                    Some(suggestion) => format!("did you mean `{suggestion}`?"),
+                    None => format!("declare it with `permission {name}` in one of the modules"),
+                };
+                problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!(
+                        "unknown permission `{name}` in the budget for `{}`",
+                        budget.module
+                    ),
+                    &hint,
+                    budget.line,
+                    0,
+                ));
+            }
+            let covers_a_module = modules
+                .iter()
+                .any(|m| self.budget(m).is_some_and(|b| b.module == budget.module));
+            if !covers_a_module {
+                let hint = match closest_name(&budget.module, modules.iter()) {
+                    Some(suggestion) => format!("did you mean `{suggestion}`?"),
+                    None => "remove it, or check the module's name".to_string(),
+                };
+                problems.push(CompilerProblem::new(
+                    ProblemClass::Lint,
+                    &format!(
+                        "the budget for `{}` doesn't apply to any module in the program",
+                        budget.module
+                    ),
+                    &hint,
+                    budget.line,
+                    0,
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// Where a function declares a permission: the word in its `#Permissions` or `#Trusted` list covering it, or else the function's name
+fn declaration(
+    program: &Program,
+    graph: &CallGraph,
+    function: &FunctionId,
+    permission: &Permissions,
+) -> (usize, usize) {
+    let line = graph.lines.get(function).copied().unwrap_or(0);
+    let Some(module) = program.modules.get(&function.module) else {
+        return (line, 1);
+    };
+    for node in module
+        .nodes
+        .iter()
+        .filter(|n| n.parent_node_line == Some(line))
+    {
+        let listed = match &node.grammar {
+            Grammar::Permission(g) => &g.p_list,
+            Grammar::Trusted(g) => &g.permissions,
+            _ => continue,
+        };
+        if let Some(position) = listed
+            .iter()
+            .position(|p| graph.hierarchy.covers(std::slice::from_ref(p), permission))
+        {
+            return (node.source_line, 2 + position);
+        }
+    }
+    (line, 1)
+}
+
+/// Functions using permissions outside their module's budget, paired with their module
+///
+/// Permissions a function is trusted with count too, so `#Trusted` can't get around the policy
+pub fn check_policy(
+    program: &Program,
+    policy: &Policy,
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    for function in graph.lines.keys() {
+        let Some(budget) = policy.budget(&function.module) else {
+            continue;
+        };
+        let mut used: Vec<Permissions> = required
+            .get(function)
+            .into_iter()
+            .flat_map(|r| r.keys().cloned())
+            .collect();
+        used.extend(graph.functions.get(function).into_iter().flat_map(|data| {
+            data.trusted
+                .iter()
+                .flat_map(|t| t.permissions.iter().cloned())
+        }));
+        let mut over: Vec<Permissions> = Vec::new();
+        for permission in used {
+            if !graph.hierarchy.covers(&budget.permissions, &permission)
+                && !over.contains(&permission)
+            {
+                over.push(permission);
+            }
+        }
+        // A scoped permission isn't worth naming next to the unscoped one
+        let unscoped = over.clone();
+        over.retain(|p| !unscoped.iter().any(|q| q != p && q.covers(p)));
+        let Some(first) = over.first() else {
+            continue;
+        };
+        let allowed = if budget.permissions.is_empty() {
+            "allows it no permissions".to_string()
+        } else {
+            format!(
+                "only allows it {}",
+                english_list(
+                    budget
+                        .permissions
+                        .iter()
+                        .map(|p| format!("`{p}`"))
+                        .collect()
+                )
+            )
+        };
+        let (line, word) = declaration(program, graph, function, first);
+        problems.push((
+            function.module.clone(),
+            CompilerProblem::new(
+                ProblemClass::Error,
+                &format!(
+                    "`{}` requires {}, but the policy for `{}` {allowed}",
+                    function.name,
+                    english_list(over.iter().map(|p| format!("`{p}`")).collect()),
+                    budget.module
+                ),
+                &format!(
+                    "stop `{}` from needing {}, or raise the budget in `{POLICY_FILE}` if `{}` should have {}",
+                    function.name,
+                    if over.len() == 1 { "it" } else { "them" },
+                    budget.module,
+                    if over.len() == 1 { "it" } else { "them" },
+                ),
+                line,
+                word,
+            )
+            .with_related_in(
+                POLICY_FILE,
+                &policy.line(budget.line),
+                &format!("the budget for `{}` is set here", budget.module),
+                budget.line,
+                0,
+            ),
+        ));
+    }
+    problems
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::tests::project;
+
+    #[test]
+    fn parse_policy() {
+        let policy = Policy::parse(
+            "# budgets for every dependency
+deps.sketchy_library = []
+\"app.net\" = [\"WriteNetwork\", 'ReadFile(\"/etc/app/*\")']   # comment
+
+[tools]
+logger = [
+    \"WriteFile\",
+]
+broken = ReadFile",
+        );
+        assert_eq!(
+            policy.budgets,
+            vec![
+                Budget {
+                    module: "deps.sketchy_library".to_string(),
+                    permissions: vec![],
+                    line: 1
+                },
+                Budget {
+                    module: "app.net".to_string(),
+                    permissions: vec![
+                        Permissions::WriteNetwork,
+                        Permissions::from_str("ReadFile(\"/etc/app/*\")")
+                    ],
+                    line: 2
+                },
+                Budget {
+                    module: "tools.logger".to_string(),
+                    permissions: vec![Permissions::WriteFile],
+                    line: 5
+                },
+            ]
+        );
+        assert_eq!(policy.problems.len(), 1);
+        assert_eq!(policy.problems[0].line, 8);
+        assert_eq!(
+            policy.budget("app.net.http").map(|b| b.module.as_str()),
+            Some("app.net")
+        );
+        assert!(policy.budget("app.network").is_none());
+    }
+
+    #[test]
+    fn functions_kept_to_budget() {
+        let root = project(
+            "policy",
+            &[
+                (
+                    "main.iona",
+                    "import fast_sqrt from deps.sketchy_library
+fn main :: void {
+    #Permissions :: ReadFile WriteNetwork
+    let x :: int = fast_sqrt 4
+}",
+                ),
+                (
+                    "deps/sketchy_library.iona",
+                    "import read_file from std.files
+import request from std.networking
+fn fast_sqrt :: n int -> int {
+    #Properties :: Export
+    #Permissions :: ReadFile WriteNetwork
+    let passwords :: str = read_file \"/etc/passwd\"
+    return n
+}",
+                ),
+                (
+                    POLICY_FILE,
+                    "deps.sketchy_library = []\ndeps.missing = [\"ReadFil\"]\n",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let errors: Vec<&CompilerProblem> = program.modules["deps.sketchy_library"]
+            .problems
+            .iter()
+            .filter(|p| p.class == ProblemClass::Error)
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "`fast_sqrt` requires `ReadFile` and `WriteNetwork`, but the policy for `deps.sketchy_library` allows it no permissions"
+        );
+        assert_eq!((errors[0].line, errors[0].word_index), (4, 2));
+        let related = errors[0].related.as_ref().unwrap();
+        assert_eq!(related.line, 0);
+        assert_eq!(
+            related.file,
+            Some((
+                POLICY_FILE.to_string(),
+                "deps.sketchy_library = []".to_string()
+            ))
+        );
+        // `main` has no budget, so it may use anything
+        assert!(!program.modules["main"]
+            .problems
+            .iter()
+            .any(|p| p.class == ProblemClass::Error));
+        let policy: Vec<&str> = program
+            .policy
+            .as_ref()
+            .unwrap()
+            .problems
+            .iter()
+            .map(|p| p.message.as_str())
+            .collect();
+        assert_eq!(
+            policy,
+            vec![
+                "unknown permission `ReadFil` in the budget for `deps.missing`",
+                "the budget for `deps.missing` doesn't apply to any module in the program",
+            ]
+        );
+    }
+}
@@ -2,15 +2,17 @@
 
 /// Tagged function properties
 ///
-/// Pure == no side effects
+/// Pure == no side effects (checked, see `purity`)
+/// Deterministic == same result for the same arguments, on every run (checked, see `determinism`)
 /// Public == visible within this module
 /// Export == visible within this module AND visible to other modules
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Properties {
     Pure,
+    Deterministic,
     Public,
     Export,
 }
 
 /// For error messages
-pub const PROPERTY_LIST: [&str; 3] = ["Pure", "Public", "Export"];
+pub const PROPERTY_LIST: [&str; 4] = ["Pure", "Deterministic", "Public", "Export"];
@@ -0,0 +1,314 @@
+//! Checking the `Pure` property: a `Pure` function has no side effects, so calling it can only compute its result
+//!
+//! A `Pure` function may not declare permissions, and may only call other `Pure` functions (which rules out the I/O builtins). Assignments can only target a function's own `let` bindings (`check_mutability` rejects arguments and undeclared names), so a `Pure` function can't mutate anything its caller sees.
+
+use std::collections::BTreeSet;
+
+use crate::call_graph::{CallGraph, FunctionId};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::Grammar;
+use crate::modules::Program;
+use crate::parse::{DataType, Node, PrimitiveDataType};
+use crate::properties::Properties;
+use crate::stdlib::is_stdlib_module;
+
+fn is_pure(graph: &CallGraph, function: &FunctionId) -> bool {
+    graph
+        .functions
+        .get(function)
+        .is_some_and(|data| data.properties.contains(&Properties::Pure))
+}
+
+/// The `#Properties` or `#Permissions` line of a function
+fn annotation<'a>(
+    program: &'a Program,
+    graph: &CallGraph,
+    function: &FunctionId,
+    is_annotation: impl Fn(&Grammar) -> bool,
+) -> Option<&'a Node> {
+    let line = graph.lines.get(function)?;
+    program
+        .modules
+        .get(&function.module)?
+        .nodes
+        .iter()
+        .find(|node| node.parent_node_line == Some(*line) && is_annotation(&node.grammar))
+}
+
+/// `Pure` functions that declare permissions or call functions that aren't `Pure`, paired with their module
+///
+/// Each callee is reported once per function, at the first call
+pub fn check_purity(program: &Program, graph: &CallGraph) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    for function in graph.lines.keys().filter(|f| is_pure(graph, f)) {
+        if let Some(node) = annotation(program, graph, function, |g| {
+            matches!(g, Grammar::Permission(_))
+        }) {
+            problems.push((
+                function.module.clone(),
+                CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("`{}` is `Pure`, but declares permissions", function.name),
+                    "`Pure` functions can't have side effects: remove the `#Permissions` line, or remove `Pure`",
+                    node.source_line,
+                    2,
+                ),
+            ));
+        }
+        let mut reported: BTreeSet<&FunctionId> = BTreeSet::new();
+        for call in graph.calls_from(function) {
+            if is_pure(graph, &call.callee) || !reported.insert(&call.callee) {
+                continue;
+            }
+            let problem = if is_stdlib_module(&call.callee.module) {
+                CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!(
+                        "`{}` is `Pure`, but calls `{}`, which performs I/O",
+                        function.name, call.called_as
+                    ),
+                    &format!(
+                        "do the I/O in a function calling `{}`, or remove `Pure`",
+                        function.name
+                    ),
+                    call.line,
+                    call.word,
+                )
+            } else {
+                let problem = CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!(
+                        "`{}` is `Pure`, but calls `{}`, which isn't `Pure`",
+                        function.name, call.called_as
+                    ),
+                    &format!(
+                        "mark `{}` as `Pure` if it has no side effects, or remove `Pure` from `{}`",
+                        call.called_as, function.name
+                    ),
+                    call.line,
+                    call.word,
+                );
+                match graph.lines.get(&call.callee) {
+                    Some(line) if call.callee.module == function.module => problem.with_related(
+                        &format!("`{}` is declared here", call.callee.name),
+                        *line,
+                        1,
+                    ),
+                    _ => problem,
+                }
+            };
+            problems.push((function.module.clone(), problem));
+        }
+    }
+    problems
+}
+
+/// Where a trailing `// ...` comment starts on a line, or its length if there isn't one
+fn comment_start(text: &str) -> usize {
+    text.match_indices(" //")
+        .map(|(i, _)| i)
+        .find(|i| text[i + 3..].is_empty() || text[i + 3..].starts_with([' ', '\t']))
+        .unwrap_or(text.len())
+}
+
+/// Functions that could be marked `Pure` but aren't, paired with their module
+///
+/// A function qualifies if it returns a value, declares no permissions, takes no functions, and only calls functions that are `Pure` or qualify themselves, so marking every suggested function at once is always valid. Functions in modules with errors never qualify, since lines that failed to parse may hold impure calls
+pub fn pure_lints(program: &Program, graph: &CallGraph) -> Vec<(String, CompilerProblem)> {
+    let void = DataType::Primitive(PrimitiveDataType::Void);
+    let has_errors = |function: &FunctionId| {
+        program
+            .modules
+            .get(&function.module)
+            .is_some_and(|m| m.problems.iter().any(|p| p.class == ProblemClass::Error))
+    };
+    let mut qualifying: BTreeSet<&FunctionId> = graph
+        .lines
+        .keys()
+        .filter(|f| !has_errors(f))
+        .filter(|f| {
+            graph.functions.get(*f).is_some_and(|data| {
+                !data.properties.contains(&Properties::Pure)
+                    && data.permissions.is_empty()
+                    && data.return_type != void
+                    // What a function passed in does isn't known
+                    && !data
+                        .args
+                        .iter()
+                        .any(|arg| matches!(arg.data_type, DataType::Function(_)))
+            })
+        })
+        .collect();
+    // Drop functions calling anything impure until nothing changes (recursion means one pass isn't enough)
+    let mut changed = true;
+    while changed {
+        let dropped: Vec<&FunctionId> = qualifying
+            .iter()
+            .filter(|f| {
+                graph
+                    .calls_from(f)
+                    .any(|call| !is_pure(graph, &call.callee) && !qualifying.contains(&call.callee))
+            })
+            .copied()
+            .collect();
+        changed = !dropped.is_empty();
+        for function in dropped {
+            qualifying.remove(function);
+        }
+    }
+    let mut lints: Vec<(String, CompilerProblem)> = Vec::new();
+    for function in qualifying {
+        let Some(source) = program.modules.get(&function.module).map(|m| &m.source) else {
+            continue;
+        };
+        let line = graph.lines[function];
+        // Add `Pure` to the function's `#Properties`, or give it a `#Properties` line
+        let (fix_line, replacement) = match annotation(program, graph, function, |g| {
+            matches!(g, Grammar::Property(_))
+        }) {
+            Some(node) => {
+                // Keep a trailing comment after the added `Pure`
+                let text = source.lines().nth(node.source_line).unwrap_or("");
+                let (code, comment) = text.split_at(comment_start(text));
+                (
+                    node.source_line,
+                    format!("{} Pure{comment}", code.trim_end()),
+                )
+            }
+            None => {
+                let text = source.lines().nth(line).unwrap_or("");
+                let indent = &text[..text.len() - text.trim_start().len()];
+                (line, format!("{text}\n{indent}    #Properties :: Pure"))
+            }
+        };
+        lints.push((
+            function.module.clone(),
+            CompilerProblem::new(
+                ProblemClass::Lint,
+                &format!(
+                    "`{}` has no side effects, so it can be `Pure`",
+                    function.name
+                ),
+                "mark it with `#Properties :: Pure` (or run with `--fix`)",
+                line,
+                1,
+            )
+            .with_fix(fix_line, Some(replacement)),
+        ));
+    }
+    lints
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler_errors::apply_fixes;
+    use crate::modules::tests::project;
+
+    #[test]
+    fn check_pure_functions() {
+        let root = project(
+            "purity",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn main :: void {
+    let b :: int = twice 2
+    let c :: str = shout \"hi\"
+}
+fn twice :: n int -> int {
+    #Properties :: Pure
+    return double n
+}
+fn double :: n int -> int {
+    return * n 2
+}
+fn shout :: text str -> str {
+    #Properties :: Pure
+    #Permissions :: ReadFile
+    println text
+    return read_file text
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let graph = CallGraph::build(&program);
+        let problems: Vec<CompilerProblem> = check_purity(&program, &graph)
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect();
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`shout` is `Pure`, but declares permissions",
+                "`shout` is `Pure`, but calls `println`, which performs I/O",
+                "`shout` is `Pure`, but calls `read_file`, which performs I/O",
+                "`twice` is `Pure`, but calls `double`, which isn't `Pure`",
+            ]
+        );
+        assert_eq!((problems[1].line, problems[1].word_index), (15, 0));
+        assert_eq!(problems[3].related.as_ref().unwrap().line, 9);
+    }
+
+    #[test]
+    fn suggest_pure() {
+        let root = project(
+            "purity-lints",
+            &[
+                (
+                    "main.iona",
+                    "import half from helpers
+fn main :: void {
+    let b :: int = double 2
+    let c :: int = half 4
+    let d :: int = triple 2
+}
+fn double :: n int -> int {
+    #Properties :: Export
+    return * n 2
+}
+fn triple :: n int -> int {
+    return * n 3
+}",
+                ),
+                (
+                    "helpers.iona",
+                    "fn half :: n int -> int {
+    #Properties :: Export
+    return / n 2
+}
+fn pair :: (str, str) {
+    return (\"a
+}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let graph = CallGraph::build(&program);
+        let lints = pure_lints(&program, &graph);
+        // `helpers` has an error, so neither of its functions is suggested
+        let messages: Vec<(&str, &str)> = lints
+            .iter()
+            .map(|(m, p)| (m.as_str(), p.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("main", "`double` has no side effects, so it can be `Pure`"),
+                ("main", "`triple` has no side effects, so it can be `Pure`"),
+            ]
+        );
+        let lints: Vec<CompilerProblem> = lints.into_iter().map(|(_, p)| p).collect();
+        let (fixed, count) = apply_fixes(&program.modules["main"].source, &lints);
+        assert_eq!(count, 2);
+        assert!(fixed.contains("    #Properties :: Export Pure\n"));
+        assert!(fixed.contains("fn triple :: n int -> int {\n    #Properties :: Pure\n    return"));
+        // `Pure` goes before a trailing comment
+        let line = "    #Properties :: Export // used by main";
+        assert_eq!(&line[..comment_start(line)], "    #Properties :: Export");
+        assert_eq!(comment_start("a //b"), 5);
+    }
+}
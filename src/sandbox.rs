@@ -0,0 +1,389 @@
+//! Runtime enforcement on Linux: the entry point installs a seccomp-bpf filter allowing only the system calls its permissions need
+//!
+//! The static checks can't see into C code linked with the program, so as a second line of defence `main` starts by installing a filter derived from every permission reachable from it (including ones absorbed with `#Trusted`, which still happen at runtime). System calls that only a missing permission would need are trapped, and the `SIGSYS` handler aborts naming that permission. Scopes can't be checked by the kernel, so only the kind of permission counts: `ReadFile("/etc/*")` allows opening any file for reading.
+//!
+//! | Missing permission | Trapped system calls |
+//! | --- | --- |
+//! | `ReadFile` (unless `main` has `SpawnProcess`) | `open`/`openat` for reading only, `openat2` |
+//! | `WriteFile` | `open`/`openat` for writing, creating, truncating or appending, `openat2`, `creat`, and calls that rename, remove or create files, make links, or change permissions, owners or timestamps |
+//! | `ReadNetwork` | `recvfrom`, `recvmsg`, `recvmmsg`, `accept`, `accept4`, `listen`, `bind` |
+//! | `WriteNetwork` | `connect`, `sendto`, `sendmsg`, `sendmmsg` |
+//! | both network permissions | `socket` too |
+//! | `SpawnProcess` | `execve`, `execveat` |
+//! | any of the above | `io_uring_setup`, `io_uring_enter`, `io_uring_register` (the kernel runs io_uring requests without the filter seeing them) |
+//!
+//! The filter stays in place across `execve`, so programs started with `SpawnProcess` are held to the same limits. They couldn't start at all without reading their executable and shared libraries, so `SpawnProcess` also lets files be opened for reading (the `read_file` builtin still needs `ReadFile`).
+//!
+//! The other permissions (like `WriteConsole`) use system calls every program needs, so only the builtins check them.
+//!
+//! If the filter can't be installed (on a kernel without seccomp, say), the program aborts rather than run without it. `--no-sandbox` builds a program that doesn't try.
+//!
+//! Other platforms (and architectures other than x86-64 and AArch64) compile the filter out.
+
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::call_graph::{CallGraph, FunctionId};
+use crate::modules::Program;
+use crate::permissions::Permissions;
+
+/// Installs the filter, given the `IONA_*` flags of the permissions `main` may use (see `C_RUNTIME` in `codegen_c`)
+pub const C_SANDBOX: &str = r#"#if defined(__linux__) && (defined(__x86_64__) || defined(__aarch64__))
+#include <fcntl.h>
+#include <signal.h>
+#include <stddef.h>
+#include <unistd.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+#include <linux/audit.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+
+#if defined(__x86_64__)
+#define IONA_AUDIT_ARCH AUDIT_ARCH_X86_64
+#else
+#define IONA_AUDIT_ARCH AUDIT_ARCH_AARCH64
+#endif
+#ifndef SECCOMP_RET_KILL_PROCESS
+#define SECCOMP_RET_KILL_PROCESS SECCOMP_RET_KILL
+#endif
+#define IONA_NETWORK (IONA_READ_NETWORK | IONA_WRITE_NETWORK)
+#define IONA_SANDBOXED (IONA_READ_FILE | IONA_WRITE_FILE | IONA_NETWORK | IONA_SPAWN_PROCESS)
+#define IONA_OPEN_FOR_WRITING (O_WRONLY | O_RDWR | O_CREAT | O_TRUNC | O_APPEND)
+
+/* The trapped call's permission arrives in si_errno (the filter's SECCOMP_RET_DATA) */
+static void iona_blocked(int signal, siginfo_t* info, void* context) {
+    (void)signal;
+    (void)context;
+    const char* message;
+    switch (info->si_errno) {
+    case IONA_READ_FILE: message = "iona: blocked a system call needing ReadFile, which main doesn't have\n"; break;
+    case IONA_WRITE_FILE: message = "iona: blocked a system call needing WriteFile, which main doesn't have\n"; break;
+    case IONA_READ_NETWORK: message = "iona: blocked a system call needing ReadNetwork, which main doesn't have\n"; break;
+    case IONA_WRITE_NETWORK: message = "iona: blocked a system call needing WriteNetwork, which main doesn't have\n"; break;
+    case IONA_SPAWN_PROCESS: message = "iona: blocked a system call needing SpawnProcess, which main doesn't have\n"; break;
+    case IONA_READ_FILE | IONA_WRITE_FILE: message = "iona: blocked a system call needing ReadFile and WriteFile, which main doesn't have both of\n"; break;
+    case IONA_NETWORK: message = "iona: blocked a system call needing ReadNetwork or WriteNetwork, which main doesn't have\n"; break;
+    case IONA_SANDBOXED: message = "iona: blocked io_uring, which would get around the permissions main doesn't have\n"; break;
+    default: message = "iona: blocked a system call main doesn't have the permissions for\n"; break;
+    }
+    (void)!write(2, message, strlen(message));
+    /* Not iona_abort: stdio isn't safe to use in a signal handler */
+    abort();
+}
+
+static void iona_install_sandbox(unsigned allowed) {
+    struct sock_filter filter[160];
+    unsigned short length = 0;
+#define IONA_FILTER(instruction) filter[length++] = (struct sock_filter)instruction
+#define IONA_TRAP(flag) ((allowed & (flag)) == (flag) ? SECCOMP_RET_ALLOW : SECCOMP_RET_TRAP | (flag))
+/* Trap a system call unless every permission in `flag` is allowed */
+#define IONA_DENY(number, flag) \
+    if ((allowed & (flag)) != (flag)) { \
+        IONA_FILTER(BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, (number), 0, 1)); \
+        IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_TRAP | (flag))); \
+    }
+/* Opening a file needs WriteFile or ReadFile, depending on its flags */
+#define IONA_OPEN(number, flags_argument) \
+    IONA_FILTER(BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, (number), 0, 4)); \
+    IONA_FILTER(BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, args[flags_argument]))); \
+    IONA_FILTER(BPF_JUMP(BPF_JMP | BPF_JSET | BPF_K, IONA_OPEN_FOR_WRITING, 0, 1)); \
+    IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, IONA_TRAP(IONA_WRITE_FILE))); \
+    IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, IONA_TRAP(IONA_READ_FILE)));
+
+    /* Spawned programs inherit the filter, and have to read their executable and libraries to start */
+    if (allowed & IONA_SPAWN_PROCESS) {
+        allowed |= IONA_READ_FILE;
+    }
+    if ((allowed & IONA_SANDBOXED) == IONA_SANDBOXED) {
+        return;
+    }
+    IONA_FILTER(BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, arch)));
+    IONA_FILTER(BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, IONA_AUDIT_ARCH, 1, 0));
+    IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS));
+    IONA_FILTER(BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, nr)));
+#if defined(__x86_64__)
+    /* x32 system calls would get around the numbers below */
+    IONA_FILTER(BPF_JUMP(BPF_JMP | BPF_JGE | BPF_K, 0x40000000, 0, 1));
+    IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS));
+    IONA_OPEN(SYS_open, 1);
+    IONA_DENY(SYS_creat, IONA_WRITE_FILE);
+    IONA_DENY(SYS_unlink, IONA_WRITE_FILE);
+    IONA_DENY(SYS_rename, IONA_WRITE_FILE);
+    IONA_DENY(SYS_mkdir, IONA_WRITE_FILE);
+    IONA_DENY(SYS_rmdir, IONA_WRITE_FILE);
+    IONA_DENY(SYS_link, IONA_WRITE_FILE);
+    IONA_DENY(SYS_symlink, IONA_WRITE_FILE);
+    IONA_DENY(SYS_chmod, IONA_WRITE_FILE);
+    IONA_DENY(SYS_chown, IONA_WRITE_FILE);
+    IONA_DENY(SYS_lchown, IONA_WRITE_FILE);
+    IONA_DENY(SYS_mknod, IONA_WRITE_FILE);
+    IONA_DENY(SYS_utime, IONA_WRITE_FILE);
+    IONA_DENY(SYS_utimes, IONA_WRITE_FILE);
+    IONA_DENY(SYS_futimesat, IONA_WRITE_FILE);
+#endif
+    IONA_OPEN(SYS_openat, 2);
+#ifdef SYS_openat2
+    IONA_DENY(SYS_openat2, IONA_READ_FILE | IONA_WRITE_FILE);
+#endif
+    IONA_DENY(SYS_unlinkat, IONA_WRITE_FILE);
+#ifdef SYS_renameat
+    IONA_DENY(SYS_renameat, IONA_WRITE_FILE);
+#endif
+#ifdef SYS_renameat2
+    IONA_DENY(SYS_renameat2, IONA_WRITE_FILE);
+#endif
+    IONA_DENY(SYS_mkdirat, IONA_WRITE_FILE);
+    IONA_DENY(SYS_truncate, IONA_WRITE_FILE);
+    IONA_DENY(SYS_linkat, IONA_WRITE_FILE);
+    IONA_DENY(SYS_symlinkat, IONA_WRITE_FILE);
+    IONA_DENY(SYS_fchmod, IONA_WRITE_FILE);
+    IONA_DENY(SYS_fchmodat, IONA_WRITE_FILE);
+#ifdef SYS_fchmodat2
+    IONA_DENY(SYS_fchmodat2, IONA_WRITE_FILE);
+#endif
+    IONA_DENY(SYS_fchown, IONA_WRITE_FILE);
+    IONA_DENY(SYS_fchownat, IONA_WRITE_FILE);
+    IONA_DENY(SYS_mknodat, IONA_WRITE_FILE);
+    IONA_DENY(SYS_utimensat, IONA_WRITE_FILE);
+    /* Either network permission needs a socket to work with */
+    if ((allowed & IONA_NETWORK) == 0) {
+        IONA_FILTER(BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, SYS_socket, 0, 1));
+        IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_TRAP | IONA_NETWORK));
+    }
+    IONA_DENY(SYS_connect, IONA_WRITE_NETWORK);
+    IONA_DENY(SYS_sendto, IONA_WRITE_NETWORK);
+    IONA_DENY(SYS_sendmsg, IONA_WRITE_NETWORK);
+    IONA_DENY(SYS_sendmmsg, IONA_WRITE_NETWORK);
+    IONA_DENY(SYS_recvfrom, IONA_READ_NETWORK);
+    IONA_DENY(SYS_recvmsg, IONA_READ_NETWORK);
+    IONA_DENY(SYS_recvmmsg, IONA_READ_NETWORK);
+    IONA_DENY(SYS_accept, IONA_READ_NETWORK);
+    IONA_DENY(SYS_accept4, IONA_READ_NETWORK);
+    IONA_DENY(SYS_listen, IONA_READ_NETWORK);
+    IONA_DENY(SYS_bind, IONA_READ_NETWORK);
+    IONA_DENY(SYS_execve, IONA_SPAWN_PROCESS);
+    IONA_DENY(SYS_execveat, IONA_SPAWN_PROCESS);
+    /* io_uring opens files and sockets without making the system calls above, so it needs every permission */
+#ifdef SYS_io_uring_setup
+    IONA_DENY(SYS_io_uring_setup, IONA_SANDBOXED);
+    IONA_DENY(SYS_io_uring_enter, IONA_SANDBOXED);
+    IONA_DENY(SYS_io_uring_register, IONA_SANDBOXED);
+#endif
+    IONA_FILTER(BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+#undef IONA_OPEN
+#undef IONA_DENY
+#undef IONA_TRAP
+#undef IONA_FILTER
+
+    struct sigaction action;
+    memset(&action, 0, sizeof(action));
+    action.sa_sigaction = iona_blocked;
+    action.sa_flags = SA_SIGINFO;
+    sigaction(SIGSYS, &action, NULL);
+    struct sock_fprog program = { .len = length, .filter = filter };
+    if (prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 || prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &program) != 0) {
+        fprintf(stderr, "iona: unable to install the seccomp filter (build with --no-sandbox to run without it)\n");
+        iona_abort();
+    }
+}
+#else
+static void iona_install_sandbox(unsigned allowed) {
+    (void)allowed;
+}
+#endif
+"#;
+
+/// The built-in permissions the program may use at runtime: everything required or trusted by a function reachable from the entry module's `main`, without scopes
+///
+/// Returns `None` if the entry module has no `main`
+pub fn runtime_permissions(program: &Program) -> Option<Vec<Permissions>> {
+    let graph = CallGraph::build(program);
+    let required = graph.required_permissions();
+    let main = FunctionId {
+        module: program.entry.clone(),
+        name: "main".to_string(),
+    };
+    if !graph.lines.contains_key(&main) {
+        return None;
+    }
+    let mut reached: BTreeSet<&FunctionId> = BTreeSet::from([&main]);
+    let mut queue: VecDeque<&FunctionId> = VecDeque::from([&main]);
+    let mut used: BTreeSet<Permissions> = BTreeSet::new();
+    while let Some(function) = queue.pop_front() {
+        used.extend(
+            required
+                .get(function)
+                .into_iter()
+                .flat_map(|r| r.keys().cloned()),
+        );
+        if let Some(data) = graph.functions.get(function) {
+            used.extend(
+                data.trusted
+                    .iter()
+                    .flat_map(|t| t.permissions.iter().cloned()),
+            );
+        }
+        for call in graph.calls_from(function) {
+            if reached.insert(&call.callee) {
+                queue.push_back(&call.callee);
+            }
+        }
+    }
+    let builtins: BTreeSet<Permissions> = used
+        .iter()
+        .flat_map(|p| graph.hierarchy.implied(p))
+        .map(|p| p.unscoped().clone())
+        .filter(|p| !matches!(p, Permissions::Custom(_)))
+        .collect();
+    Some(builtins.into_iter().collect())
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen_c::tests::compile_and_run;
+    use crate::codegen_c::CodegenOptions;
+    use crate::modules::tests::project;
+
+    #[test]
+    fn permissions_reachable_from_main() {
+        let root = project(
+            "sandbox",
+            &[(
+                "main.iona",
+                "import read_file write_file from std.files
+permission Config :: ReadFile(\"/etc/app/*\")
+fn main :: void {
+    let config :: str = load_config
+}
+fn load_config :: str {
+    #Trusted :: Config \"only reads the config file at startup\"
+    return read_file \"/etc/app/config\"
+}
+fn unused :: void {
+    #Permissions :: WriteFile
+    write_file \"x\" \"y\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert_eq!(
+            runtime_permissions(&program),
+            Some(vec![Permissions::ReadFile])
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn sandboxed_child_process() {
+        let root = project(
+            "sandbox-child",
+            &[(
+                "main.iona",
+                "import run_command from std.process
+fn main :: void {
+    #Permissions :: SpawnProcess WriteConsole
+    let status :: int = run_command \"uname\"
+    println \"parent\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let module = &program.modules["main"];
+        assert!(module.problems.is_empty(), "{:#?}", module.problems);
+        let options = CodegenOptions {
+            sandbox: runtime_permissions(&program),
+            ..Default::default()
+        };
+        let Some(output) = compile_and_run(&program, &root, &options) else {
+            eprintln!("skipping: no C compiler");
+            return;
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("unable to install the seccomp filter") {
+            eprintln!("skipping: seccomp isn't available");
+            return;
+        }
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "Linux\nparent\n");
+    }
+
+    /// Runs C code in a sandboxed `main` allowing `allowed` (like `IONA_READ_NETWORK`), or `None` if it can't be run
+    #[cfg(target_os = "linux")]
+    fn run_sandboxed(test_name: &str, allowed: &str, body: &str) -> Option<std::process::Output> {
+        use std::process::Command;
+        let root = project(test_name, &[]);
+        std::fs::create_dir_all(&*root).unwrap();
+        let source = format!(
+            "#include <sys/socket.h>\n#include <sys/stat.h>\n{}\n{C_SANDBOX}\nint main(void) {{\n    iona_install_sandbox({allowed});\n    {body}\n    printf(\"done\\n\");\n    return 0;\n}}\n",
+            crate::codegen_c::C_RUNTIME
+        );
+        std::fs::write(root.join("main.c"), source).unwrap();
+        let executable = root.join("program");
+        let compiled = Command::new("cc")
+            .arg(root.join("main.c"))
+            .arg("-o")
+            .arg(&executable)
+            .status()
+            .ok()?;
+        assert!(compiled.success());
+        let output = Command::new(&executable).output().unwrap();
+        match String::from_utf8_lossy(&output.stderr)
+            .contains("unable to install the seccomp filter")
+        {
+            true => None,
+            false => Some(output),
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn sandbox_filter() {
+        let open_socket = "if (socket(AF_INET, SOCK_STREAM, 0) < 0) return 1;";
+        let cases = [
+            // Either network permission is enough for a socket, a listener or a client
+            ("sandbox-listener", "IONA_READ_NETWORK", open_socket, None),
+            ("sandbox-client", "IONA_WRITE_NETWORK", open_socket, None),
+            (
+                "sandbox-no-network",
+                "IONA_READ_FILE",
+                open_socket,
+                Some("needing ReadNetwork or WriteNetwork"),
+            ),
+            (
+                "sandbox-link",
+                "IONA_READ_FILE",
+                "symlink(\"/etc/passwd\", \"link\");",
+                Some("needing WriteFile"),
+            ),
+            (
+                "sandbox-chmod",
+                "IONA_READ_FILE",
+                "chmod(\"/tmp\", 0777);",
+                Some("needing WriteFile"),
+            ),
+            (
+                "sandbox-io-uring",
+                "IONA_READ_FILE | IONA_WRITE_FILE | IONA_NETWORK",
+                "char params[120] = {0}; syscall(SYS_io_uring_setup, 1, params);",
+                Some("blocked io_uring"),
+            ),
+        ];
+        for (name, allowed, body, blocked) in cases {
+            let Some(output) = run_sandboxed(name, allowed, body) else {
+                eprintln!("skipping: no C compiler, or seccomp isn't available");
+                return;
+            };
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match blocked {
+                None => assert!(output.status.success(), "{name}: {output:?}"),
+                Some(message) => {
+                    assert!(!output.status.success(), "{name}: {output:?}");
+                    assert!(stderr.contains(message), "{name}: {stderr}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! The standard library is built into the compiler: its functions are declared here and implemented by the C runtime
+//!
+//! Every module under `std` is virtual (there is no `.iona` file behind it), so `import read_file from std.files` resolves here instead of on disk.
+
+use std::collections::BTreeMap;
+
+use crate::parse::{DataType, FunctionData, PrimitiveDataType, Variable};
+use crate::permissions::Permissions;
+use crate::properties::Properties;
+
+/// The module whose functions are available everywhere without an import
+pub const PRELUDE: &str = "std.io";
+
+/// A function provided by the standard library
+pub struct BuiltinFunction {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub args: &'static [(&'static str, PrimitiveDataType)],
+    pub return_type: PrimitiveDataType,
+    pub properties: &'static [Properties],
+    pub permissions: &'static [Permissions],
+    /// The argument holding the path or URL the call touches, which scoped permissions are checked against
+    pub scoped_by: Option<usize>,
+    /// The C implementation (or `None` if the C standard library already provides it)
+    pub c_definition: Option<&'static str>,
+}
+
+pub const STDLIB: [BuiltinFunction; 11] = [
+    BuiltinFunction {
+        module: "std.io",
+        name: "println",
+        args: &[("text", PrimitiveDataType::Str)],
+        return_type: PrimitiveDataType::Void,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::WriteConsole],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline void println(char* text) {
+    iona_require(IONA_WRITE_CONSOLE, \"WriteConsole\");
+    puts(text);
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.io",
+        name: "eprintln",
+        args: &[("text", PrimitiveDataType::Str)],
+        return_type: PrimitiveDataType::Void,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::WriteConsole],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline void eprintln(char* text) {
+    iona_require(IONA_WRITE_CONSOLE, \"WriteConsole\");
+    fprintf(stderr, \"%s\\n\", text);
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.io",
+        name: "read_line",
+        args: &[],
+        return_type: PrimitiveDataType::Str,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::ReadConsole],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline char* read_line(void) {
+    iona_require(IONA_READ_CONSOLE, \"ReadConsole\");
+    size_t capacity = 128, length = 0;
+    char* line = malloc(capacity);
+    int c;
+    while ((c = getchar()) != EOF && c != '\\n') {
+        if (length + 1 == capacity) line = realloc(line, capacity *= 2);
+        line[length++] = (char)c;
+    }
+    line[length] = '\\0';
+    return line;
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.files",
+        name: "read_file",
+        args: &[("path", PrimitiveDataType::Str)],
+        return_type: PrimitiveDataType::Str,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::ReadFile],
+        scoped_by: Some(0),
+        c_definition: Some(
+            "static inline char* read_file(char* path) {
+    iona_require(IONA_READ_FILE, \"ReadFile\");
+    FILE* file = fopen(path, \"rb\");
+    if (file == NULL) {
+        fprintf(stderr, \"iona: unable to read file %s\\n\", path);
+        iona_abort();
+    }
+    fseek(file, 0, SEEK_END);
+    long length = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    char* data = malloc((size_t)length + 1);
+    size_t read = fread(data, 1, (size_t)length, file);
+    data[read] = '\\0';
+    fclose(file);
+    return data;
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.files",
+        name: "write_file",
+        args: &[("data", PrimitiveDataType::Str), ("path", PrimitiveDataType::Str)],
+        return_type: PrimitiveDataType::Void,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::WriteFile],
+        scoped_by: Some(1),
+        c_definition: Some(
+            "static inline void write_file(char* data, char* path) {
+    iona_require(IONA_WRITE_FILE, \"WriteFile\");
+    FILE* file = fopen(path, \"wb\");
+    if (file == NULL) {
+        fprintf(stderr, \"iona: unable to write file %s\\n\", path);
+        iona_abort();
+    }
+    fputs(data, file);
+    fclose(file);
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.networking",
+        name: "request",
+        args: &[
+            ("method", PrimitiveDataType::Str),
+            ("url", PrimitiveDataType::Str),
+            ("body", PrimitiveDataType::Str),
+        ],
+        return_type: PrimitiveDataType::Str,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::ReadNetwork, Permissions::WriteNetwork],
+        scoped_by: Some(1),
+        c_definition: Some(
+            "static inline char* request(char* method, char* url, char* body) {
+    iona_require(IONA_READ_NETWORK | IONA_WRITE_NETWORK, \"ReadNetwork and WriteNetwork\");
+    (void)body;
+    fprintf(stderr, \"iona: unable to %s %s, networking is not supported by the C runtime yet\\n\", method, url);
+    iona_abort();
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.env",
+        name: "get_env",
+        args: &[("name", PrimitiveDataType::Str)],
+        return_type: PrimitiveDataType::Str,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::ReadEnvironment],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline char* get_env(char* name) {
+    iona_require(IONA_READ_ENVIRONMENT, \"ReadEnvironment\");
+    char* value = getenv(name);
+    return value == NULL ? \"\" : value;
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.process",
+        name: "run_command",
+        args: &[("command", PrimitiveDataType::Str)],
+        return_type: PrimitiveDataType::Int,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::SpawnProcess],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline long run_command(char* command) {
+    iona_require(IONA_SPAWN_PROCESS, \"SpawnProcess\");
+    fflush(stdout);
+    return system(command);
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.time",
+        name: "now",
+        args: &[],
+        return_type: PrimitiveDataType::Int,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::ReadClock],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline long now(void) {
+    iona_require(IONA_READ_CLOCK, \"ReadClock\");
+    return (long)time(NULL);
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.random",
+        name: "random_int",
+        args: &[("low", PrimitiveDataType::Int), ("high", PrimitiveDataType::Int)],
+        return_type: PrimitiveDataType::Int,
+        properties: &[Properties::Export],
+        permissions: &[Permissions::ReadRandom],
+        scoped_by: None,
+        c_definition: Some(
+            "static inline long random_int(long low, long high) {
+    iona_require(IONA_READ_RANDOM, \"ReadRandom\");
+    static int seeded = 0;
+    if (!seeded) {
+        srand((unsigned)time(NULL) ^ (unsigned)clock());
+        seeded = 1;
+    }
+    if (high < low) {
+        fprintf(stderr, \"iona: random_int needs low <= high, got %ld and %ld\\n\", low, high);
+        iona_abort();
+    }
+    /* Unsigned, so the widest range doesn't overflow; rand() only gives 15 bits for sure, so draw 75 */
+    unsigned long span = (unsigned long)high - (unsigned long)low;
+    unsigned long bits;
+    do {
+        bits = 0;
+        for (int i = 0; i < 5; i++) {
+            bits = (bits << 15) ^ (unsigned long)(rand() & 0x7fff);
+        }
+        /* Redraw the few values that would make some results likelier than others */
+    } while (span != (unsigned long)-1 && bits < -(span + 1) % (span + 1));
+    if (span == (unsigned long)-1) {
+        return (long)bits;
+    }
+    return (long)((unsigned long)low + bits % (span + 1));
+}",
+        ),
+    },
+    BuiltinFunction {
+        module: "std.math",
+        name: "sqrt",
+        args: &[("input", PrimitiveDataType::Float)],
+        return_type: PrimitiveDataType::Float,
+        properties: &[Properties::Pure, Properties::Export],
+        permissions: &[],
+        scoped_by: None,
+        c_definition: None,
+    },
+];
+
+/// Is this (dotted) module name part of the standard library namespace?
+pub fn is_stdlib_module(module: &str) -> bool {
+    module == "std" || module.starts_with("std.")
+}
+
+/// Does the standard library provide this module?
+pub fn module_exists(module: &str) -> bool {
+    STDLIB.iter().any(|f| f.module == module)
+}
+
+/// The builtin with this (original) name in a standard library module
+pub fn builtin(module: &str, name: &str) -> Option<&'static BuiltinFunction> {
+    STDLIB.iter().find(|f| f.module == module && f.name == name)
+}
+
+/// The argument of a builtin that its scoped permissions are checked against
+pub fn scoped_argument(module: &str, name: &str) -> Option<usize> {
+    builtin(module, name).and_then(|f| f.scoped_by)
+}
+
+/// Build the function table of a standard library module
+pub fn function_table(module: &str) -> BTreeMap<String, FunctionData> {
+    let mut table: BTreeMap<String, FunctionData> = BTreeMap::new();
+    for builtin in STDLIB.iter().filter(|f| f.module == module) {
+        let mut data = FunctionData::new();
+        data.args = builtin
+            .args
+            .iter()
+            .map(|(name, data_type)| Variable {
+                name: name.to_string(),
+                data_type: DataType::Primitive(*data_type),
+                value: None,
+            })
+            .collect();
+        data.return_type = DataType::Primitive(builtin.return_type);
+        data.properties = builtin.properties.to_vec();
+        data.permissions = builtin.permissions.to_vec();
+        table.insert(builtin.name.to_string(), data);
+    }
+    table
+}
+
+/// A wrapper around a builtin needing permissions, which logs each one with where it was called from before calling it (see `C_TRACE` in `codegen_c`)
+///
+/// Called as `iona_traced_read_file("main.load", 12, path)`, the logged argument is the path or URL the call touches
+fn c_traced_definition(builtin: &BuiltinFunction) -> String {
+    let mut parameters: Vec<String> = vec![
+        "const char* iona_function".to_string(),
+        "int iona_line".to_string(),
+    ];
+    parameters.extend(
+        builtin
+            .args
+            .iter()
+            .map(|(name, data_type)| format!("{} {name}", data_type.to_str())),
+    );
+    let argument = match builtin.scoped_by {
+        Some(position) => builtin.args[position].0,
+        None => "\"-\"",
+    };
+    let mut definition = format!(
+        "static inline {} iona_traced_{}({}) {{\n",
+        builtin.return_type.to_str(),
+        builtin.name,
+        parameters.join(", ")
+    );
+    for permission in builtin.permissions {
+        definition +=
+            &format!("    iona_trace(\"{permission}\", iona_function, iona_line, {argument});\n");
+    }
+    let names: Vec<&str> = builtin.args.iter().map(|(name, _)| *name).collect();
+    let call = format!("{}({})", builtin.name, names.join(", "));
+    definition += &match builtin.return_type {
+        PrimitiveDataType::Void => format!("    {call};\n}}"),
+        _ => format!("    return {call};\n}}"),
+    };
+    definition
+}
+
+/// The C definitions of the builtins a module can call (i.e. the ones in its function table), with tracing wrappers if `traced`
+pub fn c_definitions(function_table: &BTreeMap<String, FunctionData>, traced: bool) -> String {
+    let mut definitions: String = "#include <math.h>\n#include <time.h>\n\n".to_string();
+    let mut defined: Vec<&str> = Vec::new();
+    for origin in function_table
+        .values()
+        .filter_map(|data| data.origin.as_ref())
+    {
+        let builtin = STDLIB
+            .iter()
+            .find(|f| f.module == origin.module && f.name == origin.name);
+        // The same builtin can be imported under several names
+        if let Some(builtin) = builtin.filter(|b| !defined.contains(&b.name)) {
+            defined.push(builtin.name);
+            if let Some(c) = builtin.c_definition {
+                definitions += c;
+                definitions += "\n\n";
+            }
+            if traced && !builtin.permissions.is_empty() {
+                definitions += &c_traced_definition(builtin);
+                definitions += "\n\n";
+            }
+        }
+    }
+    definitions
+}
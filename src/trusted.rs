@@ -0,0 +1,137 @@
+//! `#Trusted`: letting a function absorb a permission instead of passing it on to its callers
+//!
+//! Some wrappers hide a permission on purpose, like a function reading one config file at startup. `#Trusted :: ReadFile "only reads the config file at startup"` lets such a function call anything requiring `ReadFile` without declaring it, and stops `ReadFile` from spreading up the call graph (see `CallGraph::absorbs`). Every `#Trusted` line needs a justification, always gets a warning, and heads `iona audit`, so reviewers see each suppression in the dependency tree.
+
+use crate::call_graph::{english_list, CallGraph, RequiredPermissions};
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+
+/// A warning for every `#Trusted` line, and a lint for trusted permissions nothing needs, paired with their module
+pub fn trusted_warnings(
+    graph: &CallGraph,
+    required: &RequiredPermissions,
+) -> Vec<(String, CompilerProblem)> {
+    let mut problems: Vec<(String, CompilerProblem)> = Vec::new();
+    for function in graph.lines.keys() {
+        let Some(data) = graph.functions.get(function) else {
+            continue;
+        };
+        for trusted in data.trusted.iter() {
+            let listed: Vec<String> = trusted
+                .permissions
+                .iter()
+                .map(|p| format!("`{p}`"))
+                .collect();
+            problems.push((
+                function.module.clone(),
+                CompilerProblem::new(
+                    ProblemClass::Warning,
+                    &format!(
+                        "`{}` is trusted with {}, so its callers don't need {}: \"{}\"",
+                        function.name,
+                        english_list(listed),
+                        if trusted.permissions.len() == 1 { "it" } else { "them" },
+                        trusted.justification
+                    ),
+                    "`#Trusted` hides permissions from every caller, so check that the justification holds (`iona audit` lists every trusted function)",
+                    trusted.line,
+                    2,
+                ),
+            ));
+            for (position, permission) in trusted.permissions.iter().enumerate() {
+                let used = graph.calls_from(function).any(|call| {
+                    call.needed(required)
+                        .any(|p| graph.hierarchy.covers(std::slice::from_ref(permission), &p))
+                });
+                if !used {
+                    problems.push((
+                        function.module.clone(),
+                        CompilerProblem::new(
+                            ProblemClass::Lint,
+                            &format!(
+                                "`{}` is trusted with `{permission}`, but nothing it calls needs it",
+                                function.name
+                            ),
+                            &format!("remove `{permission}` from `#Trusted`"),
+                            trusted.line,
+                            2 + position,
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    problems
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_errors::ProblemClass;
+    use crate::modules::tests::project;
+    use crate::modules::Program;
+
+    #[test]
+    fn trusted_permissions_absorbed() {
+        let root = project(
+            "trusted",
+            &[(
+                "main.iona",
+                "import read_file write_file from std.files
+fn main :: void {
+    let config :: str = load_config \"app.toml\"
+    write_file config \"copy.toml\"
+}
+fn load_config :: path str -> str {
+    #Trusted :: ReadFile WriteFile \"only reads the config file at startup\"
+    return read_file path
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let problems: Vec<(&ProblemClass, &str, usize)> = program.modules["main"]
+            .problems
+            .iter()
+            .map(|p| (&p.class, p.message.as_str(), p.line))
+            .collect();
+        // `main` still needs `WriteFile` for its own call, but not `ReadFile`
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    &ProblemClass::Error,
+                    "`main` calls `write_file`, which requires `WriteFile(\"copy.toml\")`, but `main` doesn't have that permission",
+                    3
+                ),
+                (
+                    &ProblemClass::Warning,
+                    "`load_config` is trusted with `ReadFile` and `WriteFile`, so its callers don't need them: \"only reads the config file at startup\"",
+                    6
+                ),
+                (
+                    &ProblemClass::Lint,
+                    "`load_config` is trusted with `WriteFile`, but nothing it calls needs it",
+                    6
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn trusted_needs_justification() {
+        let root = project(
+            "trusted_justification",
+            &[(
+                "main.iona",
+                "import read_file from std.files
+fn main :: void {
+    #Trusted :: ReadFile
+    println \"hi\"
+}",
+            )],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        assert!(program.modules["main"].problems.iter().any(|p| p.message
+            == "`#Trusted` needs a justification, saying why hiding these permissions from callers is safe"));
+    }
+}
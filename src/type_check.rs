@@ -0,0 +1,894 @@
+//! Type checking: every value is given a type, and every place a value ends up (a function argument, a variable, a return value) is checked against the type declared for it
+//!
+//! Names whose type is unknown (because it was left to inference, or because the name doesn't exist) are skipped rather than reported, so one mistake doesn't cascade into many.
+
+use std::collections::BTreeMap;
+
+use crate::compiler_errors::{CompilerProblem, ProblemClass};
+use crate::grammars::{AssignmentTypes, Grammar, GrammarVariableAssignments};
+use crate::parse::{
+    enclosing_function, find_binding, rhs_node, DataType, FunctionData, FunctionType, Node,
+    NodeType, PrimitiveDataType,
+};
+use crate::parse_expressions::{Expression, Literal, Location, Object, Operator};
+
+pub struct TypeChecker<'a> {
+    nodes: &'a [Node],
+    function_table: &'a BTreeMap<String, FunctionData>,
+    pub problems: Vec<CompilerProblem>,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(
+        nodes: &'a [Node],
+        function_table: &'a BTreeMap<String, FunctionData>,
+    ) -> TypeChecker<'a> {
+        TypeChecker {
+            nodes,
+            function_table,
+            problems: Vec::new(),
+        }
+    }
+
+    /// The line a function is declared on, if it's declared in this module
+    fn function_line(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().find_map(|node| match &node.grammar {
+            Grammar::Function(g) if g.fn_name == name => Some(node.source_line),
+            _ => None,
+        })
+    }
+
+    fn mismatch(
+        expected: &DataType,
+        found: &DataType,
+        location: Location,
+        hint: &str,
+    ) -> CompilerProblem {
+        CompilerProblem::new(
+            ProblemClass::Error,
+            &format!("expected {expected}, found {found}"),
+            hint,
+            location.line,
+            location.word,
+        )
+    }
+
+    /// Check a value against the type it must have, `declared` explains where that type came from
+    fn expect(
+        &mut self,
+        index: usize,
+        value: &Object,
+        expected: &DataType,
+        hint: &str,
+        declared: Option<(String, usize)>,
+    ) {
+        let found = self.type_of(index, value);
+        self.compare(found, value, expected, hint, declared);
+    }
+
+    /// Check an argument against the type of the parameter it's passed to, which (unlike other values) may be a function passed by name
+    fn expect_argument(
+        &mut self,
+        index: usize,
+        value: &Object,
+        expected: &DataType,
+        hint: &str,
+        declared: Option<(String, usize)>,
+    ) {
+        let found = match value {
+            Object::Value(Literal::Function(name), location) => {
+                self.type_of_reference(index, name, *location)
+            }
+            _ => self.type_of(index, value),
+        };
+        self.compare(found, value, expected, hint, declared);
+    }
+
+    fn compare(
+        &mut self,
+        found: Option<DataType>,
+        value: &Object,
+        expected: &DataType,
+        hint: &str,
+        declared: Option<(String, usize)>,
+    ) {
+        if let Some(found) = found {
+            if !expected.accepts(&found) {
+                let mut problem = Self::mismatch(expected, &found, value.location(), hint);
+                if let Some((message, line)) = declared {
+                    problem = problem.with_related(&message, line, 0);
+                }
+                self.problems.push(problem);
+            }
+        }
+    }
+
+    /// The type of a function passed by name, like `&sqrt`: a function in the table, or a function this one was passed
+    fn type_of_reference(
+        &mut self,
+        index: usize,
+        name: &str,
+        location: Location,
+    ) -> Option<DataType> {
+        let problem = |message: String, hint: &str| {
+            CompilerProblem::new(
+                ProblemClass::Error,
+                &message,
+                hint,
+                location.line,
+                location.word,
+            )
+        };
+        // Arguments shadow functions
+        let binding = find_binding(self.nodes, index, name);
+        match (binding, self.function_table.get(name)) {
+            (Some(binding), _) => match binding.data_type {
+                Some(t @ DataType::Function(_)) => Some(t),
+                Some(_) => {
+                    self.problems.push(problem(
+                        format!("`{name}` isn't a function"),
+                        "only functions can be passed with `&`",
+                    ));
+                    None
+                }
+                None => None,
+            },
+            (None, Some(function)) if function.args.is_empty() => {
+                self.problems.push(problem(
+                    format!(
+                        "`{name}` takes no arguments, so it can't be passed to another function"
+                    ),
+                    "functions without arguments can't be passed yet",
+                ));
+                None
+            }
+            (None, Some(function)) => Some(DataType::Function(Box::new(FunctionType {
+                args: function.args.iter().map(|a| a.data_type.clone()).collect(),
+                return_type: function.return_type.clone(),
+                permissions: None,
+            }))),
+            // Reported by `resolve_names`
+            (None, None) => None,
+        }
+    }
+
+    /// The type of an object, reporting any type errors inside it along the way
+    ///
+    /// Returns `None` when the type can't be known, which has either been reported already or is left to other passes
+    pub fn type_of(&mut self, index: usize, object: &Object) -> Option<DataType> {
+        match object {
+            Object::Value(Literal::Int(_), _) => Some(PrimitiveDataType::Int.into()),
+            Object::Value(Literal::Float(_), _) => Some(PrimitiveDataType::Float.into()),
+            Object::Value(Literal::Bool(_), _) => Some(PrimitiveDataType::Bool.into()),
+            Object::Value(Literal::Str(_), _) => Some(PrimitiveDataType::Str.into()),
+            Object::Value(Literal::Symbol(name), _) => {
+                find_binding(self.nodes, index, name)?.data_type
+            }
+            Object::Value(Literal::Function(name), location) => {
+                self.problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("`&{name}` can only be passed to a function"),
+                    "functions can't be stored in variables or returned yet",
+                    location.line,
+                    location.word,
+                ));
+                None
+            }
+            Object::Tuple(elements, _) => {
+                // Check every element, even after finding one we can't type
+                let types: Vec<Option<DataType>> =
+                    elements.iter().map(|e| self.type_of(index, e)).collect();
+                types
+                    .into_iter()
+                    .collect::<Option<Vec<DataType>>>()
+                    .map(DataType::Tuple)
+            }
+            // Ranges aren't values (placement is checked while parsing), but their bounds must be ints
+            Object::Range { start, end, .. } => {
+                let int: DataType = PrimitiveDataType::Int.into();
+                let hint = "ranges are made of ints, like `0..2`";
+                self.expect(index, start, &int, hint, None);
+                self.expect(index, end, &int, hint, None);
+                None
+            }
+            Object::Operation(Expression::Prefix { op, args, location }) => match op {
+                Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide => {
+                    self.type_of_arithmetic(index, op, args, *location)
+                }
+                Operator::Negate => {
+                    let found = self.type_of(index, args.first()?)?;
+                    if found == PrimitiveDataType::Int || found == PrimitiveDataType::Float {
+                        Some(found)
+                    } else {
+                        self.problems.push(Self::mismatch(
+                            &PrimitiveDataType::Int.into(),
+                            &found,
+                            args[0].location(),
+                            "only ints and floats can be negated",
+                        ));
+                        None
+                    }
+                }
+                Operator::Inverse => {
+                    let bool_type: DataType = PrimitiveDataType::Bool.into();
+                    self.expect(
+                        index,
+                        args.first()?,
+                        &bool_type,
+                        "only bools can be inverted",
+                        None,
+                    );
+                    Some(bool_type)
+                }
+                Operator::Index => self.type_of_index(index, &args[0], &args[1], *location),
+                Operator::Function { name } => self.type_of_call(index, name, args),
+            },
+        }
+    }
+
+    /// Arithmetic needs two ints or two floats, and gives back the same type
+    fn type_of_arithmetic(
+        &mut self,
+        index: usize,
+        op: &Operator,
+        args: &[Object],
+        location: Location,
+    ) -> Option<DataType> {
+        let symbol = match op {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            _ => "/",
+        };
+        let left = self.type_of(index, &args[0]);
+        let right = self.type_of(index, &args[1]);
+        let numeric = |t: &DataType| *t == PrimitiveDataType::Int || *t == PrimitiveDataType::Float;
+        for (found, arg) in [(&left, &args[0]), (&right, &args[1])] {
+            if let Some(found) = found.as_ref().filter(|t| !numeric(t)) {
+                self.problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("expected int or float, found {found}"),
+                    &format!("`{symbol}` only works on numbers"),
+                    arg.location().line,
+                    arg.location().word,
+                ));
+                return None;
+            }
+        }
+        match (left, right) {
+            (Some(left), Some(right)) if left != right => {
+                self.problems.push(
+                    Self::mismatch(
+                        &left,
+                        &right,
+                        args[1].location(),
+                        &format!("both sides of `{symbol}` must have the same type, there are no implicit conversions"),
+                    )
+                    .with_related(&format!("the left side is {left}"), location.line, location.word),
+                );
+                Some(left)
+            }
+            (Some(t), _) | (_, Some(t)) => Some(t),
+            (None, None) => None,
+        }
+    }
+
+    /// Strings are indexed by ints or ranges and give back a string, tuples give back the indexed element
+    fn type_of_index(
+        &mut self,
+        index: usize,
+        target: &Object,
+        position: &Object,
+        location: Location,
+    ) -> Option<DataType> {
+        let target_type = self.type_of(index, target);
+        // Ranges check their own bounds
+        if !matches!(position, Object::Range { .. }) {
+            self.expect(
+                index,
+                position,
+                &PrimitiveDataType::Int.into(),
+                "indices must be ints",
+                None,
+            );
+        } else {
+            self.type_of(index, position);
+        }
+        match target_type? {
+            t @ DataType::Primitive(PrimitiveDataType::Str) => Some(t),
+            DataType::Tuple(elements) => match position {
+                // Bounds were checked by `check_indexing`
+                Object::Value(Literal::Int(n), _) => elements.get(*n as usize).cloned(),
+                _ => None,
+            },
+            // Names and calls are reported by `check_indexing`, which can name the value
+            _ if matches!(
+                target,
+                Object::Value(Literal::Symbol(_), _)
+                    | Object::Operation(Expression::Prefix {
+                        op: Operator::Function { .. },
+                        ..
+                    })
+            ) =>
+            {
+                None
+            }
+            other => {
+                self.problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("cannot index into {other}"),
+                    "only strs and tuples can be indexed",
+                    location.line,
+                    location.word,
+                ));
+                None
+            }
+        }
+    }
+
+    /// Every argument must match the type the function declares for it
+    fn type_of_call(&mut self, index: usize, name: &str, args: &[Object]) -> Option<DataType> {
+        let Some(function) = self.function_table.get(name) else {
+            return self.type_of_argument_call(index, name, args);
+        };
+        let declared_line = self.function_line(name);
+        for (arg, parameter) in args.iter().zip(function.args.iter()) {
+            let explanation = format!(
+                "`{name}` declares `{}` as {}",
+                parameter.name, parameter.data_type
+            );
+            let (hint, declared) = match (&function.origin, declared_line) {
+                (None, Some(line)) => (
+                    "pass a value of the declared type".to_string(),
+                    Some((explanation, line)),
+                ),
+                (Some(origin), _) => (
+                    format!("{explanation} (in module `{}`)", origin.module),
+                    None,
+                ),
+                (None, None) => (explanation, None),
+            };
+            self.expect_argument(index, arg, &parameter.data_type, &hint, declared);
+        }
+        Some(function.return_type.clone())
+    }
+
+    /// Calls to a function passed in as an argument, which must match the argument's function type
+    fn type_of_argument_call(
+        &mut self,
+        index: usize,
+        name: &str,
+        args: &[Object],
+    ) -> Option<DataType> {
+        let binding = find_binding(self.nodes, index, name)?;
+        let Some(DataType::Function(function)) = &binding.data_type else {
+            return None;
+        };
+        let explanation = format!(
+            "`{name}` is declared as {}",
+            DataType::Function(function.clone())
+        );
+        for (arg, expected) in args.iter().zip(function.args.iter()) {
+            self.expect_argument(
+                index,
+                arg,
+                expected,
+                "pass a value of the declared type",
+                Some((explanation.clone(), binding.line)),
+            );
+        }
+        Some(function.return_type.clone())
+    }
+
+    /// `let x :: T = value` and `set x = value` (including `set x @ i = value`)
+    fn check_assignment(&mut self, index: usize, g: &GrammarVariableAssignments, value: &Object) {
+        let line = self.nodes[index].source_line;
+        if g.assignment_type == AssignmentTypes::Initialize {
+            if g.type_provided {
+                let declared = (format!("`{}` is declared as {}", g.name, g.data_type), line);
+                self.expect(
+                    index,
+                    value,
+                    &g.data_type,
+                    "give the variable a value of its declared type",
+                    Some(declared),
+                );
+            } else if let Some(found) = self.type_of(index, value) {
+                if found == PrimitiveDataType::Void {
+                    let location = value.location();
+                    self.problems.push(CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("`{}` is given a value of type void", g.name),
+                        "the function called here doesn't return anything",
+                        location.line,
+                        location.word,
+                    ));
+                }
+            }
+            return;
+        }
+        // Mutation
+        if let Some(position) = &g.index {
+            self.type_of_index_target_for_set(index, position);
+        }
+        let binding = match find_binding(self.nodes, index, &g.name) {
+            Some(b) => b,
+            None => {
+                self.type_of(index, value);
+                return;
+            }
+        };
+        let target = match (&g.index, binding.data_type) {
+            (_, None) => None,
+            (None, t) => t,
+            (Some(_), Some(t @ DataType::Primitive(PrimitiveDataType::Str))) => Some(t),
+            (Some(Object::Value(Literal::Int(n), _)), Some(DataType::Tuple(elements))) => {
+                elements.get(*n as usize).cloned()
+            }
+            (Some(_), Some(_)) => None,
+        };
+        let how = if binding.inferred {
+            "inferred"
+        } else {
+            "declared"
+        };
+        match target {
+            Some(expected) => {
+                let declared = (
+                    format!("`{}` is {how} as {}", g.name, expected),
+                    binding.line,
+                );
+                self.expect(
+                    index,
+                    value,
+                    &expected,
+                    "assign a value of the variable's type",
+                    Some(declared),
+                );
+            }
+            None => {
+                self.type_of(index, value);
+            }
+        }
+    }
+
+    /// The index in `set x @ i = ...` must be an int (or a range of ints)
+    fn type_of_index_target_for_set(&mut self, index: usize, position: &Object) {
+        if matches!(position, Object::Range { .. }) {
+            self.type_of(index, position);
+        } else {
+            self.expect(
+                index,
+                position,
+                &PrimitiveDataType::Int.into(),
+                "indices must be ints",
+                None,
+            );
+        }
+    }
+
+    /// `return` must give back a value of the function's declared return type
+    fn check_return(&mut self, index: usize, value: Option<&Object>) {
+        let Some(function) = enclosing_function(self.nodes, index) else {
+            return;
+        };
+        let name = function.fn_name.clone();
+        let expected = match self.function_table.get(&name) {
+            Some(f) => f.return_type.clone(),
+            None => function.return_type.clone(),
+        };
+        let function_line = self.function_line(&name).unwrap_or(0);
+        let line = self.nodes[index].source_line;
+        match value {
+            Some(value) if expected == PrimitiveDataType::Void => {
+                let location = value.location();
+                let mut problem = CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("`{name}` doesn't return a value, but this `return` gives one"),
+                    &format!("declare what `{name}` returns, like `fn {name} -> int`"),
+                    location.line,
+                    location.word,
+                );
+                if let Some(found) = self.type_of(index, value) {
+                    problem.message = format!("expected void, found {found}");
+                }
+                self.problems.push(problem.with_related(
+                    &format!("`{name}` doesn't declare a return type"),
+                    function_line,
+                    0,
+                ));
+            }
+            Some(value) => {
+                let declared = (format!("`{name}` returns {expected}"), function_line);
+                self.expect(
+                    index,
+                    value,
+                    &expected,
+                    "return a value of the declared return type",
+                    Some(declared),
+                );
+            }
+            None if expected != PrimitiveDataType::Void => {
+                self.problems.push(
+                    CompilerProblem::new(
+                        ProblemClass::Error,
+                        &format!("expected {expected}, found nothing"),
+                        &format!("`{name}` must return a value"),
+                        line,
+                        0,
+                    )
+                    .with_related(
+                        &format!("`{name}` returns {expected}"),
+                        function_line,
+                        0,
+                    ),
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Check every line of a module
+    pub fn check(&mut self) {
+        let mut index = 0;
+        while index < self.nodes.len() {
+            let node = &self.nodes[index];
+            // Lines like `let` and `return` consume the expression node that follows them
+            let rhs = rhs_node(self.nodes, index).and_then(|n| n.expression.as_ref());
+            let mut consumed_rhs = false;
+            match (&node.node_type, &node.grammar) {
+                (NodeType::VariableAssignment, Grammar::VariableAssignment(g)) => {
+                    if let Some(value) = rhs {
+                        consumed_rhs = true;
+                        self.check_assignment(index, g, value);
+                    }
+                }
+                (NodeType::ReturnStatement, _) => {
+                    consumed_rhs = rhs.is_some();
+                    self.check_return(index, rhs);
+                }
+                (NodeType::Expression, _) => {
+                    if let Some(value) = &node.expression {
+                        self.type_of(index, value);
+                    }
+                }
+                _ => {}
+            }
+            index += if consumed_rhs { 2 } else { 1 };
+        }
+    }
+}
+
+/// Type check a module, once its expressions have been parsed
+pub fn check_types(
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Vec<CompilerProblem> {
+    let mut checker = TypeChecker::new(nodes, function_table);
+    checker.check();
+    checker.problems
+}
+
+/// Infer the types of variables declared with `auto` (or without a type), and record them on their `let`
+///
+/// The initialiser decides the type. When it can't (because it uses names whose types aren't known), the first later use that pins the type down decides instead: a `set`, passing the variable to a function, or returning it.
+pub fn infer_types(
+    nodes: &mut [Node],
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Vec<CompilerProblem> {
+    let mut problems: Vec<CompilerProblem> = Vec::new();
+    for index in 0..nodes.len() {
+        let (name, destructure) = match &nodes[index].grammar {
+            Grammar::VariableAssignment(g)
+                if g.assignment_type == AssignmentTypes::Initialize && !g.type_provided =>
+            {
+                (g.name.clone(), g.destructure.clone())
+            }
+            _ => continue,
+        };
+        // Lines that never got a value have already been reported
+        let Some(value) = rhs_node(nodes, index).and_then(|n| n.expression.as_ref()) else {
+            continue;
+        };
+        let inferred = type_of(nodes, function_table, index, value).or_else(|| match destructure {
+            Some(_) => None,
+            None => type_from_uses(nodes, function_table, index, &name),
+        });
+        match inferred {
+            // A void initialiser is reported by the type checker
+            Some(t) if t == PrimitiveDataType::Void => {}
+            // A name that isn't in scope is reported by `resolve_names`
+            None if value
+                .symbols()
+                .iter()
+                .any(|(symbol, _)| find_binding(nodes, index, symbol).is_none()) => {}
+            // A destructuring arity mismatch is reported by `check_destructuring`
+            Some(t)
+                if destructure
+                    .as_ref()
+                    .is_some_and(|names| names.len() != t.arity()) => {}
+            Some(t) => {
+                if let Grammar::VariableAssignment(g) = &mut nodes[index].grammar {
+                    g.data_type = t;
+                    g.inferred = true;
+                }
+            }
+            None => {
+                let location = value.location();
+                problems.push(CompilerProblem::new(
+                    ProblemClass::Error,
+                    &format!("unable to infer the type of `{name}`"),
+                    &format!("write the type down, like `let {name} :: int = ...`"),
+                    location.line,
+                    location.word,
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// The type a variable must have according to how it's used after its `let` (in the same scope, until it's shadowed)
+fn type_from_uses(
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+    index: usize,
+    name: &str,
+) -> Option<DataType> {
+    let parent_line = nodes[index].parent_node_line;
+    for later in index + 1..nodes.len() {
+        let node = &nodes[later];
+        if node.parent_node_line != parent_line {
+            continue;
+        }
+        match (&node.node_type, &node.grammar) {
+            (_, Grammar::VariableAssignment(g)) if g.name == name => {
+                if g.assignment_type == AssignmentTypes::Initialize {
+                    // Shadowed
+                    return None;
+                }
+                if g.index.is_none() {
+                    let value = rhs_node(nodes, later).and_then(|n| n.expression.as_ref());
+                    if let Some(t) = value.and_then(|v| type_of(nodes, function_table, later, v)) {
+                        return Some(t);
+                    }
+                }
+            }
+            (NodeType::ReturnStatement, _) => {
+                let returned = rhs_node(nodes, later).and_then(|n| n.expression.as_ref());
+                if let Some(Object::Value(Literal::Symbol(returned), _)) = returned {
+                    if returned == name {
+                        let function = enclosing_function(nodes, later)?;
+                        return function_table
+                            .get(&function.fn_name)
+                            .map(|f| f.return_type.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(object) = &node.expression {
+            if let Some(t) = parameter_type_of(object, name, function_table) {
+                return Some(t);
+            }
+        }
+    }
+    None
+}
+
+/// The declared type of the first parameter a name is passed to as an argument
+fn parameter_type_of(
+    object: &Object,
+    name: &str,
+    function_table: &BTreeMap<String, FunctionData>,
+) -> Option<DataType> {
+    match object {
+        Object::Operation(Expression::Prefix { op, args, .. }) => {
+            if let Operator::Function { name: function } = op {
+                let parameters = &function_table.get(function)?.args;
+                for (arg, parameter) in args.iter().zip(parameters.iter()) {
+                    if matches!(arg, Object::Value(Literal::Symbol(n), _) if n == name) {
+                        return Some(parameter.data_type.clone());
+                    }
+                }
+            }
+            args.iter()
+                .find_map(|arg| parameter_type_of(arg, name, function_table))
+        }
+        Object::Tuple(elements, _) => elements
+            .iter()
+            .find_map(|e| parameter_type_of(e, name, function_table)),
+        _ => None,
+    }
+}
+
+/// The variables whose types were inferred, as `(line, name, type)`, for showing to the user
+pub fn inferred_types(nodes: &[Node]) -> Vec<(usize, String, DataType)> {
+    nodes
+        .iter()
+        .filter_map(|node| match &node.grammar {
+            Grammar::VariableAssignment(g) if g.inferred => {
+                Some((node.source_line, g.name.clone(), g.data_type.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The type of an object, without reporting problems (for passes that run after type checking)
+pub fn type_of(
+    nodes: &[Node],
+    function_table: &BTreeMap<String, FunctionData>,
+    index: usize,
+    object: &Object,
+) -> Option<DataType> {
+    TypeChecker::new(nodes, function_table).type_of(index, object)
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::{compute_scopes, parse, parse_node_expressions, populate_function_table};
+
+    fn check(code: &str) -> Vec<CompilerProblem> {
+        let (mut nodes, _) = parse(lex(code));
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        check_types(&nodes, &function_table)
+    }
+
+    #[test]
+    fn type_check_well_typed() {
+        let problems = check(
+            "fn add :: a int -> b int -> int {
+            let total :: int = + a b
+            let pair :: (int, str) = (total, \"x\")
+            let name :: str mut = \"hello\"
+            set name @ 0 = @ pair 1
+            return add total (@ pair 0)
+        }",
+        );
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn type_check_let_and_set() {
+        let problems = check(
+            "fn main {
+            let a :: int = \"hello\"
+            let b :: (int, bool) = (1, 2)
+            let c :: int mut = 1
+            set c = true
+        }",
+        );
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected int, found str",
+                "expected (int, bool), found (int, int)",
+                "expected int, found bool"
+            ]
+        );
+        // Reported at the value, with the declaration as the related location
+        assert_eq!((problems[0].line, problems[0].word_index), (1, 5));
+        let related = problems[2].related.as_ref().unwrap();
+        assert_eq!(related.line, 3);
+        assert_eq!(related.message, "`c` is declared as int");
+    }
+
+    #[test]
+    fn type_check_calls_and_returns() {
+        let problems = check(
+            "fn twice :: x int -> int {
+            return * x 2
+        }
+        fn main {
+            let h :: int = twice true
+            let s :: str = twice 1
+            return 1
+        }",
+        );
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected int, found bool",
+                "expected str, found int",
+                "expected void, found int"
+            ]
+        );
+        // The argument is checked against the declaration of `twice`
+        let related = problems[0].related.as_ref().unwrap();
+        assert_eq!(related.message, "`twice` declares `x` as int");
+        assert_eq!(related.line, 0);
+        assert_eq!((problems[0].line, problems[0].word_index), (4, 6));
+    }
+
+    #[test]
+    fn type_check_indexing() {
+        let problems = check(
+            "fn main :: s str -> void {
+            let a :: str = @ 5 0
+            let k :: str = \"x\"
+            let b :: str = @ s k
+            let c :: str = @ s 0..k
+        }",
+        );
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "cannot index into int",
+                "expected int, found str",
+                "expected int, found str"
+            ]
+        );
+    }
+
+    /// Infer, then type check
+    fn infer(code: &str) -> (Vec<(usize, String, DataType)>, Vec<CompilerProblem>) {
+        let (mut nodes, _) = parse(lex(code));
+        compute_scopes(&mut nodes);
+        let function_table = populate_function_table(&nodes).unwrap();
+        assert!(parse_node_expressions(&mut nodes, &function_table).is_empty());
+        let mut problems = infer_types(&mut nodes, &function_table);
+        problems.extend(check_types(&nodes, &function_table));
+        (inferred_types(&nodes), problems)
+    }
+
+    #[test]
+    fn infer_from_initialiser() {
+        let (inferred, problems) = infer(
+            "fn pair :: (int, str) {
+            return (1, \"a\")
+        }
+        fn main {
+            let a :: auto = pair
+            let (n, s) :: auto = a
+            let b = + n 1
+            let c :: str = b
+        }",
+        );
+        let inferred: Vec<String> = inferred
+            .iter()
+            .map(|(line, name, t)| format!("{line} {name} {t}"))
+            .collect();
+        assert_eq!(
+            inferred,
+            vec!["4 a (int, str)", "5 (n, s) (int, str)", "6 b int"]
+        );
+        // Inferred types are checked like declared ones
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].message, "expected str, found int");
+    }
+
+    #[test]
+    fn infer_from_later_use() {
+        let (inferred, problems) = infer(
+            "fn twice :: x int -> int {
+            return * x 2
+        }
+        fn main :: t (int, int) -> k int -> void {
+            let a :: auto = @ t k
+            let b :: auto mut = @ t k
+            set b = true
+            let c :: auto = @ t k
+            twice c
+        }",
+        );
+        let inferred: Vec<String> = inferred
+            .iter()
+            .map(|(_, name, t)| format!("{name} {t}"))
+            .collect();
+        assert_eq!(inferred, vec!["b bool", "c int"]);
+        // Nothing pins down the type of `a`
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].message, "unable to infer the type of `a`");
+        assert_eq!(problems[0].line, 4);
+    }
+}
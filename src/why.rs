@@ -0,0 +1,230 @@
+//! `iona why`: explain which calls make a function require a permission
+//!
+//! A chain starts at the function asked about and follows calls down to a function that introduces the permission (usually a standard library function). Each hop is shown with the file and line of the call.
+
+use crate::call_graph::{CallGraph, CallSite, FunctionId};
+use crate::modules::Program;
+use crate::names::closest_name;
+use crate::permissions::Permissions;
+
+/// Find a function by its name in the entry module, or by its full `module.name`
+pub fn find_function(
+    program: &Program,
+    graph: &CallGraph,
+    name: &str,
+) -> Result<FunctionId, String> {
+    let in_entry = FunctionId {
+        module: program.entry.clone(),
+        name: name.to_string(),
+    };
+    if graph.functions.contains_key(&in_entry) {
+        return Ok(in_entry);
+    }
+    if let Some(id) = graph.functions.keys().find(|id| id.to_string() == name) {
+        return Ok(id.clone());
+    }
+    // Functions in the entry module can be named on their own
+    let candidates: Vec<String> = graph
+        .functions
+        .keys()
+        .map(|id| match id.module == program.entry {
+            true => id.name.clone(),
+            false => id.to_string(),
+        })
+        .collect();
+    Err(match closest_name(name, candidates.iter()) {
+        Some(suggestion) => format!("no function named `{name}`, did you mean `{suggestion}`?"),
+        None => format!("no function named `{name}`"),
+    })
+}
+
+/// Where a call is made, like `main.iona:3`
+fn location(program: &Program, call: &CallSite) -> String {
+    let file = program
+        .modules
+        .get(&call.caller.module)
+        .map_or(call.caller.module.clone(), |m| m.display_name());
+    format!("{file}:{}", call.line + 1)
+}
+
+/// The most chains `--all` lists for one permission
+pub const MAX_CHAINS: usize = 20;
+
+/// The chains of calls that make a function require a permission: just the shortest, or all of them (up to `MAX_CHAINS`)
+///
+/// Asking about a permission also explains its scoped versions, like `ReadFile("/etc/app/config")` for `ReadFile`, each under its own heading
+pub fn explain(
+    program: &Program,
+    graph: &CallGraph,
+    function: &FunctionId,
+    permission: &Permissions,
+    all: bool,
+) -> String {
+    let required = graph.required_permissions();
+    let matching: Vec<&Permissions> = required
+        .get(function)
+        .into_iter()
+        .flat_map(|r| r.keys())
+        .filter(|p| p.narrows(permission))
+        .collect();
+    if matching.is_empty() {
+        return format!("{function} doesn't require {permission}\n");
+    }
+    let mut explanation = String::new();
+    for permission in matching.iter() {
+        if matching.len() > 1 {
+            explanation.push_str(&format!("{permission}\n"));
+        }
+        // Only calls passing the permission on (with the same scope) are part of a chain
+        let passes_on = |call: &CallSite| call.needed(&required).any(|p| p == **permission);
+        let is_introducer = |f: &FunctionId| graph.is_introducer(&required, f, permission);
+        // Look for one more chain than is shown, to tell whether any were left out
+        let mut chains: Vec<Vec<&CallSite>> = if all {
+            graph.all_chains(function, is_introducer, passes_on, MAX_CHAINS + 1)
+        } else {
+            graph
+                .chain(function, is_introducer, passes_on)
+                .into_iter()
+                .collect()
+        };
+        let truncated = chains.len() > MAX_CHAINS;
+        chains.truncate(MAX_CHAINS);
+        for (number, chain) in chains.iter().enumerate() {
+            if all {
+                let total = match truncated {
+                    true => format!("at least {}", chains.len() + 1),
+                    false => chains.len().to_string(),
+                };
+                explanation.push_str(&format!("chain {} of {total}:\n", number + 1));
+            }
+            let width = chain
+                .iter()
+                .map(|call| location(program, call).len())
+                .max()
+                .unwrap_or(0);
+            for call in chain {
+                explanation.push_str(&format!(
+                    "  {:<width$}  {} calls `{}` ({})\n",
+                    location(program, call),
+                    call.caller,
+                    call.called_as,
+                    call.callee
+                ));
+            }
+            let introducer = chain.last().map_or(function, |call| &call.callee);
+            let declared = match graph.declared_permissions(introducer).contains(permission) {
+                true => permission,
+                false => permission.unscoped(),
+            };
+            explanation.push_str(&format!("  {introducer} declares {declared}\n"));
+        }
+    }
+    explanation
+}
+
+// -------------------- Unit Tests --------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::tests::project;
+
+    #[test]
+    fn why_chains() {
+        let root = project(
+            "why",
+            &[
+                (
+                    "main.iona",
+                    "import sync from net
+import request from std.networking
+fn main :: void {
+    #Permissions :: WriteNetwork ReadNetwork
+    sync \"http://example.com\"
+    upload \"http://example.com\"
+}
+fn upload :: url str -> void {
+    #Permissions :: WriteNetwork ReadNetwork
+    request \"POST\" url \"data\"
+}",
+                ),
+                (
+                    "net.iona",
+                    "import request from std.networking
+fn sync :: url str -> void {
+    #Properties :: Export
+    #Permissions :: WriteNetwork ReadNetwork
+    request \"GET\" url \"\"
+}",
+                ),
+            ],
+        );
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let graph = CallGraph::build(&program);
+        let main = find_function(&program, &graph, "main").unwrap();
+        assert_eq!(
+            find_function(&program, &graph, "net.sync").unwrap().name,
+            "sync"
+        );
+        assert_eq!(
+            find_function(&program, &graph, "uplod"),
+            Err("no function named `uplod`, did you mean `upload`?".to_string())
+        );
+
+        let shortest = explain(&program, &graph, &main, &Permissions::WriteNetwork, false);
+        println!("{shortest}");
+        let net = root.join("net.iona").display().to_string();
+        let main_file = root.join("main.iona").display().to_string();
+        assert_eq!(
+            shortest,
+            format!(
+                "  {main_file}:5  main.main calls `sync` (net.sync)\n  {net}:5   net.sync calls `request` (std.networking.request)\n  std.networking.request declares WriteNetwork\n"
+            )
+        );
+
+        let all = explain(&program, &graph, &main, &Permissions::WriteNetwork, true);
+        println!("{all}");
+        assert!(all.starts_with("chain 1 of 2:\n"));
+        assert!(all.contains("chain 2 of 2:\n"));
+        assert!(all.contains("main.main calls `upload` (main.upload)"));
+        assert_eq!(
+            explain(&program, &graph, &main, &Permissions::ReadFile, false),
+            "main.main doesn't require ReadFile\n"
+        );
+    }
+
+    #[test]
+    fn why_all_is_bounded() {
+        // A ladder of diamonds: every level doubles the number of chains from `main` to `read_file`
+        let mut source =
+            String::from("import read_file from std.files\nfn main :: void {\n    #Permissions :: ReadFile\n    step0\n}\n");
+        for level in 0..24 {
+            let next = match level {
+                23 => "let data :: str = read_file \"done\"".to_string(),
+                _ => format!("step{}", level + 1),
+            };
+            for name in [
+                format!("step{level}"),
+                format!("left{level}"),
+                format!("right{level}"),
+            ] {
+                let body = match name.starts_with("step") && level < 23 {
+                    true => format!("left{level}\n    right{level}"),
+                    false => next.clone(),
+                };
+                source.push_str(&format!(
+                    "fn {name} :: void {{\n    #Permissions :: ReadFile\n    {body}\n}}\n"
+                ));
+            }
+        }
+        let root = project("why-bounded", &[("main.iona", &source)]);
+        let program = Program::load(&root.join("main.iona"), vec![root.clone()]).unwrap();
+        let graph = CallGraph::build(&program);
+        let main = find_function(&program, &graph, "main").unwrap();
+        let done = Permissions::from_str("ReadFile(\"done\")");
+        let all = explain(&program, &graph, &main, &done, true);
+        assert!(all.starts_with(&format!("chain 1 of at least {}:\n", MAX_CHAINS + 1)));
+        assert!(all.contains(&format!("chain {MAX_CHAINS} of at least")));
+        assert!(!all.contains(&format!("chain {} of", MAX_CHAINS + 1)));
+    }
+}